@@ -0,0 +1,18 @@
+// Draws the portal camera's image where it would be on screen,
+// so the view through the portal lines up with the world around it.
+// See src/world/portal.rs
+
+#import bevy_pbr::{
+    forward_io::VertexOutput,
+    mesh_view_bindings::view,
+    utils::coords_to_viewport_uv,
+}
+
+@group(#{MATERIAL_BIND_GROUP}) @binding(0) var portal_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(1) var portal_sampler: sampler;
+
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+    let viewport_uv = coords_to_viewport_uv(mesh.position.xy, view.viewport);
+    return textureSample(portal_texture, portal_sampler, viewport_uv);
+}
@@ -0,0 +1,262 @@
+use std::time::Duration;
+
+use bevy::{
+    app::ScheduleRunnerPlugin,
+    ecs::entity::Entities,
+    prelude::*,
+    render::{settings::WgpuSettings, RenderPlugin},
+    window::ExitCondition,
+    winit::WinitPlugin,
+};
+
+use crate::{
+    console::ConsoleLog,
+    input_context::InputOwner,
+    inventory::{ItemCatalog, ItemDefinition},
+    world::{
+        object_loading::{ObjectLoadingPlugin, ObjectSceneStatus},
+        object_pool::{ObjectPoolPlugin, ObjectSpawnSettings, ObjectTemplates},
+        objects::{ObjectsPlugin, SpawnedObject},
+    },
+};
+
+/*
+    How much do a few thousand boats cost? Run
+
+        cargo run --release -- --benchmark 5000
+
+    to find out without a window. It spawns that many of
+    an ItemCatalog item (the boat, or --benchmark-item <id>)
+    in a grid, the same way E does, waits for them to load,
+    then measures a few hundred frames and prints the frame
+    times and how many entities there are.
+
+    --no-instancing spawns every object as a full SceneRoot,
+    like before object_pool.rs, to compare the two.
+
+    There is no GPU here, so this measures the game side:
+    spawning, transform propagation, visibility and so on.
+    The model has to exist in assets/ or there's nothing
+    to measure.
+*/
+
+// How many objects are spawned each frame
+const SPAWNS_PER_FRAME: usize = 500;
+// Space between the objects in the grid
+const GRID_SPACING: f32 = 4.0;
+// Frames to wait after everything loaded, for scenes to spawn
+const SETTLE_FRAMES: u32 = 10;
+const MEASURED_FRAMES: usize = 300;
+
+#[derive(Debug, Clone)]
+pub struct BenchmarkSettings {
+    pub count: usize,
+    pub item: String,
+    pub instancing: bool,
+}
+
+impl BenchmarkSettings {
+    // None if the game wasn't started with --benchmark
+    pub fn from_args() -> Option<Result<Self, String>> {
+        let args: Vec<String> = std::env::args().collect();
+        let value_of = |flag: &str| {
+            args.iter()
+                .skip_while(|arg| *arg != flag)
+                .nth(1)
+                .cloned()
+        };
+
+        if !args.iter().any(|arg| arg == "--benchmark") {
+            return None;
+        }
+        let count = value_of("--benchmark").unwrap_or_default();
+        let Ok(count) = count.parse() else {
+            return Some(Err(format!("--benchmark expects how many objects to spawn, not {count:?}")));
+        };
+        Some(Ok(Self {
+            count,
+            item: value_of("--benchmark-item").unwrap_or_else(|| "boat".to_string()),
+            instancing: !args.iter().any(|arg| arg == "--no-instancing"),
+        }))
+    }
+}
+
+// Runs the benchmark instead of the game
+pub fn run(settings: BenchmarkSettings) -> AppExit {
+    let catalog = ItemCatalog::default();
+    let Some(item) = catalog.get(&settings.item).cloned() else {
+        eprintln!("{:?} isn't in the ItemCatalog", settings.item);
+        return AppExit::error();
+    };
+
+    let mut app = App::new();
+
+    // No window and no GPU
+    app.add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
+                primary_window: None,
+                exit_condition: ExitCondition::DontExit,
+                ..default()
+            })
+            .set(RenderPlugin {
+                render_creation: WgpuSettings {
+                    backends: None,
+                    ..default()
+                }
+                .into(),
+                ..default()
+            })
+            .disable::<WinitPlugin>(),
+    );
+    // Winit was running the frames, without it the app only updates once
+    app.add_plugins(ScheduleRunnerPlugin::run_loop(Duration::ZERO));
+    app.add_plugins((ObjectsPlugin, ObjectLoadingPlugin, ObjectPoolPlugin));
+
+    // What the rest of the game would have added
+    app.insert_resource(catalog);
+    app.init_resource::<InputOwner>();
+    app.init_resource::<ConsoleLog>();
+
+    app.insert_resource(ObjectSpawnSettings {
+        instancing: settings.instancing,
+        ..default()
+    });
+    app.insert_resource(Benchmark {
+        settings,
+        item,
+        phase: default(),
+        spawned: 0,
+        phase_frames: 0,
+        frame_times: Vec::new(),
+    });
+    app.add_systems(Update, run_benchmark);
+
+    app.run()
+}
+
+#[derive(Resource)]
+struct Benchmark {
+    settings: BenchmarkSettings,
+    item: ItemDefinition,
+    phase: BenchmarkPhase,
+    spawned: usize,
+    phase_frames: u32,
+    frame_times: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum BenchmarkPhase {
+    // One object, so the model (and its template) are loaded
+    // before the timing starts
+    #[default]
+    WarmUp,
+    Spawning,
+    // Waiting for everything to finish loading
+    Settling,
+    Measuring,
+}
+
+impl Benchmark {
+    fn next_phase(&mut self, phase: BenchmarkPhase) {
+        info!("Benchmark: {phase:?}");
+        self.phase = phase;
+        self.phase_frames = 0;
+    }
+}
+
+fn run_benchmark(
+    mut commands: Commands,
+    mut benchmark: ResMut<Benchmark>,
+    templates: Res<ObjectTemplates>,
+    time: Res<Time<Real>>,
+    entities: &Entities,
+    objects: Query<(Entity, &ObjectSceneStatus), With<SpawnedObject>>,
+    mut exit: MessageWriter<AppExit>,
+) {
+    let benchmark = benchmark.as_mut();
+    let (settings, item) = (&benchmark.settings, &benchmark.item);
+    benchmark.phase_frames += 1;
+
+    match benchmark.phase {
+        BenchmarkPhase::WarmUp => {
+            if benchmark.phase_frames == 1 {
+                commands.spawn(SpawnedObject {
+                    scene_path: item.scene_path.clone(),
+                });
+                return;
+            }
+
+            let Some((warm_up, status)) = objects.iter().next() else { return };
+            match status {
+                ObjectSceneStatus::Loading => return,
+                ObjectSceneStatus::Failed { .. } => {
+                    error!("Couldn't load {}, there's nothing to benchmark", item.scene_path);
+                    exit.write(AppExit::error());
+                    return;
+                }
+                ObjectSceneStatus::Loaded => {}
+            }
+            if settings.instancing && !templates.is_ready(&item.scene_path) {
+                return;
+            }
+
+            // It was spawned before the template was ready
+            commands.entity(warm_up).despawn();
+            benchmark.next_phase(BenchmarkPhase::Spawning);
+        }
+        BenchmarkPhase::Spawning => {
+            let columns = (settings.count as f32).sqrt().ceil().max(1.0) as usize;
+            let batch = SPAWNS_PER_FRAME.min(settings.count - benchmark.spawned);
+            for index in benchmark.spawned..benchmark.spawned + batch {
+                let position = Vec3::new((index % columns) as f32, 0.0, (index / columns) as f32) * GRID_SPACING;
+                commands.spawn((
+                    Name::new(format!("Benchmark {}", item.name)),
+                    SpawnedObject {
+                        scene_path: item.scene_path.clone(),
+                    },
+                    Transform::from_translation(position),
+                ));
+            }
+            benchmark.spawned += batch;
+
+            if benchmark.spawned == settings.count {
+                benchmark.next_phase(BenchmarkPhase::Settling);
+            }
+        }
+        BenchmarkPhase::Settling => {
+            if objects.iter().any(|(_, status)| *status == ObjectSceneStatus::Loading) {
+                benchmark.phase_frames = 0;
+            } else if benchmark.phase_frames >= SETTLE_FRAMES {
+                benchmark.next_phase(BenchmarkPhase::Measuring);
+            }
+        }
+        BenchmarkPhase::Measuring => {
+            let frame_time = time.delta_secs() * 1000.0;
+            benchmark.frame_times.push(frame_time);
+            if benchmark.frame_times.len() < MEASURED_FRAMES {
+                return;
+            }
+
+            let mut frame_times = std::mem::take(&mut benchmark.frame_times);
+            frame_times.sort_by(f32::total_cmp);
+            let average = frame_times.iter().sum::<f32>() / frame_times.len() as f32;
+            let percentile = |p: f32| frame_times[((frame_times.len() - 1) as f32 * p).round() as usize];
+
+            info!(
+                "Benchmark: {} {} objects, instancing {}",
+                objects.iter().count(),
+                settings.item,
+                if settings.instancing { "on" } else { "off" },
+            );
+            info!("Benchmark: {} entities", entities.len());
+            info!(
+                "Benchmark: frame time average {average:.2} ms, median {:.2} ms, p99 {:.2} ms, max {:.2} ms",
+                percentile(0.5),
+                percentile(0.99),
+                percentile(1.0),
+            );
+            exit.write(AppExit::Success);
+        }
+    }
+}
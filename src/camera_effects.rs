@@ -0,0 +1,303 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+use crate::{
+    keyboard_input::{SPRINT_KEY, WALK_SPEED},
+    pause_menu::PauseState,
+    player::{FpsPlayer, PlayerAim, PlayerVelocity, Teleported, update_player_layout},
+    sound::STRIDE_LENGTH,
+};
+
+/*
+    Camera effects make the player camera feel less like
+    it's glued to a pole. There are four of them:
+
+    - Head bob: the camera bobs up and down with every step,
+      more the faster we walk
+    - Landing dip: the camera dips and springs back when
+      we land on the ground after falling
+    - FOV kick: the view widens a little while sprinting
+    - Shake: any system can shake the camera with
+      commands.trigger(CameraShake::new(0.5))
+
+    The player camera gets a CameraEffects component with
+    the strength of each effect. CameraEffectsSettings turns
+    each of them on or off (in the pause menu settings) for
+    anyone who gets motion sick.
+
+    The effects are only an offset on top of the camera's
+    Transform and FOV. We take last frame's offset back off in
+    First, so movement, mouse look and respawning always see
+    the plain camera, then put the new offset on in PostUpdate.
+*/
+
+pub struct CameraEffectsPlugin;
+
+impl Plugin for CameraEffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraEffectsSettings>();
+        app.register_type::<CameraEffectsSettings>();
+        app.register_type::<CameraEffects>();
+
+        app.add_observer(add_camera_shake);
+        app.add_systems(First, remove_camera_effects);
+        app.add_systems(PostUpdate, apply_camera_effects
+            .after(update_player_layout)
+            .before(TransformSystems::Propagate)
+            .run_if(in_state(PauseState::Running)));
+    }
+}
+
+#[derive(Resource, Debug, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct CameraEffectsSettings {
+    pub head_bob: bool,
+    pub landing_dip: bool,
+    pub fov_kick: bool,
+    pub shake: bool,
+}
+
+impl Default for CameraEffectsSettings {
+    fn default() -> Self {
+        Self {
+            head_bob: true,
+            landing_dip: true,
+            fov_kick: true,
+            shake: true,
+        }
+    }
+}
+
+// Goes on the player camera, player.rs adds it
+#[derive(Component, Reflect, Debug, Clone)]
+#[reflect(Component, Default)]
+#[require(CameraEffectsState)]
+pub struct CameraEffects {
+    // How far the camera bobs up and down in meters at walking speed
+    pub bob_amplitude: f32,
+    // Meters of dip for every m/s we were falling when we landed
+    pub landing_dip_per_speed: f32,
+    // Extra field of view while sprinting in degrees
+    pub sprint_fov_kick: f32,
+    // Rotation at full trauma in degrees
+    pub max_shake_angle: f32,
+    // Movement at full trauma in meters
+    pub max_shake_offset: f32,
+    // How much trauma wears off every second
+    pub trauma_decay: f32,
+    // 0..1, shake grows with trauma squared so small hits stay subtle
+    pub trauma: f32,
+}
+
+impl Default for CameraEffects {
+    fn default() -> Self {
+        Self {
+            bob_amplitude: 0.04,
+            landing_dip_per_speed: 0.01,
+            sprint_fov_kick: 12.0,
+            max_shake_angle: 4.0,
+            max_shake_offset: 0.05,
+            trauma_decay: 1.0,
+            trauma: 0.0,
+        }
+    }
+}
+
+impl CameraEffects {
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+}
+
+// Shakes every camera with CameraEffects.
+// 0.2 is a bump, 1.0 is an explosion next to you.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct CameraShake {
+    pub trauma: f32,
+}
+
+impl CameraShake {
+    pub fn new(trauma: f32) -> Self {
+        Self { trauma }
+    }
+}
+
+// What the effects are doing right now and
+// what we added to the camera last frame
+#[derive(Component, Default)]
+pub struct CameraEffectsState {
+    bob_phase: f32,
+    bob_weight: f32,
+    dip_offset: f32,
+    dip_velocity: f32,
+    fov_kick_weight: f32,
+    shake_time: f32,
+    last_feet_height: Option<f32>,
+    last_vertical_speed: f32,
+
+    applied_offset: Vec3,
+    applied_rotation: Quat,
+    applied_fov: f32,
+}
+
+// Slower than this (m/s) and we're standing still
+const MIN_MOVING_SPEED: f32 = 0.5;
+// Falling faster than this (m/s) and then stopping is a landing
+const MIN_LANDING_SPEED: f32 = 4.0;
+const MAX_LANDING_DIP: f32 = 0.2;
+// How far from the feet (m) the ground has to be to count as a landing
+const GROUND_CHECK_DISTANCE: f32 = 0.25;
+// The dip springs back like a soft spring
+const DIP_STIFFNESS: f32 = 120.0;
+const DIP_DAMPING: f32 = 14.0;
+// How fast bob and FOV kick fade in and out
+const EFFECT_FADE_SPEED: f32 = 6.0;
+
+fn add_camera_shake(trigger: On<CameraShake>, mut cameras: Query<&mut CameraEffects>) {
+    for mut effects in &mut cameras {
+        effects.add_trauma(trigger.trauma);
+    }
+}
+
+fn remove_camera_effects(
+    mut cameras: Query<(&mut Transform, Option<&mut Projection>, &mut CameraEffectsState)>,
+) {
+    for (mut transform, projection, mut state) in &mut cameras {
+        if state.applied_offset != Vec3::ZERO {
+            transform.translation -= state.applied_offset;
+            state.applied_offset = Vec3::ZERO;
+        }
+        if state.applied_rotation != Quat::IDENTITY {
+            transform.rotation *= state.applied_rotation.inverse();
+            state.applied_rotation = Quat::IDENTITY;
+        }
+        if state.applied_fov != 0.0 {
+            if let Some(mut projection) = projection {
+                if let Projection::Perspective(perspective) = projection.as_mut() {
+                    perspective.fov -= state.applied_fov;
+                }
+            }
+            state.applied_fov = 0.0;
+        }
+    }
+}
+
+pub fn apply_camera_effects(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<CameraEffectsSettings>,
+    players: Query<(&FpsPlayer, &Transform, &PlayerVelocity, Has<Teleported>)>,
+    mut aim: PlayerAim,
+    mut cameras: Query<
+        (&mut Transform, Option<&mut Projection>, &mut CameraEffects, &mut CameraEffectsState),
+        Without<FpsPlayer>,
+    >,
+) {
+    let dt = time.delta_secs();
+    if dt <= 0.0 {
+        return;
+    }
+
+    for (player, player_transform, velocity, teleported) in &players {
+        let Some(camera_entity) = player.camera_entity() else { continue };
+        let Ok((mut transform, projection, mut effects, mut state)) = cameras.get_mut(camera_entity) else {
+            continue;
+        };
+
+        let horizontal_speed = velocity.0.with_y(0.0).length();
+        let moving = horizontal_speed > MIN_MOVING_SPEED;
+        let fade = (EFFECT_FADE_SPEED * dt).min(1.0);
+
+        // Head bob, one bounce per footstep and
+        // a sway to the side every other step
+        let mut offset = Vec3::ZERO;
+        let bob_target = if settings.head_bob && moving {
+            (horizontal_speed / WALK_SPEED).min(1.5)
+        } else {
+            0.0
+        };
+        state.bob_weight += (bob_target - state.bob_weight) * fade;
+        if moving {
+            state.bob_phase = (state.bob_phase + horizontal_speed * dt * PI / STRIDE_LENGTH) % (4.0 * PI);
+        }
+        let bob = effects.bob_amplitude * state.bob_weight;
+        offset.y -= state.bob_phase.sin().abs() * bob;
+        offset.x += (state.bob_phase * 0.5).cos() * bob * 0.5;
+
+        // Landing dip. We watch the feet instead of the
+        // velocity so stopping on the terrain counts too.
+        // There's no gravity, so we can also stop while flying:
+        // it only counts when there's ground right under the feet.
+        // A teleport isn't a fall, so we start watching again after it.
+        let feet_height = player_transform.translation.y;
+        if teleported {
+            state.last_feet_height = None;
+        }
+        let vertical_speed = state
+            .last_feet_height
+            .map_or(0.0, |last| (feet_height - last) / dt);
+        let stopped_falling = state.last_vertical_speed < -MIN_LANDING_SPEED && vertical_speed > -MIN_LANDING_SPEED * 0.5;
+        let landed = stopped_falling && {
+            let feet = player_transform.translation;
+            let ray = Ray3d::new(feet + Vec3::Y * GROUND_CHECK_DISTANCE, Dir3::NEG_Y);
+            aim.cast_ray(ray, GROUND_CHECK_DISTANCE * 2.0).is_some()
+        };
+        if landed && settings.landing_dip {
+            let dip = (-state.last_vertical_speed * effects.landing_dip_per_speed).min(MAX_LANDING_DIP);
+            // Push the spring down fast enough to dip about that far
+            state.dip_velocity -= dip * DIP_STIFFNESS.sqrt();
+        }
+        state.last_feet_height = Some(feet_height);
+        state.last_vertical_speed = vertical_speed;
+
+        let spring = -DIP_STIFFNESS * state.dip_offset - DIP_DAMPING * state.dip_velocity;
+        state.dip_velocity += spring * dt;
+        state.dip_offset = (state.dip_offset + state.dip_velocity * dt).clamp(-MAX_LANDING_DIP, MAX_LANDING_DIP);
+        if !settings.landing_dip {
+            state.dip_offset = 0.0;
+            state.dip_velocity = 0.0;
+        }
+        offset.y += state.dip_offset;
+
+        // Trauma shake. A few sine waves at odd frequencies
+        // are wobbly enough to feel random.
+        let mut rotation = Quat::IDENTITY;
+        effects.trauma = (effects.trauma - effects.trauma_decay * dt).max(0.0);
+        if settings.shake && effects.trauma > 0.0 {
+            state.shake_time += dt;
+            let shake = effects.trauma * effects.trauma;
+            let t = state.shake_time;
+            let wobble = |a: f32, b: f32| ((t * a).sin() + (t * b).sin() * 0.5) / 1.5;
+            let angle = effects.max_shake_angle.to_radians() * shake;
+
+            rotation = Quat::from_euler(
+                EulerRot::YXZ,
+                angle * wobble(23.0, 37.0),
+                angle * wobble(29.0, 41.0),
+                angle * wobble(31.0, 17.0),
+            );
+            offset += Vec3::new(wobble(19.0, 43.0), wobble(27.0, 13.0), 0.0) * effects.max_shake_offset * shake;
+        }
+
+        // Sprint FOV kick
+        let sprinting = keys.pressed(SPRINT_KEY) && moving;
+        let kick_target = if settings.fov_kick && sprinting { 1.0 } else { 0.0 };
+        state.fov_kick_weight += (kick_target - state.fov_kick_weight) * fade;
+        let fov = effects.sprint_fov_kick.to_radians() * state.fov_kick_weight;
+
+        // The camera is a child of the player root, which already
+        // turns with our yaw, so x is always to our right
+        transform.translation += offset;
+        transform.rotation *= rotation;
+        state.applied_offset = offset;
+        state.applied_rotation = rotation;
+
+        if let Some(mut projection) = projection {
+            if let Projection::Perspective(perspective) = projection.as_mut() {
+                perspective.fov += fov;
+                state.applied_fov = fov;
+            }
+        }
+    }
+}
@@ -0,0 +1,724 @@
+use std::{any::TypeId, collections::{BTreeMap, VecDeque}, str::FromStr};
+
+use bevy::{
+    ecs::{reflect::ReflectResource, system::SystemId},
+    input::{ButtonState, keyboard::KeyboardInput},
+    prelude::*,
+    reflect::{DynamicEnum, DynamicVariant, GetPath, PartialReflect, ReflectMut, ReflectRef, TypeInfo, TypeRegistry},
+};
+
+use crate::{
+    input_context::{InputClaims, editor_has_input, update_input_owner},
+    inspector::{game_resources, is_choice},
+    pause_menu::PauseState,
+    player::{FpsPlayer, PlayerVelocity, Teleported},
+    scene_io::{LoadScene, SaveScene, SceneSaveSettings},
+};
+
+/*
+    The developer console opens with the backtick key (`).
+
+    Type a command and press Enter. Up and Down go through the
+    commands you typed before, Tab completes the command name
+    (and for some commands the first argument), and the line
+    under the input shows what Tab can complete to.
+
+        help                  list every command
+        tp 0 10 0             move the player
+        spawn boat            spawn an item from the ItemCatalog
+        set sensitivity 0.004 change a setting (any reflected
+                              resource field, see below)
+        noclip                same as N, the spectator camera
+        timescale 0.5         slow motion
+        save / load           the same as the pause menu
+
+    set looks for the field in the game's reflected resources,
+    the same ones the inspector shows under "Settings". Write
+    "set MouseLookSettings.sensitivity.x 0.004" if the name is
+    ambiguous or to set one part of a vector. Give one value to
+    set every part of a vector at once.
+
+    While the console is open it owns the input (see
+    input_context.rs), so typing WASD doesn't move the player.
+
+    Any plugin can add its own commands, like it adds saveable
+    components. A command is a normal bevy system that gets the
+    words after the command name as In<ConsoleArgs> and returns
+    the text to print, or an error:
+
+        app.add_console_command("heal", "heal <amount>  heal the player", heal_command);
+
+        fn heal_command(In(args): In<ConsoleArgs>, mut health: Query<&mut Health>) -> ConsoleResult {
+            let amount: f32 = args.parse(0, "amount")?;
+            ...
+            Ok(format!("Healed {amount}"))
+        }
+
+    add_console_completions gives Tab a list of words for the
+    first argument (spawn completes item ids this way).
+*/
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Console>();
+        app.init_resource::<ConsoleLog>();
+        app.init_resource::<ConsoleCommands>();
+
+        app.add_systems(Startup, spawn_console);
+        app.add_systems(PreUpdate, claim_console_input.before(update_input_owner));
+        app.add_systems(Update, (
+            toggle_console
+                .run_if(in_state(PauseState::Running))
+                .run_if(not(editor_has_input)),
+            type_in_console,
+            update_suggestions.run_if(console_open),
+            update_console_text,
+        ).chain());
+
+        app.add_console_command("help", "help [command]  list the commands, or show how to use one", help_command);
+        app.add_console_command("clear", "clear  empty the console", clear_command);
+        app.add_console_command("tp", "tp <x> <y> <z>  move the player", teleport_command);
+        app.add_console_command("set", "set <setting> [value...]  show or change a setting", set_command);
+        app.add_console_command("timescale", "timescale [scale]  show or change how fast time runs", timescale_command);
+        app.add_console_command("save", "save [path]  save the scene (to another file)", save_command);
+        app.add_console_command("load", "load [path]  load the scene (from another file)", load_command);
+        app.add_console_completions("help", command_names);
+        app.add_console_completions("set", setting_names);
+    }
+}
+
+const TOGGLE_KEY: KeyCode = KeyCode::Backquote;
+const MAX_LOG_LINES: usize = 200;
+const VISIBLE_LOG_LINES: usize = 12;
+const MAX_SUGGESTIONS: usize = 12;
+
+// What a command prints, or why it failed
+pub type ConsoleResult = Result<String, String>;
+
+// The words typed after the command name
+#[derive(Debug, Clone, Default)]
+pub struct ConsoleArgs(pub Vec<String>);
+
+impl ConsoleArgs {
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.0.get(index).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // Parses one argument. The name is only for the error message.
+    pub fn parse<T: FromStr>(&self, index: usize, name: &str) -> Result<T, String> {
+        let text = self.get(index).ok_or_else(|| format!("missing <{name}>"))?;
+        text.parse().map_err(|_| format!("<{name}> can't be '{text}'"))
+    }
+}
+
+struct ConsoleCommand {
+    help: String,
+    system: SystemId<In<ConsoleArgs>, ConsoleResult>,
+    completions: Option<fn(&World) -> Vec<String>>,
+}
+
+// Every command by name, sorted so help and Tab list them in order
+#[derive(Resource, Default)]
+pub struct ConsoleCommands(BTreeMap<String, ConsoleCommand>);
+
+pub trait ConsoleAppExt {
+    // The help text starts with how to use it, like "tp <x> <y> <z>  move the player"
+    fn add_console_command<M>(
+        &mut self,
+        name: &str,
+        help: &str,
+        system: impl IntoSystem<In<ConsoleArgs>, ConsoleResult, M> + 'static,
+    ) -> &mut Self;
+
+    // Words Tab can complete the command's first argument with
+    fn add_console_completions(&mut self, name: &str, completions: fn(&World) -> Vec<String>) -> &mut Self;
+}
+
+impl ConsoleAppExt for App {
+    fn add_console_command<M>(
+        &mut self,
+        name: &str,
+        help: &str,
+        system: impl IntoSystem<In<ConsoleArgs>, ConsoleResult, M> + 'static,
+    ) -> &mut Self {
+        let system = self.world_mut().register_system(system);
+        self.world_mut()
+            .get_resource_or_init::<ConsoleCommands>()
+            .0
+            .insert(name.to_string(), ConsoleCommand { help: help.to_string(), system, completions: None });
+        self
+    }
+
+    fn add_console_completions(&mut self, name: &str, completions: fn(&World) -> Vec<String>) -> &mut Self {
+        match self.world_mut().get_resource_or_init::<ConsoleCommands>().0.get_mut(name) {
+            Some(command) => command.completions = Some(completions),
+            None => warn!("Console completions for {name}, which isn't a command (add the command first)"),
+        }
+        self
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Console {
+    pub open: bool,
+    input: String,
+    history: Vec<String>,
+    // Where Up and Down are in the history, None for a new line
+    history_index: Option<usize>,
+    // What Tab can complete the word being typed to
+    suggestions: Vec<String>,
+}
+
+impl Console {
+    fn step_history(&mut self, back: bool) {
+        if self.history.is_empty() {
+            return;
+        }
+        self.history_index = match (self.history_index, back) {
+            (None, true) => Some(self.history.len() - 1),
+            (None, false) => None,
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) if index + 1 < self.history.len() => Some(index + 1),
+            (Some(_), false) => None,
+        };
+        self.input = self.history_index.map_or_else(String::new, |index| self.history[index].clone());
+    }
+
+    // Completes as much of the last word as all suggestions agree on
+    fn complete(&mut self) {
+        let Some(first) = self.suggestions.first() else { return };
+        let common = self.suggestions.iter().fold(first.as_str(), |common, suggestion| {
+            let length = common
+                .char_indices()
+                .zip(suggestion.chars())
+                .take_while(|((_, a), b)| a == b)
+                .last()
+                .map_or(0, |((index, a), _)| index + a.len_utf8());
+            &common[..length]
+        });
+
+        let word_start = self.input.rfind(' ').map_or(0, |index| index + 1);
+        let mut input = self.input[..word_start].to_string();
+        input.push_str(common);
+        if self.suggestions.len() == 1 {
+            input.push(' ');
+        }
+        self.input = input;
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ConsoleLog {
+    lines: VecDeque<String>,
+}
+
+impl ConsoleLog {
+    pub fn print(&mut self, text: impl Into<String>) {
+        for line in text.into().lines() {
+            self.lines.push_back(line.to_string());
+        }
+        while self.lines.len() > MAX_LOG_LINES {
+            self.lines.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+}
+
+#[derive(Component)]
+struct ConsoleRoot;
+
+#[derive(Component)]
+enum ConsoleText {
+    Log,
+    Input,
+    Suggestions,
+}
+
+fn console_open(console: Res<Console>) -> bool {
+    console.open
+}
+
+// Typing goes to the console, even over the inspector
+fn claim_console_input(console: Res<Console>, mut claims: ResMut<InputClaims>) {
+    claims.console = console.open;
+}
+
+fn toggle_console(keys: Res<ButtonInput<KeyCode>>, mut console: ResMut<Console>) {
+    if keys.just_pressed(TOGGLE_KEY) {
+        console.open = !console.open;
+    }
+}
+
+fn type_in_console(
+    mut commands: Commands,
+    mut keyboard: MessageReader<KeyboardInput>,
+    mut console: ResMut<Console>,
+    mut log: ResMut<ConsoleLog>,
+) {
+    // Keys pressed while it was closed aren't typed when it opens
+    if !console.open {
+        keyboard.clear();
+        return;
+    }
+
+    for key in keyboard.read() {
+        // Held keys repeat as more presses
+        if key.state != ButtonState::Pressed || key.key_code == TOGGLE_KEY {
+            continue;
+        }
+        match key.key_code {
+            KeyCode::Enter | KeyCode::NumpadEnter => {
+                let line = std::mem::take(&mut console.input).trim().to_string();
+                console.history_index = None;
+                if line.is_empty() {
+                    continue;
+                }
+                if console.history.last() != Some(&line) {
+                    console.history.push(line.clone());
+                }
+                log.print(format!("> {line}"));
+                // Commands are systems, running them needs the whole world
+                commands.queue(move |world: &mut World| run_console_line(world, &line));
+            }
+            KeyCode::Backspace => {
+                console.input.pop();
+            }
+            KeyCode::Escape => console.open = false,
+            KeyCode::Tab => console.complete(),
+            KeyCode::ArrowUp => console.step_history(true),
+            KeyCode::ArrowDown => console.step_history(false),
+            _ => {
+                let Some(text) = &key.text else { continue };
+                console.input.extend(text.chars().filter(|c| !c.is_control()));
+                console.history_index = None;
+            }
+        }
+    }
+}
+
+// Runs a line like "tp 0 10 0" as if it was typed in the console,
+// and prints what the command returned
+pub fn run_console_line(world: &mut World, line: &str) {
+    let mut words = line.split_whitespace().map(str::to_string);
+    let Some(name) = words.next() else { return };
+    let args = ConsoleArgs(words.collect());
+
+    let command = world
+        .resource::<ConsoleCommands>()
+        .0
+        .get(&name)
+        .map(|command| (command.system, command.help.clone()));
+    let output = match command {
+        None => Err(format!("unknown command '{name}', try help")),
+        Some((system, help)) => match world.run_system_with(system, args) {
+            Ok(Ok(output)) => Ok(output),
+            Ok(Err(err)) => Err(format!("{err}\nusage: {help}")),
+            Err(err) => Err(format!("{name} failed to run: {err}")),
+        },
+    };
+
+    let mut log = world.resource_mut::<ConsoleLog>();
+    match output {
+        Ok(output) if output.is_empty() => {}
+        Ok(output) => log.print(output),
+        Err(err) => log.print(format!("error: {err}")),
+    }
+}
+
+// Completions can look at anything in the world (like the item
+// catalog), so this is an exclusive system. It only does the work
+// when the input changed.
+fn update_suggestions(world: &mut World, mut last_input: Local<Option<String>>) {
+    let input = world.resource::<Console>().input.clone();
+    if last_input.as_ref() == Some(&input) {
+        return;
+    }
+
+    let words: Vec<&str> = input.split_whitespace().collect();
+    // Right after a space we're starting a new word
+    let new_word = input.is_empty() || input.ends_with(' ');
+    let commands = world.resource::<ConsoleCommands>();
+    let (candidates, typed) = match (words.as_slice(), new_word) {
+        ([], _) => (command_names(world), ""),
+        ([typed], false) => (command_names(world), *typed),
+        ([name], true) | ([name, _], false) => {
+            let completions = commands.0.get(*name).and_then(|command| command.completions);
+            let typed = if new_word { "" } else { words[1] };
+            (completions.map_or_else(Vec::new, |completions| completions(world)), typed)
+        }
+        _ => (Vec::new(), ""),
+    };
+
+    let suggestions = candidates.into_iter().filter(|candidate| candidate.starts_with(typed)).collect();
+    world.resource_mut::<Console>().suggestions = suggestions;
+    *last_input = Some(input);
+}
+
+fn spawn_console(mut commands: Commands) {
+    let font = TextFont { font_size: 16.0, ..default() };
+    commands.spawn((
+        Name::new("Console"),
+        ConsoleRoot,
+        Node {
+            display: Display::None,
+            position_type: PositionType::Absolute,
+            top: px(0),
+            left: px(0),
+            right: px(0),
+            padding: UiRect::all(px(8)),
+            row_gap: px(4),
+            flex_direction: FlexDirection::Column,
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.85)),
+        // Over the inspector and the hud
+        GlobalZIndex(10),
+        children![
+            (ConsoleText::Log, Text::default(), font.clone()),
+            (ConsoleText::Input, Text::default(), font.clone(), TextColor(Color::srgb(1.0, 1.0, 0.6))),
+            (ConsoleText::Suggestions, Text::default(), font, TextColor(Color::srgb(0.6, 0.6, 0.6))),
+        ],
+    ));
+}
+
+fn update_console_text(
+    console: Res<Console>,
+    log: Res<ConsoleLog>,
+    mut roots: Query<&mut Node, With<ConsoleRoot>>,
+    mut texts: Query<(&mut Text, &ConsoleText)>,
+) {
+    if !console.is_changed() && !log.is_changed() {
+        return;
+    }
+
+    for mut node in &mut roots {
+        node.display = if console.open { Display::Flex } else { Display::None };
+    }
+    for (mut text, kind) in &mut texts {
+        text.0 = match kind {
+            ConsoleText::Log => {
+                let skip = log.lines.len().saturating_sub(VISIBLE_LOG_LINES);
+                log.lines.iter().skip(skip).cloned().collect::<Vec<_>>().join("\n")
+            }
+            ConsoleText::Input => format!("> {}_", console.input),
+            ConsoleText::Suggestions => console
+                .suggestions
+                .iter()
+                .take(MAX_SUGGESTIONS)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join("  "),
+        };
+    }
+}
+
+fn command_names(world: &World) -> Vec<String> {
+    world.resource::<ConsoleCommands>().0.keys().cloned().collect()
+}
+
+fn help_command(In(args): In<ConsoleArgs>, commands: Res<ConsoleCommands>) -> ConsoleResult {
+    if let Some(name) = args.get(0) {
+        return commands
+            .0
+            .get(name)
+            .map(|command| command.help.clone())
+            .ok_or_else(|| format!("unknown command '{name}'"));
+    }
+    Ok(commands.0.values().map(|command| command.help.as_str()).collect::<Vec<_>>().join("\n"))
+}
+
+fn clear_command(In(_): In<ConsoleArgs>, mut log: ResMut<ConsoleLog>) -> ConsoleResult {
+    log.clear();
+    Ok(String::new())
+}
+
+fn teleport_command(
+    In(args): In<ConsoleArgs>,
+    mut commands: Commands,
+    mut players: Query<(Entity, &mut Transform, Option<&mut PlayerVelocity>), With<FpsPlayer>>,
+) -> ConsoleResult {
+    let position = Vec3::new(args.parse(0, "x")?, args.parse(1, "y")?, args.parse(2, "z")?);
+    if players.is_empty() {
+        return Err("there is no player".to_string());
+    }
+    // Like respawning, we don't keep falling from where we were
+    for (entity, mut transform, velocity) in &mut players {
+        transform.translation = position;
+        if let Some(mut velocity) = velocity {
+            velocity.0 = Vec3::ZERO;
+        }
+        commands.entity(entity).insert(Teleported);
+    }
+    Ok(format!("Teleported to {position}"))
+}
+
+fn timescale_command(In(args): In<ConsoleArgs>, mut time: ResMut<Time<Virtual>>) -> ConsoleResult {
+    if args.is_empty() {
+        return Ok(format!("timescale is {}", time.relative_speed()));
+    }
+    let scale: f32 = args.parse(0, "scale")?;
+    // inf and nan parse as numbers, but bevy panics on them
+    if !scale.is_finite() || scale < 0.0 {
+        return Err("expects a number from 0 up, time can't run backwards".to_string());
+    }
+    time.set_relative_speed(scale);
+    Ok(format!("timescale is {scale}"))
+}
+
+fn save_command(In(args): In<ConsoleArgs>, mut commands: Commands, mut settings: ResMut<SceneSaveSettings>) -> ConsoleResult {
+    if let Some(path) = args.get(0) {
+        settings.path = path.into();
+    }
+    commands.trigger(SaveScene);
+    Ok(format!("Saving to {}", settings.path.display()))
+}
+
+fn load_command(In(args): In<ConsoleArgs>, mut commands: Commands, mut settings: ResMut<SceneSaveSettings>) -> ConsoleResult {
+    if let Some(path) = args.get(0) {
+        settings.path = path.into();
+    }
+    commands.trigger(LoadScene);
+    Ok(format!("Loading {}", settings.path.display()))
+}
+
+// Reflection reaches into any resource, so set gets the whole world
+fn set_command(In(args): In<ConsoleArgs>, world: &mut World) -> ConsoleResult {
+    let Some(name) = args.get(0) else {
+        return Ok(format!("settings: {}", setting_names(world).join(", ")));
+    };
+
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+    let (type_id, path) = find_setting(world, &registry, name)?;
+    let reflect = registry
+        .get_type_data::<ReflectResource>(type_id)
+        .ok_or_else(|| format!("{name} isn't in a resource"))?;
+    let mut resource = reflect.reflect_mut(world).map_err(|err| format!("{err:?}"))?;
+    let value = resource.reflect_path_mut(path.as_str()).map_err(|err| err.to_string())?;
+
+    if args.len() > 1 {
+        set_value(value, &args.0[1..])?;
+    }
+    Ok(format!("{name} = {value:?}"))
+}
+
+// Finds "sensitivity" or "MouseLookSettings.sensitivity" (and
+// deeper paths like "sensitivity.x") in the game's resources
+fn find_setting(world: &World, registry: &TypeRegistry, name: &str) -> Result<(TypeId, String), String> {
+    let resources = game_resources(world, registry);
+    let (first, rest) = name.split_once('.').unwrap_or((name, ""));
+
+    let resource = resources
+        .iter()
+        .find(|registration| registration.type_info().type_path_table().short_path() == first);
+    if let Some(registration) = resource {
+        let path = if rest.is_empty() { String::new() } else { format!(".{rest}") };
+        return Ok((registration.type_id(), path));
+    }
+
+    let matches: Vec<_> = resources
+        .iter()
+        .filter(|registration| match registration.type_info() {
+            TypeInfo::Struct(info) => info.field(first).is_some(),
+            _ => false,
+        })
+        .collect();
+    match matches.as_slice() {
+        [registration] => Ok((registration.type_id(), format!(".{name}"))),
+        [] => Err(format!("no setting called '{name}', type set to list them")),
+        _ => {
+            let names: Vec<String> = matches
+                .iter()
+                .map(|registration| format!("{}.{name}", registration.type_info().type_path_table().short_path()))
+                .collect();
+            Err(format!("'{first}' is in more than one resource, try {}", names.join(" or ")))
+        }
+    }
+}
+
+// Each resource field as "field" and "Resource.field"
+fn setting_names(world: &World) -> Vec<String> {
+    let registry = world.resource::<AppTypeRegistry>().read();
+    let mut names = Vec::new();
+    for registration in game_resources(world, &registry) {
+        let TypeInfo::Struct(info) = registration.type_info() else { continue };
+        let resource = registration.type_info().type_path_table().short_path();
+        for field in info.field_names() {
+            names.push(field.to_string());
+            names.push(format!("{resource}.{field}"));
+        }
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn set_value(value: &mut dyn PartialReflect, values: &[String]) -> Result<(), String> {
+    if is_choice(value) {
+        let [name] = values else { return Err("expects one value".to_string()) };
+        let Some(TypeInfo::Enum(info)) = value.get_represented_type_info() else { return Ok(()) };
+        let variant = info
+            .iter()
+            .find(|variant| variant.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("expects one of {}", info.variant_names().join(", ")))?;
+        value.apply(&DynamicEnum::new(variant.name(), DynamicVariant::Unit));
+        return Ok(());
+    }
+
+    let field_count = match value.reflect_ref() {
+        ReflectRef::Opaque(_) => {
+            let [text] = values else { return Err("expects one value".to_string()) };
+            return parse_opaque(value, text);
+        }
+        ReflectRef::Struct(struct_ref) => struct_ref.field_len(),
+        ReflectRef::TupleStruct(tuple_ref) => tuple_ref.field_len(),
+        ReflectRef::Tuple(tuple_ref) => tuple_ref.field_len(),
+        _ => return Err(format!("{} can't be set from the console", value.reflect_short_type_path())),
+    };
+
+    // One value sets every field, like "set sensitivity 0.003"
+    if values.len() != 1 && values.len() != field_count {
+        return Err(format!("expects 1 or {field_count} values"));
+    }
+    for index in 0..field_count {
+        let text = values.get(index).unwrap_or(&values[0]);
+        let field = match value.reflect_mut() {
+            ReflectMut::Struct(struct_mut) => struct_mut.field_at_mut(index),
+            ReflectMut::TupleStruct(tuple_mut) => tuple_mut.field_mut(index),
+            ReflectMut::Tuple(tuple_mut) => tuple_mut.field_mut(index),
+            _ => None,
+        };
+        if let Some(field) = field {
+            set_value(field, std::slice::from_ref(text))?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_opaque(value: &mut dyn PartialReflect, text: &str) -> Result<(), String> {
+    // The same code for every type that can be parsed from text.
+    // Floats also have to be finite: inf and nan parse as numbers
+    // (and "1e300" overflows to inf as an f32), but nothing
+    // reading a setting expects them.
+    macro_rules! parse {
+        ($($ty:ty $(: $valid:path)?),*) => {
+            $(if let Some(current) = value.try_downcast_mut::<$ty>() {
+                let parsed: $ty = text.parse().map_err(|_| format!("'{text}' isn't a {}", stringify!($ty)))?;
+                $(if !$valid(parsed) {
+                    return Err(format!("'{text}' isn't a finite number"));
+                })?
+                *current = parsed;
+                return Ok(());
+            })*
+        };
+    }
+    parse!(f32: f32::is_finite, f64: f64::is_finite, i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, bool, String);
+    Err(format!("{} can't be set from the console", value.reflect_short_type_path()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Resource, Reflect, Default)]
+    #[reflect(Resource)]
+    struct LookSettings {
+        sensitivity: f32,
+        invert: bool,
+    }
+
+    #[derive(Resource, Reflect, Default)]
+    #[reflect(Resource)]
+    struct MoveSettings {
+        speed: f32,
+        sensitivity: f32,
+    }
+
+    fn console(input: &str, suggestions: &[&str]) -> Console {
+        Console {
+            input: input.to_string(),
+            suggestions: suggestions.iter().map(|suggestion| suggestion.to_string()).collect(),
+            ..default()
+        }
+    }
+
+    #[test]
+    fn complete_stops_at_the_common_prefix() {
+        let mut several = console("s", &["save", "set", "spawn"]);
+        several.complete();
+        assert_eq!(several.input, "s");
+
+        let mut two = console("s", &["save", "sample"]);
+        two.complete();
+        assert_eq!(two.input, "sa");
+    }
+
+    #[test]
+    fn complete_finishes_the_only_suggestion_with_a_space() {
+        let mut command = console("sp", &["spawn"]);
+        command.complete();
+        assert_eq!(command.input, "spawn ");
+
+        let mut argument = console("set sens", &["sensitivity"]);
+        argument.complete();
+        assert_eq!(argument.input, "set sensitivity ");
+    }
+
+    fn settings_world() -> (World, TypeRegistry) {
+        let mut world = World::new();
+        world.init_resource::<LookSettings>();
+        world.init_resource::<MoveSettings>();
+        let mut registry = TypeRegistry::default();
+        registry.register::<LookSettings>();
+        registry.register::<MoveSettings>();
+        (world, registry)
+    }
+
+    #[test]
+    fn find_setting_by_field_or_resource_name() {
+        let (world, registry) = settings_world();
+        assert_eq!(
+            find_setting(&world, &registry, "invert"),
+            Ok((TypeId::of::<LookSettings>(), ".invert".to_string())),
+        );
+        assert_eq!(
+            find_setting(&world, &registry, "MoveSettings.sensitivity"),
+            Ok((TypeId::of::<MoveSettings>(), ".sensitivity".to_string())),
+        );
+        assert_eq!(
+            find_setting(&world, &registry, "MoveSettings"),
+            Ok((TypeId::of::<MoveSettings>(), String::new())),
+        );
+    }
+
+    #[test]
+    fn find_setting_rejects_ambiguous_and_unknown_names() {
+        let (world, registry) = settings_world();
+        let ambiguous = find_setting(&world, &registry, "sensitivity").unwrap_err();
+        assert!(ambiguous.contains("LookSettings.sensitivity or MoveSettings.sensitivity"));
+        assert!(find_setting(&world, &registry, "gravity").is_err());
+    }
+
+    #[test]
+    fn parse_opaque_rejects_floats_that_overflow() {
+        let mut value = 1.0f32;
+        assert!(parse_opaque(&mut value, "1e300").is_err());
+        assert!(parse_opaque(&mut value, "nan").is_err());
+        assert_eq!(value, 1.0);
+        assert!(parse_opaque(&mut value, "2.5").is_ok());
+        assert_eq!(value, 2.5);
+    }
+}
@@ -0,0 +1,162 @@
+/*
+    Like editor_setup.rs, this whole file is only compiled
+    with the space_editor feature.
+
+    Pressing play in the editor used to change the edited
+    scene for good: the player stayed where you walked it,
+    and every boat you spawned was still there afterwards.
+
+    Now entering play mode takes a snapshot of the scene
+    entities (anything Saveable, spawned by a level, or
+    placed in the editor with a PrefabMarker). Only their
+    spawn info components are copied, the same ones the pause
+    menu saves, since observers rebuild everything else.
+
+    Going back to the editor restores the snapshot:
+    - entities spawned while playing are despawned
+    - entities that were despawned or pooled (picked up
+      boats) come back
+    - everything else gets its snapshot components back
+
+    With "keep selected changes" on, selected entities keep
+    whatever happened to them while playing. Both options are
+    in the Gameplay tab.
+*/
+
+use std::collections::HashSet;
+
+use bevy::{
+    prelude::*,
+    scene::DynamicSceneBuilder,
+    ecs::{entity::EntityHashMap, entity_disabling::Disabled, query::Allow},
+};
+use space_editor::prelude::{EditorState, PrefabMarker, Selected};
+
+use crate::{
+    scene_io::{Saveable, SaveableComponents},
+    world::{level::LevelEntity, object_pool::set_hierarchy_disabled},
+};
+
+pub struct EditorPlaymodePlugin;
+
+impl Plugin for EditorPlaymodePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlaymodeSettings>();
+        app.add_systems(
+            OnTransition { exited: EditorState::Editor, entered: EditorState::Game },
+            take_playmode_snapshot,
+        );
+        app.add_systems(
+            OnTransition { exited: EditorState::Game, entered: EditorState::Editor },
+            restore_playmode_snapshot,
+        );
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct PlaymodeSettings {
+    // Put the scene back the way it was when play mode ends
+    pub restore: bool,
+    // Selected entities keep their play mode changes
+    pub keep_selected: bool,
+}
+
+impl Default for PlaymodeSettings {
+    fn default() -> Self {
+        Self {
+            restore: true,
+            keep_selected: false,
+        }
+    }
+}
+
+#[derive(Resource)]
+struct PlaymodeSnapshot {
+    scene: DynamicScene,
+    entities: HashSet<Entity>,
+}
+
+type SceneEntityFilter = Or<(With<Saveable>, With<LevelEntity>, With<PrefabMarker>)>;
+// Also objects the pool disabled while playing (see object_pool.rs)
+type PooledSceneEntityFilter = (SceneEntityFilter, Allow<Disabled>);
+
+fn take_playmode_snapshot(world: &mut World) {
+    let entities: Vec<Entity> = world
+        .query_filtered::<Entity, SceneEntityFilter>()
+        .iter(world)
+        .collect();
+
+    // The saveable components, and the markers that make them scene entities
+    let filter = world
+        .resource::<SaveableComponents>()
+        .filter()
+        .allow::<LevelEntity>()
+        .allow::<PrefabMarker>();
+    let scene = DynamicSceneBuilder::from_world(world)
+        .with_component_filter(filter)
+        .deny_all_resources()
+        .extract_entities(entities.iter().copied())
+        .build();
+
+    info!("Play mode snapshot of {} entities", entities.len());
+    world.insert_resource(PlaymodeSnapshot {
+        scene,
+        entities: entities.into_iter().collect(),
+    });
+}
+
+fn restore_playmode_snapshot(world: &mut World) {
+    let Some(PlaymodeSnapshot { mut scene, entities }) = world.remove_resource::<PlaymodeSnapshot>() else { return };
+    let settings = world.resource::<PlaymodeSettings>().clone();
+    if !settings.restore {
+        return;
+    }
+
+    let kept: HashSet<Entity> = if settings.keep_selected {
+        world.query_filtered::<Entity, With<Selected>>().iter(world).collect()
+    } else {
+        HashSet::new()
+    };
+
+    // Whatever play mode spawned goes away, pooled or not
+    let spawned: Vec<Entity> = world
+        .query_filtered::<Entity, PooledSceneEntityFilter>()
+        .iter(world)
+        .filter(|entity| !entities.contains(entity) && !kept.contains(entity))
+        .collect();
+    for entity in &spawned {
+        if let Ok(entity) = world.get_entity_mut(*entity) {
+            entity.despawn();
+        }
+    }
+
+    // Snapshot objects that were picked up into the pool are shown again.
+    // The pool skips them when spawning, they aren't disabled anymore.
+    let pooled: Vec<Entity> = world
+        .query_filtered::<Entity, (With<Disabled>, PooledSceneEntityFilter)>()
+        .iter(world)
+        .filter(|entity| entities.contains(entity) && !kept.contains(entity))
+        .collect();
+    for entity in pooled {
+        set_hierarchy_disabled(world, entity, false);
+    }
+
+    // Entities that still exist get their components written back
+    // in place, the rest are spawned again
+    scene.entities.retain(|entity| !kept.contains(&entity.entity));
+    let mut entity_map: EntityHashMap<Entity> = scene
+        .entities
+        .iter()
+        .filter(|entity| world.get_entity(entity.entity).is_ok())
+        .map(|entity| (entity.entity, entity.entity))
+        .collect();
+
+    match scene.write_to_world(world, &mut entity_map) {
+        Ok(()) => info!(
+            "Restored {} entities from the play mode snapshot, removed {}",
+            scene.entities.len(),
+            spawned.len(),
+        ),
+        Err(err) => error!("Failed to restore the play mode snapshot: {err}"),
+    }
+}
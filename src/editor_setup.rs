@@ -3,24 +3,67 @@
     we don't need config flags in this file itself.
 */
 
-use bevy::{camera::{CameraOutputMode, visibility::RenderLayers}, prelude::*, render::render_resource::BlendState};
-use bevy_egui::{EguiGlobalSettings, PrimaryEguiContext};
+use bevy::{camera::{CameraOutputMode, visibility::RenderLayers}, prelude::*, render::render_resource::BlendState, window::{CursorOptions, PrimaryWindow}};
+use bevy_egui::{EguiGlobalSettings, PrimaryEguiContext, input::EguiWantsInput};
 use space_editor::{prelude::{EditorCameraMarker, EditorGameViewWorldCameraMarker, EditorRegistryExt, EditorState, simple_editor_setup}, space_editor_ui::{all_render_layers, ext::bevy_panorbit_camera::PanOrbitCamera}};
 use transform_gizmo_bevy::GizmoCamera;
 
-use crate::{mouse_input::MouseLookSettings, player::FpsPlayer};
+use crate::{
+    input_context::{InputClaims, InputOwner, update_input_owner},
+    light_tool::PlacedLight,
+    mouse_input::{MouseLookSettings, MouseTabFocus, set_mouse_focus},
+    player::FpsPlayer,
+    respawn::SpawnPoint,
+    world::mechanisms::{Door, Switch, TriggerVolume},
+};
 
 pub struct EditorSetupPlugin;
 
 impl Plugin for EditorSetupPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, setup_editor);
+        // Gameplay keys stop while editing or typing in the editor ui
+        app.add_systems(PreUpdate, claim_editor_input.before(update_input_owner));
+        app.add_systems(OnEnter(EditorState::Editor), release_mouse_for_editor);
 
         app.register_type::<FpsPlayer>();
         app.register_type::<MouseLookSettings>();
         app.editor_registry::<FpsPlayer>();
+        // Add these to any entity and they build themselves
+        app.editor_registry::<TriggerVolume>();
+        app.editor_registry::<Door>();
+        app.editor_registry::<Switch>();
+        // So the gameplay tab's spawn points and lights can be edited
+        app.editor_registry::<SpawnPoint>();
+        app.editor_registry::<PlacedLight>();
     }
 }
+
+// Claims the input for the editor if it's editing
+// or egui has the keyboard or the mouse
+fn claim_editor_input(
+    editor_state: Res<State<EditorState>>,
+    egui_input: Res<EguiWantsInput>,
+    mut claims: ResMut<InputClaims>,
+) {
+    claims.editor = if *editor_state.get() == EditorState::Editor {
+        Some(InputOwner::Editor)
+    } else if egui_input.wants_any_keyboard_input() || egui_input.wants_any_pointer_input() {
+        Some(InputOwner::EditorUi)
+    } else {
+        None
+    };
+}
+
+// The orbit camera and the editor panels need the cursor,
+// so stopping play mode gives it back
+fn release_mouse_for_editor(
+    mut settings: ResMut<MouseLookSettings>,
+    mut window: Query<&mut CursorOptions, With<PrimaryWindow>>,
+) {
+    let Ok(mut cursor) = window.single_mut() else { return };
+    set_mouse_focus(&mut settings, &mut cursor, MouseTabFocus::None);
+}
 fn setup_editor(
     mut commands: Commands,
     mut egui_global_settings: ResMut<EguiGlobalSettings>,
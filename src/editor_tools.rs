@@ -0,0 +1,162 @@
+/*
+    Like editor_setup.rs, this whole file is only compiled
+    with the space_editor feature.
+
+    Adds a "Gameplay" tab to the editor with buttons that
+    spawn gameplay entities where the editor camera is looking
+    (the orbit camera's focus point):
+
+    - a player, a spawn point, a placed light or a trigger volume
+    - any item from the ItemCatalog, which is also a pickup
+
+    Each of these is a single spawn info component, so the
+    observers build the rest, the same as in game mode.
+
+    When an FpsPlayer is selected, the tab also shows which
+    camera, head and crosshair entities it is linked to.
+    "Rebuild children" despawns them and lets the FpsPlayer
+    observer spawn new ones, in case they got deleted or moved.
+
+    The play mode snapshot options (see editor_playmode.rs)
+    are at the bottom.
+*/
+
+use bevy::prelude::*;
+use bevy_egui::egui;
+use space_editor::{
+    prelude::{EditorCameraMarker, EditorTab, EditorTabName, EditorUiAppExt, PrefabMarker, Selected},
+    space_editor_ui::ext::bevy_panorbit_camera::PanOrbitCamera,
+};
+
+use crate::{
+    editor_playmode::PlaymodeSettings,
+    inventory::{ItemCatalog, Pickup},
+    light_tool::PlacedLight,
+    player::{FpsPlayer, rebuild_player_children},
+    respawn::SpawnPoint,
+    world::{mechanisms::TriggerVolume, objects::SpawnedObject},
+};
+
+pub struct EditorToolsPlugin;
+
+impl Plugin for EditorToolsPlugin {
+    fn build(&self, app: &mut App) {
+        app.editor_tab_by_trait(EditorTabName::Other("Gameplay".into()), GameplayTab::default());
+    }
+}
+
+#[derive(Resource, Default)]
+struct GameplayTab {
+    // The catalog item the "Spawn item" button spawns
+    item: String,
+}
+
+impl EditorTab for GameplayTab {
+    fn ui(&mut self, ui: &mut egui::Ui, commands: &mut Commands, world: &mut World) {
+        let focus = editor_focus(world);
+
+        ui.heading("Spawn at camera focus");
+        ui.horizontal_wrapped(|ui| {
+            if ui.button("Player").clicked() {
+                spawn_at(commands, focus, (Name::new("Player"), FpsPlayer::default()));
+            }
+            if ui.button("Spawn point").clicked() {
+                spawn_at(commands, focus, (Name::new("Spawn Point"), SpawnPoint::default()));
+            }
+            if ui.button("Light").clicked() {
+                // A bit above the ground so it lights something
+                spawn_at(commands, focus + Vec3::Y * 2.0, PlacedLight::default());
+            }
+            if ui.button("Trigger volume").clicked() {
+                spawn_at(commands, focus + Vec3::Y, (Name::new("Trigger Volume"), TriggerVolume::default()));
+            }
+        });
+
+        let catalog = world.resource::<ItemCatalog>();
+        if catalog.get(&self.item).is_none() {
+            self.item = catalog.iter().next().map(|(id, _)| id.to_string()).unwrap_or_default();
+        }
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("gameplay_item")
+                .selected_text(catalog.display_name(&self.item).to_string())
+                .show_ui(ui, |ui| {
+                    for (id, item) in catalog.iter() {
+                        ui.selectable_value(&mut self.item, id.to_string(), &item.name);
+                    }
+                });
+
+            if ui.button("Spawn item").clicked() {
+                if let Some(item) = catalog.get(&self.item) {
+                    spawn_at(commands, focus, (
+                        Name::new(item.name.clone()),
+                        SpawnedObject { scene_path: item.scene_path.clone() },
+                        Pickup::new(self.item.clone(), 1),
+                    ));
+                }
+            }
+        });
+
+        ui.separator();
+        player_section(ui, commands, world);
+
+        ui.separator();
+        ui.heading("Play mode");
+        let mut settings = world.resource_mut::<PlaymodeSettings>();
+        ui.checkbox(&mut settings.restore, "Restore the scene when play mode ends");
+        ui.add_enabled(
+            settings.restore,
+            egui::Checkbox::new(&mut settings.keep_selected, "Keep changes to selected entities"),
+        );
+    }
+
+    fn title(&self) -> egui::WidgetText {
+        "Gameplay".into()
+    }
+}
+
+// Where the editor's orbit camera is looking
+fn editor_focus(world: &mut World) -> Vec3 {
+    world
+        .query_filtered::<&PanOrbitCamera, With<EditorCameraMarker>>()
+        .iter(world)
+        .next()
+        .map_or(Vec3::ZERO, |camera| camera.focus)
+}
+
+fn spawn_at(commands: &mut Commands, position: Vec3, bundle: impl Bundle) {
+    // PrefabMarker makes the editor show and save it
+    commands.spawn((bundle, Transform::from_translation(position), PrefabMarker));
+}
+
+// The selected players' child links, like an inspector section
+fn player_section(ui: &mut egui::Ui, commands: &mut Commands, world: &mut World) {
+    let mut players = world.query_filtered::<(Entity, &FpsPlayer, Option<&Name>), With<Selected>>();
+    let mut any_selected = false;
+
+    for (entity, player, name) in players.iter(world) {
+        any_selected = true;
+        let title = name.map_or_else(|| format!("{entity}"), |name| name.to_string());
+        ui.heading(format!("FpsPlayer: {title}"));
+
+        let link = |child: Option<Entity>| child.map_or_else(|| "missing".to_string(), |child| format!("{child}"));
+        egui::Grid::new(("fps_player_links", entity)).show(ui, |ui| {
+            ui.label("Camera");
+            ui.label(link(player.camera_entity()));
+            ui.end_row();
+            ui.label("Head");
+            ui.label(link(player.head_entity()));
+            ui.end_row();
+            ui.label("Crosshair");
+            ui.label(link(player.crosshair_entity()));
+            ui.end_row();
+        });
+
+        if ui.button("Rebuild children").clicked() {
+            rebuild_player_children(commands, entity, player);
+        }
+    }
+
+    if !any_selected {
+        ui.label("Select an FpsPlayer to see its camera, head and crosshair");
+    }
+}
@@ -0,0 +1,165 @@
+use bevy::prelude::*;
+
+use crate::pause_menu::PauseState;
+
+/*
+    Keys mean different things depending on who is listening.
+    WASD moves the player, but it also types into a text field
+    in the editor, and E spawns a boat, but it's also a letter.
+
+    InputOwner is a resource that says who gets the input this
+    frame. It is worked out once at the start of every frame in
+    PreUpdate, before any gameplay system reads the keyboard:
+
+    - Game      gameplay systems read the input
+    - Menu      the pause menu is open
+    - Inspector the F1 inspector is open (inspector.rs)
+    - Console   the console is open and everything typed is text
+    - Editor    the editor is in edit mode (not playing)
+    - EditorUi  playing in the editor, but an egui panel has
+                the keyboard or the mouse (typing in a field,
+                dragging a slider)
+
+    Here we only know about the pause menu. editor_setup.rs,
+    inspector.rs and console.rs add a system right before
+    update_input_owner that claims the input in InputClaims,
+    so none of the gameplay code needs to know the editor or
+    the inspector exist. update_input_owner then picks one:
+    menu, then editor, console, inspector and last the game.
+
+    Gameplay systems that read input use
+    .run_if(gameplay_input), like they use
+    .run_if(in_state(PauseState::Running)).
+
+    A small label in the top right corner shows the owner.
+*/
+
+pub struct InputContextPlugin;
+
+impl Plugin for InputContextPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputOwner>();
+        app.init_resource::<InputClaims>();
+        app.add_systems(Startup, spawn_input_owner_label);
+        app.add_systems(PreUpdate, update_input_owner);
+        app.add_systems(Update, update_input_owner_label);
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputOwner {
+    #[default]
+    Game,
+    Menu,
+    Inspector,
+    Console,
+    Editor,
+    EditorUi,
+}
+
+impl InputOwner {
+    pub fn is_editor(self) -> bool {
+        matches!(self, InputOwner::Editor | InputOwner::EditorUi)
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            InputOwner::Game => "Input: Game",
+            InputOwner::Menu => "Input: Menu",
+            InputOwner::Inspector => "Input: Inspector",
+            InputOwner::Console => "Input: Console",
+            InputOwner::Editor => "Input: Editor",
+            InputOwner::EditorUi => "Input: Editor UI",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            InputOwner::Game => Color::srgb(0.6, 1.0, 0.6),
+            InputOwner::Menu | InputOwner::Inspector | InputOwner::Console => Color::srgb(1.0, 1.0, 0.6),
+            InputOwner::Editor | InputOwner::EditorUi => Color::srgb(1.0, 0.6, 0.4),
+        }
+    }
+}
+
+// Who else wants the input this frame, filled in
+// by systems that run before update_input_owner
+#[derive(Resource, Debug, Default)]
+pub struct InputClaims {
+    // Editor or EditorUi
+    pub editor: Option<InputOwner>,
+    pub console: bool,
+    pub inspector: bool,
+}
+
+// Run condition for systems that read gameplay keys and the mouse
+pub fn gameplay_input(owner: Res<InputOwner>) -> bool {
+    *owner == InputOwner::Game
+}
+
+// Run condition for the editor having the input, gameplay
+// or not (nothing else should open over the editor)
+pub fn editor_has_input(owner: Res<InputOwner>) -> bool {
+    owner.is_editor()
+}
+
+// Run condition for the keys that open menus, like Escape and F1.
+// Not while the editor has the input, or the console is typing text.
+pub fn menu_keys(owner: Res<InputOwner>) -> bool {
+    !owner.is_editor() && *owner != InputOwner::Console
+}
+
+pub fn update_input_owner(
+    pause_state: Res<State<PauseState>>,
+    claims: Res<InputClaims>,
+    mut owner: ResMut<InputOwner>,
+) {
+    let new_owner = if *pause_state.get() == PauseState::Paused {
+        InputOwner::Menu
+    } else if let Some(editor) = claims.editor {
+        editor
+    } else if claims.console {
+        InputOwner::Console
+    } else if claims.inspector {
+        InputOwner::Inspector
+    } else {
+        InputOwner::Game
+    };
+    // Only write when it changes so the label doesn't update every frame
+    owner.set_if_neq(new_owner);
+}
+
+#[derive(Component)]
+struct InputOwnerLabel;
+
+fn spawn_input_owner_label(mut commands: Commands) {
+    commands.spawn((
+        Name::new("Input Owner Label"),
+        InputOwnerLabel,
+        Node {
+            position_type: PositionType::Absolute,
+            top: px(8),
+            right: px(12),
+            ..default()
+        },
+        Text::new(InputOwner::default().label()),
+        TextFont {
+            font_size: 16.0,
+            ..default()
+        },
+        TextColor(InputOwner::default().color()),
+    ));
+}
+
+fn update_input_owner_label(
+    owner: Res<InputOwner>,
+    mut labels: Query<(&mut Text, &mut TextColor), With<InputOwnerLabel>>,
+) {
+    if !owner.is_changed() {
+        return;
+    }
+    for (mut text, mut color) in &mut labels {
+        text.0 = owner.label().to_string();
+        color.0 = owner.color();
+    }
+}
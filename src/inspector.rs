@@ -0,0 +1,821 @@
+use std::{any::TypeId, collections::HashSet};
+
+use bevy::{
+    ecs::{hierarchy::ChildSpawner, reflect::ReflectResource},
+    input::mouse::{AccumulatedMouseScroll, MouseScrollUnit},
+    prelude::*,
+    reflect::{
+        DynamicEnum, DynamicVariant, GetPath, PartialReflect, ReflectRef, TypeInfo, TypeRegistration,
+        TypeRegistry, VariantInfo, VariantType,
+    },
+    window::{CursorOptions, PrimaryWindow},
+};
+
+use crate::{
+    input_context::{InputClaims, menu_keys, update_input_owner},
+    mouse_input::{MouseLookSettings, MouseTabFocus, set_mouse_focus},
+    pause_menu::PauseState,
+};
+
+/*
+    A small inspector made with bevy_ui, so it works in normal
+    builds without the space_editor feature (and without egui).
+
+    F1 opens it. On the left is every entity with a Name, a page
+    at a time, and a "Settings" entry for the game's resources
+    (MouseLookSettings, TimeOfDay, CameraEffectsSettings...).
+    On the right are the reflected components of whatever is
+    selected. Scroll the mouse wheel to see more, click a
+    component's name to fold it.
+
+    Everything is read through reflection, so any component
+    registered with app.register_type shows up here without
+    the inspector knowing about it:
+
+    - numbers get - and + buttons (Shift for bigger steps)
+    - bools get a toggle
+    - enums like Stance or MouseTabFocus get < and >
+    - vectors and colours are structs, so each of x/y/z or
+      red/green/blue/alpha is a number (colours get a swatch)
+    - rotations are shown as yaw, pitch and roll in degrees
+    - anything else (strings, handles, lists) is only shown
+
+    The panel is only rebuilt when its rows change (another
+    entity is selected, a component is added, an Option becomes
+    Some...). The values in the rows are refreshed every frame.
+
+    While it's open the cursor is free and the inspector owns
+    the input (see input_context.rs), so clicking and scrolling
+    don't move the player or change the hotbar. The console
+    (console.rs) can change the same resources with set.
+*/
+
+pub struct InspectorPlugin;
+
+impl Plugin for InspectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Inspector>();
+        app.add_systems(PreUpdate, claim_inspector_input.before(update_input_owner));
+        app.add_systems(Update, (
+            toggle_inspector
+                .run_if(in_state(PauseState::Running))
+                .run_if(menu_keys),
+            (
+                inspector_buttons,
+                scroll_inspector,
+                rebuild_inspector,
+                refresh_inspector_values,
+            ).chain().run_if(inspector_open),
+        ).chain());
+    }
+}
+
+const TOGGLE_KEY: KeyCode = KeyCode::F1;
+const ENTITIES_PER_PAGE: usize = 20;
+// Values nested deeper than this are shown as text
+const MAX_DEPTH: usize = 5;
+const MAX_TEXT_LEN: usize = 40;
+// Degrees per click for rotations
+const ANGLE_STEP: f64 = 5.0;
+// Holding Shift makes every step this much bigger
+const FAST_STEP: f64 = 10.0;
+const SCROLL_LINE_PIXELS: f32 = 24.0;
+const INDENT: f32 = 12.0;
+const LABEL_WIDTH: f32 = 150.0;
+
+const PANEL_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.75);
+const BUTTON_COLOR: Color = Color::srgba(0.3, 0.3, 0.3, 0.9);
+const SELECTED_COLOR: Color = Color::srgba(0.2, 0.4, 0.7, 0.9);
+
+#[derive(Resource, Default)]
+pub struct Inspector {
+    pub open: bool,
+    selection: InspectorSelection,
+    page: usize,
+    // Components folded or unfolded by hand, the opposite of is_expanded's default
+    toggled: HashSet<TypeId>,
+    // The mouse focus to give back when it's closed
+    focus_before: MouseTabFocus,
+}
+
+impl Inspector {
+    // The game's own components and Transform start unfolded,
+    // bevy's (Camera, Mesh3d...) start folded
+    fn is_expanded(&self, registration: &TypeRegistration) -> bool {
+        let default = is_game_type(registration) || registration.type_id() == TypeId::of::<Transform>();
+        default != self.toggled.contains(&registration.type_id())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum InspectorSelection {
+    // The game's resources
+    #[default]
+    Settings,
+    Entity(Entity),
+}
+
+// What a row edits: a component on an entity or a resource
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Target {
+    Component(Entity, TypeId),
+    Resource(TypeId),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct FieldRef {
+    target: Target,
+    // A reflect path like ".translation.x", empty for the whole value
+    path: String,
+    kind: FieldKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FieldKind {
+    Number,
+    Bool,
+    // An enum with only unit variants, like Stance
+    Choice,
+    // One of a rotation's yaw, pitch and roll
+    Angle(usize),
+    Color,
+    ReadOnly,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Row {
+    // A component or resource, click it to fold it
+    Header { label: String, type_id: TypeId, expanded: bool },
+    // A struct inside a component, its fields are the next rows
+    Group { depth: usize, label: String },
+    Field { depth: usize, label: String, field: FieldRef },
+}
+
+// Everything the panel shows apart from the values,
+// when this changes the panel is spawned again
+#[derive(PartialEq)]
+struct PanelContents {
+    entities: Vec<(Entity, String)>,
+    page: usize,
+    pages: usize,
+    selection: InspectorSelection,
+    title: String,
+    rows: Vec<Row>,
+}
+
+#[derive(Component)]
+struct InspectorPanel;
+
+// The scrolling column with the component rows
+#[derive(Component)]
+struct InspectorScroll;
+
+#[derive(Component, Clone)]
+enum InspectorButton {
+    Select(InspectorSelection),
+    Page(isize),
+    Fold(TypeId),
+    // The field and which way to change it
+    Edit(FieldRef, f64),
+}
+
+// Text showing a field's value, refreshed every frame
+#[derive(Component)]
+struct InspectorValue(FieldRef);
+
+#[derive(Component)]
+struct InspectorSwatch(FieldRef);
+
+fn inspector_open(inspector: Res<Inspector>) -> bool {
+    inspector.open
+}
+
+pub(crate) fn is_game_type(registration: &TypeRegistration) -> bool {
+    registration.type_info().type_path().starts_with(env!("CARGO_CRATE_NAME"))
+}
+
+// The pause menu and the editor come first,
+// but while it's open it has the input over the game
+fn claim_inspector_input(inspector: Res<Inspector>, mut claims: ResMut<InputClaims>) {
+    claims.inspector = inspector.open;
+}
+
+fn toggle_inspector(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut inspector: ResMut<Inspector>,
+    mut settings: ResMut<MouseLookSettings>,
+    mut window: Query<&mut CursorOptions, With<PrimaryWindow>>,
+    panels: Query<Entity, With<InspectorPanel>>,
+) {
+    if !keys.just_pressed(TOGGLE_KEY) {
+        return;
+    }
+    let Ok(mut cursor) = window.single_mut() else { return };
+
+    inspector.open = !inspector.open;
+    if inspector.open {
+        // Like the pause menu, the cursor is needed to click things
+        inspector.focus_before = settings.focus;
+        set_mouse_focus(&mut settings, &mut cursor, MouseTabFocus::None);
+    } else {
+        let focus = inspector.focus_before;
+        set_mouse_focus(&mut settings, &mut cursor, focus);
+        for panel in &panels {
+            commands.entity(panel).despawn();
+        }
+    }
+}
+
+fn inspector_buttons(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    buttons: Query<(&Interaction, &InspectorButton), Changed<Interaction>>,
+    mut inspector: ResMut<Inspector>,
+) {
+    for (interaction, button) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match button {
+            InspectorButton::Select(selection) => inspector.selection = *selection,
+            InspectorButton::Page(direction) => inspector.page = inspector.page.saturating_add_signed(*direction),
+            InspectorButton::Fold(type_id) => {
+                if !inspector.toggled.remove(type_id) {
+                    inspector.toggled.insert(*type_id);
+                }
+            }
+            InspectorButton::Edit(field, direction) => {
+                let fast = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+                let (field, direction) = (field.clone(), *direction);
+                // Editing needs the whole world to reach any component through reflection
+                commands.queue(move |world: &mut World| edit_field(world, &field, direction, fast));
+            }
+        }
+    }
+}
+
+fn scroll_inspector(
+    mouse_scroll: Res<AccumulatedMouseScroll>,
+    mut scrolls: Query<&mut ScrollPosition, With<InspectorScroll>>,
+) {
+    let pixels = match mouse_scroll.unit {
+        MouseScrollUnit::Line => mouse_scroll.delta.y * SCROLL_LINE_PIXELS,
+        MouseScrollUnit::Pixel => mouse_scroll.delta.y,
+    };
+    if pixels == 0.0 {
+        return;
+    }
+    for mut scroll in &mut scrolls {
+        scroll.y = (scroll.y - pixels).max(0.0);
+    }
+}
+
+fn rebuild_inspector(
+    world: &mut World,
+    named: &mut QueryState<(Entity, &Name), Without<InspectorPanel>>,
+    panels: &mut QueryState<Entity, With<InspectorPanel>>,
+    scrolls: &mut QueryState<&ScrollPosition, With<InspectorScroll>>,
+    mut shown: Local<Option<PanelContents>>,
+) {
+    // The selected entity was despawned
+    let selection = world.resource::<Inspector>().selection;
+    if matches!(selection, InspectorSelection::Entity(entity) if world.get_entity(entity).is_err()) {
+        world.resource_mut::<Inspector>().selection = InspectorSelection::Settings;
+    }
+
+    let mut entities: Vec<(Entity, String)> = named
+        .iter(world)
+        .map(|(entity, name)| (entity, name.to_string()))
+        .collect();
+    entities.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
+    let pages = entities.len().div_ceil(ENTITIES_PER_PAGE).max(1);
+    let page = world.resource::<Inspector>().page.min(pages - 1);
+    world.resource_mut::<Inspector>().page = page;
+    let entities = entities
+        .into_iter()
+        .skip(page * ENTITIES_PER_PAGE)
+        .take(ENTITIES_PER_PAGE)
+        .collect();
+
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+    let inspector = world.resource::<Inspector>();
+    let selection = inspector.selection;
+    let (title, rows) = match selection {
+        InspectorSelection::Settings => ("Settings".to_string(), settings_rows(world, &registry, inspector)),
+        InspectorSelection::Entity(entity) => {
+            let title = match world.get::<Name>(entity) {
+                Some(name) => format!("{name} ({entity})"),
+                None => format!("{entity}"),
+            };
+            (title, entity_rows(world, &registry, inspector, entity))
+        }
+    };
+    let contents = PanelContents { entities, page, pages, selection, title, rows };
+
+    let old_panels: Vec<Entity> = panels.iter(world).collect();
+    if !old_panels.is_empty() && shown.as_ref() == Some(&contents) {
+        return;
+    }
+
+    // Keep the scroll position unless another entity was selected
+    let same_selection = shown.as_ref().is_some_and(|shown| shown.selection == contents.selection);
+    let scroll = scrolls.iter(world).next().filter(|_| same_selection).map_or(Vec2::ZERO, |scroll| scroll.0);
+    for panel in old_panels {
+        world.despawn(panel);
+    }
+    spawn_panel(world, &contents, scroll);
+    *shown = Some(contents);
+}
+
+fn refresh_inspector_values(
+    world: &mut World,
+    values: &mut QueryState<(Entity, &InspectorValue)>,
+    swatches: &mut QueryState<(Entity, &InspectorSwatch)>,
+) {
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+
+    // Read everything first, the texts can't be written while the world is borrowed
+    let texts: Vec<(Entity, String)> = values
+        .iter(world)
+        .map(|(entity, value)| {
+            let text = read_field(world, &registry, &value.0)
+                .map_or_else(|| "-".to_string(), |field| format_value(field, value.0.kind));
+            (entity, text)
+        })
+        .collect();
+    let colors: Vec<(Entity, Color)> = swatches
+        .iter(world)
+        .filter_map(|(entity, swatch)| {
+            let color = read_field(world, &registry, &swatch.0)?.try_downcast_ref::<Color>()?;
+            Some((entity, *color))
+        })
+        .collect();
+
+    for (entity, text) in texts {
+        if let Some(mut current) = world.get_mut::<Text>(entity) {
+            // Only write when it changed, so the text isn't laid out again every frame
+            if current.0 != text {
+                current.0 = text;
+            }
+        }
+    }
+    for (entity, color) in colors {
+        if let Some(mut background) = world.get_mut::<BackgroundColor>(entity) {
+            background.set_if_neq(BackgroundColor(color));
+        }
+    }
+}
+
+// Every reflected resource from this game that exists, by name.
+// The console's set command changes the same ones.
+pub(crate) fn game_resources<'a>(world: &World, registry: &'a TypeRegistry) -> Vec<&'a TypeRegistration> {
+    let mut resources: Vec<&TypeRegistration> = registry
+        .iter()
+        .filter(|registration| is_game_type(registration))
+        .filter(|registration| {
+            registration
+                .data::<ReflectResource>()
+                .is_some_and(|reflect| reflect.reflect(world).is_ok())
+        })
+        .collect();
+    resources.sort_by_key(|registration| registration.type_info().type_path_table().short_path());
+    resources
+}
+
+fn settings_rows(world: &World, registry: &TypeRegistry, inspector: &Inspector) -> Vec<Row> {
+    let mut rows = Vec::new();
+    for registration in game_resources(world, registry) {
+        push_type_rows(&mut rows, world, registry, inspector, registration, Target::Resource(registration.type_id()));
+    }
+    rows
+}
+
+// Every reflected component on the entity
+fn entity_rows(world: &World, registry: &TypeRegistry, inspector: &Inspector, entity: Entity) -> Vec<Row> {
+    let Ok(components) = world.inspect_entity(entity) else { return Vec::new() };
+    let mut components: Vec<&TypeRegistration> = components
+        .filter_map(|info| info.type_id())
+        .filter_map(|type_id| registry.get(type_id))
+        .filter(|registration| registration.data::<ReflectComponent>().is_some())
+        .collect();
+    components.sort_by_key(|registration| registration.type_info().type_path_table().short_path());
+
+    let mut rows = Vec::new();
+    for registration in components {
+        push_type_rows(&mut rows, world, registry, inspector, registration, Target::Component(entity, registration.type_id()));
+    }
+    rows
+}
+
+fn push_type_rows(
+    rows: &mut Vec<Row>,
+    world: &World,
+    registry: &TypeRegistry,
+    inspector: &Inspector,
+    registration: &TypeRegistration,
+    target: Target,
+) {
+    let expanded = inspector.is_expanded(registration);
+    rows.push(Row::Header {
+        label: registration.type_info().type_path_table().short_path().to_string(),
+        type_id: registration.type_id(),
+        expanded,
+    });
+    if !expanded {
+        return;
+    }
+    let Some(value) = reflect_target(world, registry, target) else { return };
+    let value = value.as_partial_reflect();
+    match value.reflect_ref() {
+        // The fields of a struct are the rows, the header already has its name
+        ReflectRef::Struct(_) => push_children(rows, value, target, "", 0),
+        _ => push_value(rows, value, target, String::new(), "value".to_string(), 0),
+    }
+}
+
+// Adds the row (or rows) for one value
+fn push_value(
+    rows: &mut Vec<Row>,
+    value: &dyn PartialReflect,
+    target: Target,
+    path: String,
+    label: String,
+    depth: usize,
+) {
+    if value.try_downcast_ref::<Quat>().is_some() {
+        rows.push(Row::Group { depth, label: format!("{label} (degrees)") });
+        for (axis, name) in ["yaw", "pitch", "roll"].into_iter().enumerate() {
+            let field = FieldRef { target, path: path.clone(), kind: FieldKind::Angle(axis) };
+            rows.push(Row::Field { depth: depth + 1, label: name.to_string(), field });
+        }
+        return;
+    }
+
+    if value.try_downcast_ref::<Color>().is_some() {
+        let field = FieldRef { target, path: path.clone(), kind: FieldKind::Color };
+        rows.push(Row::Field { depth, label, field });
+        // Color::Srgba(Srgba { red, ... }), the channels are the rows
+        if let Some(channels) = single_field(value) {
+            push_children(rows, channels, target, &format!("{path}.0"), depth + 1);
+        }
+        return;
+    }
+
+    let kind = match value.reflect_ref() {
+        ReflectRef::Opaque(_) => opaque_kind(value),
+        ReflectRef::Enum(_) if is_choice(value) => FieldKind::Choice,
+        _ if depth >= MAX_DEPTH => FieldKind::ReadOnly,
+        ReflectRef::Struct(_) | ReflectRef::TupleStruct(_) | ReflectRef::Tuple(_) | ReflectRef::Enum(_) => {
+            // Wrappers like Some(x) or SpawnKeyItem(String) are shown as what they wrap
+            if let Some(inner) = single_field(value) {
+                push_value(rows, inner, target, format!("{path}.0"), label, depth);
+                return;
+            }
+            let label = match value.reflect_ref() {
+                // An enum's variant is part of its shape, like None
+                ReflectRef::Enum(enum_ref) if enum_ref.field_len() == 0 => {
+                    let field = FieldRef { target, path, kind: FieldKind::ReadOnly };
+                    rows.push(Row::Field { depth, label, field });
+                    return;
+                }
+                ReflectRef::Enum(enum_ref) => format!("{label}: {}", enum_ref.variant_name()),
+                _ => label,
+            };
+            rows.push(Row::Group { depth, label });
+            push_children(rows, value, target, &path, depth + 1);
+            return;
+        }
+        _ => FieldKind::ReadOnly,
+    };
+    rows.push(Row::Field { depth, label, field: FieldRef { target, path, kind } });
+}
+
+fn push_children(rows: &mut Vec<Row>, value: &dyn PartialReflect, target: Target, path: &str, depth: usize) {
+    match value.reflect_ref() {
+        ReflectRef::Struct(struct_ref) => {
+            for index in 0..struct_ref.field_len() {
+                let (Some(name), Some(field)) = (struct_ref.name_at(index), struct_ref.field_at(index)) else { continue };
+                push_value(rows, field, target, format!("{path}.{name}"), name.to_string(), depth);
+            }
+        }
+        ReflectRef::TupleStruct(tuple_ref) => {
+            for (index, field) in tuple_ref.iter_fields().enumerate() {
+                push_value(rows, field, target, format!("{path}.{index}"), index.to_string(), depth);
+            }
+        }
+        ReflectRef::Tuple(tuple_ref) => {
+            for (index, field) in tuple_ref.iter_fields().enumerate() {
+                push_value(rows, field, target, format!("{path}.{index}"), index.to_string(), depth);
+            }
+        }
+        ReflectRef::Enum(enum_ref) => {
+            for index in 0..enum_ref.field_len() {
+                let Some(field) = enum_ref.field_at(index) else { continue };
+                let name = enum_ref.name_at(index).map_or_else(|| index.to_string(), str::to_string);
+                push_value(rows, field, target, format!("{path}.{name}"), name, depth);
+            }
+        }
+        _ => {}
+    }
+}
+
+// The only field of a newtype or a tuple variant like Some(x)
+fn single_field(value: &dyn PartialReflect) -> Option<&dyn PartialReflect> {
+    match value.reflect_ref() {
+        ReflectRef::TupleStruct(tuple_ref) if tuple_ref.field_len() == 1 => tuple_ref.field(0),
+        ReflectRef::Tuple(tuple_ref) if tuple_ref.field_len() == 1 => tuple_ref.field(0),
+        ReflectRef::Enum(enum_ref) if enum_ref.variant_type() == VariantType::Tuple && enum_ref.field_len() == 1 => {
+            enum_ref.field_at(0)
+        }
+        _ => None,
+    }
+}
+
+// Enums can only be cycled when no variant has fields,
+// there would be nothing to fill them with
+pub(crate) fn is_choice(value: &dyn PartialReflect) -> bool {
+    let Some(TypeInfo::Enum(info)) = value.get_represented_type_info() else { return false };
+    info.iter().all(|variant| matches!(variant, VariantInfo::Unit(_)))
+}
+
+fn opaque_kind(value: &dyn PartialReflect) -> FieldKind {
+    let Some(type_id) = value.get_represented_type_info().map(|info| info.type_id()) else {
+        return FieldKind::ReadOnly;
+    };
+    let numbers = [
+        TypeId::of::<f32>(), TypeId::of::<f64>(),
+        TypeId::of::<i8>(), TypeId::of::<i16>(), TypeId::of::<i32>(), TypeId::of::<i64>(), TypeId::of::<isize>(),
+        TypeId::of::<u8>(), TypeId::of::<u16>(), TypeId::of::<u32>(), TypeId::of::<u64>(), TypeId::of::<usize>(),
+    ];
+    if numbers.contains(&type_id) {
+        FieldKind::Number
+    } else if type_id == TypeId::of::<bool>() {
+        FieldKind::Bool
+    } else {
+        FieldKind::ReadOnly
+    }
+}
+
+fn reflect_target<'w>(world: &'w World, registry: &TypeRegistry, target: Target) -> Option<&'w dyn Reflect> {
+    match target {
+        Target::Component(entity, type_id) => {
+            registry.get_type_data::<ReflectComponent>(type_id)?.reflect(world.get_entity(entity).ok()?)
+        }
+        Target::Resource(type_id) => registry.get_type_data::<ReflectResource>(type_id)?.reflect(world).ok(),
+    }
+}
+
+fn read_field<'w>(world: &'w World, registry: &TypeRegistry, field: &FieldRef) -> Option<&'w dyn PartialReflect> {
+    reflect_target(world, registry, field.target)?.reflect_path(field.path.as_str()).ok()
+}
+
+fn format_value(value: &dyn PartialReflect, kind: FieldKind) -> String {
+    match kind {
+        FieldKind::Angle(axis) => value
+            .try_downcast_ref::<Quat>()
+            .map(|rotation| format!("{:.1}", euler_angles(rotation)[axis].to_degrees()))
+            .unwrap_or_default(),
+        FieldKind::Color => value
+            .try_downcast_ref::<Color>()
+            .map(|color| color.to_srgba().to_hex())
+            .unwrap_or_default(),
+        FieldKind::Choice => match value.reflect_ref() {
+            ReflectRef::Enum(enum_ref) => enum_ref.variant_name().to_string(),
+            _ => String::new(),
+        },
+        FieldKind::Number | FieldKind::Bool | FieldKind::ReadOnly => {
+            if let Some(number) = value.try_downcast_ref::<f32>() {
+                return format!("{number:.3}");
+            }
+            if let Some(number) = value.try_downcast_ref::<f64>() {
+                return format!("{number:.3}");
+            }
+            let text = format!("{value:?}");
+            if text.chars().count() > MAX_TEXT_LEN {
+                format!("{}...", text.chars().take(MAX_TEXT_LEN).collect::<String>())
+            } else {
+                text
+            }
+        }
+    }
+}
+
+fn euler_angles(rotation: &Quat) -> [f32; 3] {
+    let (yaw, pitch, roll) = rotation.to_euler(EulerRot::YXZ);
+    [yaw, pitch, roll]
+}
+
+fn edit_field(world: &mut World, field: &FieldRef, direction: f64, fast: bool) {
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+
+    // Going through Mut marks the component as changed,
+    // so observers and systems that watch it react like
+    // it was changed by the game
+    let root = match field.target {
+        Target::Component(entity, type_id) => {
+            let Some(reflect) = registry.get_type_data::<ReflectComponent>(type_id) else { return };
+            let Ok(entity) = world.get_entity_mut(entity) else { return };
+            reflect.reflect_mut(entity)
+        }
+        Target::Resource(type_id) => {
+            let Some(reflect) = registry.get_type_data::<ReflectResource>(type_id) else { return };
+            reflect.reflect_mut(world).ok()
+        }
+    };
+    let Some(mut root) = root else { return };
+    let Ok(value) = root.reflect_path_mut(field.path.as_str()) else { return };
+
+    let steps = if fast { direction * FAST_STEP } else { direction };
+    match field.kind {
+        FieldKind::Number => step_number(value, steps),
+        FieldKind::Bool => {
+            if let Some(flag) = value.try_downcast_mut::<bool>() {
+                *flag = !*flag;
+            }
+        }
+        FieldKind::Choice => next_variant(value, direction),
+        FieldKind::Angle(axis) => {
+            if let Some(rotation) = value.try_downcast_mut::<Quat>() {
+                let mut angles = euler_angles(rotation);
+                angles[axis] += (ANGLE_STEP * steps).to_radians() as f32;
+                *rotation = Quat::from_euler(EulerRot::YXZ, angles[0], angles[1], angles[2]);
+            }
+        }
+        FieldKind::Color | FieldKind::ReadOnly => {}
+    }
+}
+
+// Floats step by a tenth of their size, so the tiny mouse
+// sensitivity and a light's thousands of lumens both work
+fn float_step(value: f64) -> f64 {
+    if value.abs() < 1e-4 {
+        return 0.1;
+    }
+    10f64.powf(value.abs().log10().floor() - 1.0)
+}
+
+fn step_number(value: &mut dyn PartialReflect, steps: f64) {
+    // The same code for every number type
+    macro_rules! step {
+        ($($float:ty),* ; $($int:ty),*) => {
+            $(if let Some(number) = value.try_downcast_mut::<$float>() {
+                let stepped = *number + (float_step(*number as f64) * steps) as $float;
+                // Stepping past the largest number gives inf, stop there instead
+                if stepped.is_finite() {
+                    *number = stepped;
+                }
+                return;
+            })*
+            $(if let Some(number) = value.try_downcast_mut::<$int>() {
+                // `as` saturates, so unsigned numbers stop at 0
+                *number = (*number as f64 + steps).round() as $int;
+                return;
+            })*
+        };
+    }
+    step!(f32, f64; i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+}
+
+fn next_variant(value: &mut dyn PartialReflect, direction: f64) {
+    let (ReflectRef::Enum(enum_ref), Some(TypeInfo::Enum(info))) = (value.reflect_ref(), value.get_represented_type_info()) else {
+        return;
+    };
+    let count = info.variant_len();
+    let index = if direction < 0.0 {
+        (enum_ref.variant_index() + count - 1) % count
+    } else {
+        (enum_ref.variant_index() + 1) % count
+    };
+    let Some(variant) = info.variant_at(index) else { return };
+    value.apply(&DynamicEnum::new(variant.name(), DynamicVariant::Unit));
+}
+
+fn spawn_panel(world: &mut World, contents: &PanelContents, scroll: Vec2) {
+    let font = TextFont { font_size: 14.0, ..default() };
+
+    world.spawn((
+        Name::new("Inspector Panel"),
+        InspectorPanel,
+        Node {
+            position_type: PositionType::Absolute,
+            top: px(36),
+            bottom: px(12),
+            left: px(12),
+            column_gap: px(8),
+            ..default()
+        },
+    )).with_children(|panel| {
+        panel.spawn((
+            Node {
+                width: px(220),
+                padding: UiRect::all(px(8)),
+                row_gap: px(2),
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            BackgroundColor(PANEL_COLOR),
+        )).with_children(|list| {
+            list.spawn((Text::new("Inspector (F1)"), font.clone()));
+            let settings = InspectorSelection::Settings;
+            spawn_button(list, "Settings", InspectorButton::Select(settings), contents.selection == settings, &font);
+            for (entity, name) in &contents.entities {
+                let selection = InspectorSelection::Entity(*entity);
+                spawn_button(list, name, InspectorButton::Select(selection), contents.selection == selection, &font);
+            }
+            list.spawn(Node { column_gap: px(6), margin: UiRect::top(px(4)), ..default() })
+                .with_children(|row| {
+                    spawn_button(row, "<", InspectorButton::Page(-1), false, &font);
+                    row.spawn((Text::new(format!("page {}/{}", contents.page + 1, contents.pages)), font.clone()));
+                    spawn_button(row, ">", InspectorButton::Page(1), false, &font);
+                });
+        });
+
+        panel.spawn((
+            InspectorScroll,
+            Node {
+                width: px(440),
+                padding: UiRect::all(px(8)),
+                row_gap: px(2),
+                flex_direction: FlexDirection::Column,
+                overflow: Overflow::scroll_y(),
+                ..default()
+            },
+            ScrollPosition(scroll),
+            BackgroundColor(PANEL_COLOR),
+        )).with_children(|details| {
+            details.spawn((Text::new(contents.title.clone()), font.clone()));
+            for row in &contents.rows {
+                spawn_row(details, row, &font);
+            }
+        });
+    });
+}
+
+fn spawn_row(details: &mut ChildSpawner, row: &Row, font: &TextFont) {
+    match row {
+        Row::Header { label, type_id, expanded } => {
+            let arrow = if *expanded { "v" } else { ">" };
+            details.spawn((
+                Button,
+                InspectorButton::Fold(*type_id),
+                Node { margin: UiRect::top(px(4)), padding: UiRect::horizontal(px(4)), ..default() },
+                BackgroundColor(BUTTON_COLOR),
+                children![(Text::new(format!("{arrow} {label}")), font.clone())],
+            ));
+        }
+        Row::Group { depth, label } => {
+            details.spawn((
+                Text::new(label.clone()),
+                font.clone(),
+                Node { margin: UiRect::left(px(*depth as f32 * INDENT)), ..default() },
+            ));
+        }
+        Row::Field { depth, label, field } => {
+            let indent = *depth as f32 * INDENT;
+            details.spawn(Node { column_gap: px(4), align_items: AlignItems::Center, ..default() })
+                .with_children(|row| {
+                    row.spawn((
+                        Text::new(label.clone()),
+                        font.clone(),
+                        Node { width: px(LABEL_WIDTH - indent), margin: UiRect::left(px(indent)), ..default() },
+                    ));
+                    row.spawn((
+                        Text::default(),
+                        font.clone(),
+                        InspectorValue(field.clone()),
+                        Node { width: px(120), ..default() },
+                    ));
+                    if field.kind == FieldKind::Color {
+                        row.spawn((
+                            InspectorSwatch(field.clone()),
+                            Node { width: px(24), height: px(12), ..default() },
+                            BackgroundColor(Color::NONE),
+                        ));
+                    }
+                    let buttons: &[(&str, f64)] = match field.kind {
+                        FieldKind::Number | FieldKind::Angle(_) => &[("-", -1.0), ("+", 1.0)],
+                        FieldKind::Bool => &[("toggle", 1.0)],
+                        FieldKind::Choice => &[("<", -1.0), (">", 1.0)],
+                        FieldKind::Color | FieldKind::ReadOnly => &[],
+                    };
+                    for (text, direction) in buttons {
+                        spawn_button(row, text, InspectorButton::Edit(field.clone(), *direction), false, font);
+                    }
+                });
+        }
+    }
+}
+
+fn spawn_button(parent: &mut ChildSpawner, text: &str, button: InspectorButton, selected: bool, font: &TextFont) {
+    parent.spawn((
+        Button,
+        button,
+        Node { padding: UiRect::horizontal(px(6)), ..default() },
+        BackgroundColor(if selected { SELECTED_COLOR } else { BUTTON_COLOR }),
+        children![(Text::new(text), font.clone())],
+    ));
+}
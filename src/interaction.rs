@@ -0,0 +1,214 @@
+use bevy::prelude::*;
+
+use crate::{
+    input_context::gameplay_input,
+    pause_menu::PauseState,
+    player::{FpsPlayer, PlayerAim},
+    scene_io::SaveableAppExt,
+    spectator::CameraMode,
+};
+
+/*
+    Anything with an Interactable component can be used by
+    looking at it and pressing F (or West on a gamepad).
+
+    Every frame we cast a ray through the crosshair. If it
+    hits an Interactable (or a child of one, GLTF models are
+    made of many child meshes) within reach, it becomes the
+    focused interactable and the HUD shows "Press F to open".
+
+    Pressing F triggers an Interact event on that entity.
+    Doors, switches and pickups listen for it, either with
+    a global observer:
+
+        app.add_observer(|trigger: On<Interact>| { ... });
+
+    or an observer on just one entity:
+
+        commands.spawn(Interactable::new("open")).observe(open_door);
+*/
+
+pub struct InteractionPlugin;
+
+impl Plugin for InteractionPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InteractionSettings::default());
+        app.init_resource::<FocusedInteractable>();
+        app.register_type::<InteractionSettings>();
+        app.register_saveable::<Interactable>();
+
+        app.add_observer(log_interaction);
+        app.add_systems(Startup, spawn_interaction_prompt);
+        app.add_systems(Update, (
+            focus_interactable,
+            interact_with_focused,
+        ).chain()
+            .run_if(in_state(PauseState::Running))
+            .run_if(in_state(CameraMode::Player))
+            .run_if(gameplay_input));
+        app.add_systems(Update, update_interaction_prompt);
+        // Nothing is focused while the player isn't in control
+        app.add_systems(OnEnter(PauseState::Paused), clear_focus);
+        app.add_systems(OnEnter(CameraMode::Spectator), clear_focus);
+    }
+}
+
+#[derive(Resource, Debug, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct InteractionSettings {
+    pub key: KeyCode,
+    pub gamepad_button: GamepadButton,
+    // How far from the eyes we can reach in meters
+    pub reach: f32,
+}
+
+impl Default for InteractionSettings {
+    fn default() -> Self {
+        Self {
+            key: KeyCode::KeyF,
+            gamepad_button: GamepadButton::West,
+            reach: 3.0,
+        }
+    }
+}
+
+#[derive(Component, Reflect, Debug, Clone)]
+#[reflect(Component, Default)]
+pub struct Interactable {
+    // Shown in the prompt, "Press F to {verb}"
+    pub verb: String,
+    // Disabled interactables can't be focused
+    pub enabled: bool,
+}
+
+impl Default for Interactable {
+    fn default() -> Self {
+        Self::new("use")
+    }
+}
+
+impl Interactable {
+    pub fn new(verb: impl Into<String>) -> Self {
+        Self {
+            verb: verb.into(),
+            enabled: true,
+        }
+    }
+}
+
+// The interactable under the crosshair, if any
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FocusedInteractable(pub Option<Entity>);
+
+// Triggered on the interactable the player used
+#[derive(EntityEvent, Debug, Clone, Copy)]
+pub struct Interact {
+    pub entity: Entity,
+    // The FpsPlayer that pressed the button
+    pub interactor: Entity,
+}
+
+#[derive(Component)]
+struct InteractionPrompt;
+
+fn spawn_interaction_prompt(mut commands: Commands) {
+    // Centered a bit below the crosshair
+    commands.spawn((
+        Name::new("Interaction Prompt"),
+        Node {
+            position_type: PositionType::Absolute,
+            width: percent(100),
+            top: percent(55),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        Visibility::Hidden,
+        children![(
+            InteractionPrompt,
+            Text::default(),
+            TextFont { font_size: 24.0, ..default() },
+            TextShadow::default(),
+        )],
+    ));
+}
+
+fn focus_interactable(
+    settings: Res<InteractionSettings>,
+    mut focused: ResMut<FocusedInteractable>,
+    mut aim: PlayerAim,
+    parents: Query<&ChildOf>,
+    interactables: Query<&Interactable>,
+) {
+    let target = aim.cast(settings.reach).and_then(|(hit_entity, _)| {
+        // The mesh we hit might be deep inside the interactable's model
+        std::iter::once(hit_entity)
+            .chain(parents.iter_ancestors(hit_entity))
+            .find(|entity| interactables.get(*entity).is_ok_and(|interactable| interactable.enabled))
+    });
+
+    // Only write when it changes so the prompt isn't rebuilt every frame
+    if focused.0 != target {
+        focused.0 = target;
+    }
+}
+
+fn interact_with_focused(
+    mut commands: Commands,
+    settings: Res<InteractionSettings>,
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    focused: Res<FocusedInteractable>,
+    players: Query<Entity, With<FpsPlayer>>,
+) {
+    let pressed = keys.just_pressed(settings.key)
+        || gamepads.iter().any(|gamepad| gamepad.just_pressed(settings.gamepad_button));
+    if !pressed {
+        return;
+    }
+
+    let (Some(target), Some(player)) = (focused.0, players.iter().next()) else { return };
+    commands.trigger(Interact { entity: target, interactor: player });
+}
+
+fn log_interaction(trigger: On<Interact>, names: Query<NameOrEntity>) {
+    let target = names.get(trigger.entity).map(|name| name.to_string()).unwrap_or_default();
+    debug!("{} interacted with {target}", trigger.interactor);
+}
+
+fn clear_focus(mut focused: ResMut<FocusedInteractable>) {
+    focused.0 = None;
+}
+
+fn update_interaction_prompt(
+    settings: Res<InteractionSettings>,
+    focused: Res<FocusedInteractable>,
+    interactables: Query<Ref<Interactable>>,
+    mut prompts: Query<(&mut Text, &ChildOf), With<InteractionPrompt>>,
+    mut visibilities: Query<&mut Visibility>,
+) {
+    let interactable = focused.0.and_then(|entity| interactables.get(entity).ok());
+    // The verb can change while we look at it, like "open" to "close"
+    let verb_changed = interactable.as_ref().is_some_and(|interactable| interactable.is_changed());
+    if !focused.is_changed() && !verb_changed {
+        return;
+    }
+
+    for (mut text, child_of) in &mut prompts {
+        let visibility = match &interactable {
+            Some(interactable) => {
+                text.0 = format!("Press {} to {}", key_name(settings.key), interactable.verb);
+                Visibility::Inherited
+            }
+            None => Visibility::Hidden,
+        };
+        if let Ok(mut root_visibility) = visibilities.get_mut(child_of.parent()) {
+            *root_visibility = visibility;
+        }
+    }
+}
+
+// "KeyF" reads better as "F"
+fn key_name(key: KeyCode) -> String {
+    let name = format!("{key:?}");
+    name.strip_prefix("Key").unwrap_or(&name).to_string()
+}
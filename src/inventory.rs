@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+
+use bevy::{
+    input::mouse::{AccumulatedMouseScroll, MouseScrollUnit},
+    prelude::*,
+};
+
+use crate::{
+    input_context::gameplay_input,
+    interaction::{Interact, Interactable},
+    pause_menu::PauseState,
+    player::FpsPlayer,
+    scene_io::{Saveable, SaveableAppExt},
+    spectator::CameraMode,
+    world::{
+        object_pool::release_object,
+        objects::{ObjectSpawner, BOAT_SCENE},
+    },
+};
+
+/*
+    The player carries an Inventory, a row of slots that
+    each hold a stack of one kind of item. All the slots are
+    shown in the hotbar at the bottom of the screen.
+
+    1-9     select a hotbar slot
+    Scroll  select the next/previous slot
+    F       pick up the Pickup under the crosshair
+    G       drop one of the selected item in front of you
+
+    Items are just ids like "boat". The ItemCatalog knows what
+    each id looks like in the world and how many fit in a slot.
+    Register your own items with:
+
+        app.world_mut().resource_mut::<ItemCatalog>()
+            .register("crate", ItemDefinition::new("Crate", "models/crate.gltf#Scene0", 16));
+
+    Anything in the world with a Pickup component can be picked up.
+    Dropping goes through the same ObjectSpawner as pressing E,
+    and the dropped object gets a Pickup so you can take it back.
+
+    Inventory is a reflected component on the player, so it's
+    saved and loaded with the player like everything else.
+*/
+
+pub struct InventoryPlugin;
+
+impl Plugin for InventoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ItemCatalog>();
+        app.register_type::<ItemStack>();
+        app.register_saveable::<Inventory>();
+        app.register_saveable::<Pickup>();
+
+        app.add_observer(setup_pickup);
+        app.add_observer(pick_up_item);
+        app.add_systems(Startup, spawn_hotbar);
+        app.add_systems(Update, (
+            select_hotbar_slot,
+            drop_selected_item,
+        ).chain()
+            .run_if(in_state(PauseState::Running))
+            .run_if(in_state(CameraMode::Player))
+            .run_if(gameplay_input));
+        app.add_systems(Update, update_hotbar);
+    }
+}
+
+pub const HOTBAR_SIZE: usize = 9;
+const DROP_KEY: KeyCode = KeyCode::KeyG;
+// Touchpads scroll in pixels, about this many make one notch
+const PIXELS_PER_SCROLL_LINE: f32 = 20.0;
+
+const SLOT_KEYS: [KeyCode; HOTBAR_SIZE] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+#[derive(Clone, Debug)]
+pub struct ItemDefinition {
+    // Shown in the hotbar and the pickup prompt
+    pub name: String,
+    // What gets spawned when the item is dropped
+    pub scene_path: String,
+    // How many fit in one slot
+    pub max_stack: u32,
+}
+
+impl ItemDefinition {
+    pub fn new(name: impl Into<String>, scene_path: impl Into<String>, max_stack: u32) -> Self {
+        Self {
+            name: name.into(),
+            scene_path: scene_path.into(),
+            max_stack: max_stack.max(1),
+        }
+    }
+}
+
+#[derive(Resource, Debug)]
+pub struct ItemCatalog {
+    items: HashMap<String, ItemDefinition>,
+}
+
+impl Default for ItemCatalog {
+    fn default() -> Self {
+        let mut catalog = Self { items: HashMap::new() };
+        catalog.register("boat", ItemDefinition::new("Boat", BOAT_SCENE, 8));
+        catalog
+    }
+}
+
+impl ItemCatalog {
+    pub fn register(&mut self, id: impl Into<String>, definition: ItemDefinition) {
+        self.items.insert(id.into(), definition);
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ItemDefinition> {
+        self.items.get(id)
+    }
+
+    // Item ids and definitions, sorted by id
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ItemDefinition)> {
+        let mut items: Vec<_> = self.items.iter().map(|(id, item)| (id.as_str(), item)).collect();
+        items.sort_by_key(|(id, _)| *id);
+        items.into_iter()
+    }
+
+    // Unknown items still work, they just can't be dropped
+    pub fn max_stack(&self, id: &str) -> u32 {
+        self.get(id).map_or(1, |item| item.max_stack)
+    }
+
+    pub fn display_name<'a>(&'a self, id: &'a str) -> &'a str {
+        self.get(id).map_or(id, |item| &item.name)
+    }
+}
+
+#[derive(Reflect, Clone, Debug, Default, PartialEq, Eq)]
+#[reflect(Default)]
+pub struct ItemStack {
+    // An id from the ItemCatalog
+    pub item: String,
+    pub count: u32,
+}
+
+#[derive(Component, Reflect, Clone, Debug)]
+#[reflect(Component, Default)]
+pub struct Inventory {
+    pub slots: Vec<Option<ItemStack>>,
+    // Index of the selected hotbar slot
+    pub selected: usize,
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self {
+            slots: vec![None; HOTBAR_SIZE],
+            selected: 0,
+        }
+    }
+}
+
+impl Inventory {
+    // Tops up existing stacks first, then fills empty slots.
+    // Returns how many didn't fit.
+    pub fn add(&mut self, item: &str, mut count: u32, max_stack: u32) -> u32 {
+        for stack in self.slots.iter_mut().flatten() {
+            if count == 0 {
+                break;
+            }
+            if stack.item == item && stack.count < max_stack {
+                let moved = count.min(max_stack - stack.count);
+                stack.count += moved;
+                count -= moved;
+            }
+        }
+
+        for slot in self.slots.iter_mut().filter(|slot| slot.is_none()) {
+            if count == 0 {
+                break;
+            }
+            let moved = count.min(max_stack);
+            *slot = Some(ItemStack { item: item.to_string(), count: moved });
+            count -= moved;
+        }
+
+        count
+    }
+
+    pub fn selected_stack(&self) -> Option<&ItemStack> {
+        self.slots.get(self.selected).and_then(Option::as_ref)
+    }
+
+    // Takes up to count items out of the selected slot
+    pub fn take_selected(&mut self, count: u32) -> Option<ItemStack> {
+        let slot = self.slots.get_mut(self.selected)?;
+        let stack = slot.as_mut()?;
+        let taken = count.min(stack.count);
+        stack.count -= taken;
+        let item = stack.item.clone();
+        if stack.count == 0 {
+            *slot = None;
+        }
+        (taken > 0).then_some(ItemStack { item, count: taken })
+    }
+}
+
+// Put this on anything in the world that can be picked up
+#[derive(Component, Reflect, Clone, Debug)]
+#[reflect(Component, Default)]
+pub struct Pickup {
+    pub item: String,
+    pub count: u32,
+}
+
+impl Default for Pickup {
+    fn default() -> Self {
+        Self::new("boat", 1)
+    }
+}
+
+impl Pickup {
+    pub fn new(item: impl Into<String>, count: u32) -> Self {
+        Self {
+            item: item.into(),
+            count,
+        }
+    }
+}
+
+#[derive(Component)]
+struct HotbarSlot(usize);
+
+// Pickups are used like any other interactable
+fn setup_pickup(
+    trigger: On<Add, Pickup>,
+    mut commands: Commands,
+    catalog: Res<ItemCatalog>,
+    pickups: Query<&Pickup>,
+) {
+    let Ok(pickup) = pickups.get(trigger.entity) else { return };
+    commands.entity(trigger.entity).insert_if_new((
+        Interactable::new(format!("pick up {}", catalog.display_name(&pickup.item))),
+        Saveable,
+    ));
+}
+
+fn pick_up_item(
+    trigger: On<Interact>,
+    mut commands: Commands,
+    catalog: Res<ItemCatalog>,
+    mut pickups: Query<&mut Pickup>,
+    mut inventories: Query<&mut Inventory>,
+) {
+    let Ok(mut pickup) = pickups.get_mut(trigger.entity) else { return };
+    let Ok(mut inventory) = inventories.get_mut(trigger.interactor) else { return };
+
+    let left_over = inventory.add(&pickup.item, pickup.count, catalog.max_stack(&pickup.item));
+    if left_over == 0 {
+        // Goes back to the object pool if it was spawned
+        release_object(&mut commands, trigger.entity);
+    } else if left_over == pickup.count {
+        info!("Inventory is full");
+    } else {
+        // Whatever didn't fit stays in the world
+        pickup.count = left_over;
+    }
+}
+
+fn select_hotbar_slot(
+    keys: Res<ButtonInput<KeyCode>>,
+    scroll: Res<AccumulatedMouseScroll>,
+    mut inventories: Query<&mut Inventory, With<FpsPlayer>>,
+    mut scrolled: Local<f32>,
+) {
+    // A touchpad sends a few pixels every frame, so we
+    // add them up and only move for every whole notch
+    *scrolled += match scroll.unit {
+        MouseScrollUnit::Line => scroll.delta.y,
+        MouseScrollUnit::Pixel => scroll.delta.y / PIXELS_PER_SCROLL_LINE,
+    };
+    let notches = scrolled.trunc();
+    *scrolled -= notches;
+
+    for mut inventory in &mut inventories {
+        let slot_count = inventory.slots.len().min(HOTBAR_SIZE);
+        if slot_count == 0 {
+            continue;
+        }
+
+        let mut selected = inventory.selected;
+        if let Some(index) = SLOT_KEYS.iter().position(|key| keys.just_pressed(*key)) {
+            selected = index;
+        }
+        // Scrolling down moves right, like most games
+        let steps = -(notches as i64);
+        selected = (selected as i64 + steps).rem_euclid(slot_count as i64) as usize;
+
+        selected = selected.min(slot_count - 1);
+        // Only write when it changes so the hotbar isn't rebuilt every frame
+        if inventory.selected != selected {
+            inventory.selected = selected;
+        }
+    }
+}
+
+fn drop_selected_item(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    catalog: Res<ItemCatalog>,
+    mut spawner: ObjectSpawner,
+    mut inventories: Query<&mut Inventory, With<FpsPlayer>>,
+) {
+    if !keys.just_pressed(DROP_KEY) {
+        return;
+    }
+
+    for mut inventory in &mut inventories {
+        let Some(stack) = inventory.selected_stack() else { continue };
+        let Some(definition) = catalog.get(&stack.item) else {
+            info!("{} has no model, so it can't be dropped", stack.item);
+            continue;
+        };
+
+        // Only take it out of the inventory if it actually spawned
+        let Some(object) = spawner.spawn_in_front(&definition.name, &definition.scene_path) else { continue };
+        if let Some(dropped) = inventory.take_selected(1) {
+            commands.entity(object).insert(Pickup::new(dropped.item, dropped.count));
+        }
+    }
+}
+
+fn spawn_hotbar(mut commands: Commands) {
+    commands.spawn((
+        Name::new("Hotbar"),
+        Node {
+            position_type: PositionType::Absolute,
+            width: percent(100),
+            bottom: px(12),
+            justify_content: JustifyContent::Center,
+            column_gap: px(4),
+            ..default()
+        },
+    )).with_children(|hotbar| {
+        for index in 0..HOTBAR_SIZE {
+            hotbar.spawn((
+                HotbarSlot(index),
+                Node {
+                    width: px(64),
+                    height: px(64),
+                    padding: UiRect::all(px(4)),
+                    border: UiRect::all(px(2)),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::SpaceBetween,
+                    ..default()
+                },
+                BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+                BorderColor::all(Color::srgba(0.5, 0.5, 0.5, 0.8)),
+                children![
+                    (Text::new((index + 1).to_string()), TextFont { font_size: 12.0, ..default() }),
+                    (Text::default(), TextFont { font_size: 14.0, ..default() }),
+                ],
+            ));
+        }
+    });
+}
+
+// Rebuilds the slot labels when the player's inventory changes
+fn update_hotbar(
+    catalog: Res<ItemCatalog>,
+    inventories: Query<Ref<Inventory>, With<FpsPlayer>>,
+    mut slots: Query<(&HotbarSlot, &Children, &mut BorderColor)>,
+    mut texts: Query<&mut Text>,
+) {
+    let Some(inventory) = inventories.iter().next() else { return };
+    if !inventory.is_changed() {
+        return;
+    }
+
+    for (slot, children, mut border) in &mut slots {
+        *border = if slot.0 == inventory.selected {
+            BorderColor::all(Color::WHITE)
+        } else {
+            BorderColor::all(Color::srgba(0.5, 0.5, 0.5, 0.8))
+        };
+
+        let label = match inventory.slots.get(slot.0).and_then(Option::as_ref) {
+            Some(stack) => format!("{}\nx{}", catalog.display_name(&stack.item), stack.count),
+            None => String::new(),
+        };
+        // The second child is the item label, the first is the slot number
+        if let Some(mut text) = children.get(1).and_then(|child| texts.get_mut(*child).ok()) {
+            text.0 = label;
+        }
+    }
+}
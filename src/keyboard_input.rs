@@ -1,22 +1,36 @@
 use bevy::{ecs::query::QuerySingleError, prelude::*};
 
-use crate::player::FpsPlayer;
+use crate::{input_context::gameplay_input, pause_menu::PauseState, player::{FpsPlayer, PlayerVelocity}, spectator::CameraMode};
 
 pub struct KeyboardInputPlugin;
 
 impl Plugin for KeyboardInputPlugin {
     fn build(&self, app: &mut App) {
-       app.add_systems(Update, update_keyboard_movement);
+       app.add_systems(Update, update_keyboard_movement
+           .run_if(in_state(PauseState::Running))
+           .run_if(in_state(CameraMode::Player))
+           .run_if(gameplay_input));
    }
 }
 
-fn update_keyboard_movement(
+// Held to move faster. Camera effects widen the FOV while it's held.
+pub const SPRINT_KEY: KeyCode = KeyCode::ControlLeft;
+const FLY_DOWN_KEY: KeyCode = KeyCode::KeyX;
+
+// In m/s, so moving feels the same at any framerate.
+// Camera effects and footsteps are tuned to these.
+pub const WALK_SPEED: f32 = 12.0;
+pub const SPRINT_SPEED: f32 = 60.0;
+
+// Sound plays footsteps from the velocity written here
+pub fn update_keyboard_movement(
    keyboard_input: Res<ButtonInput<KeyCode>>,
-   mut player_query: Query<&mut Transform, With<FpsPlayer>>,
+   time: Res<Time>,
+   mut player_query: Query<(&mut Transform, &mut PlayerVelocity, &FpsPlayer)>,
 
 ) {
-   let mut player_transform = match player_query.single_mut() {
-      Ok(transform) => transform,
+   let (mut player_transform, mut velocity, player) = match player_query.single_mut() {
+      Ok(player) => player,
       Err(QuerySingleError::NoEntities(_)) => {
          error!("No FpsPlayer found (0 players)");
          return;
@@ -49,28 +63,39 @@ fn update_keyboard_movement(
       movement.y += 1.0;
    }
 
-   if keyboard_input.pressed(KeyCode::ShiftLeft) {
+   // Shift crouches now (see stance.rs), so X flies down
+   if keyboard_input.pressed(FLY_DOWN_KEY) {
       movement.y -= 1.0;
    }
    
-   let mut speed = 0.22;
-   
    // speed control
-   if keyboard_input.pressed(KeyCode::ControlLeft) {
-      speed = 1.0;
+   let mut speed = if keyboard_input.pressed(SPRINT_KEY) {
+      SPRINT_SPEED
    } else {
-      speed = 0.2;
-   }
+      WALK_SPEED
+   };
+
+   // Crouching and lying down are slower
+   speed *= player.stance.speed_multiplier();
+
+   velocity.0 = Vec3::ZERO;
 
    // Can't normalize a zero vector
    if movement != Vec3::ZERO {
-      movement = movement.normalize() * speed;
+      movement = movement.normalize() * speed * time.delta_secs();
+      // The player root only turns around y (the camera does the pitch)
+      // so forward and right stay flat on the x-z plane.
       let forward = player_transform.forward();
       let right = player_transform.right();
       let up = player_transform.up();
 
       let delta_translation = (forward * movement.z) + (right * movement.x) + (up * movement.y);
       player_transform.translation += delta_translation;
+
+      // Other systems (like respawning) want to know how fast we're going
+      if time.delta_secs() > 0.0 {
+         velocity.0 = delta_translation / time.delta_secs();
+      }
    }
 
 }
@@ -0,0 +1,375 @@
+use bevy::{color::palettes::css::YELLOW, prelude::*};
+
+use crate::{
+    input_context::gameplay_input,
+    pause_menu::PauseState,
+    player::PlayerAim,
+    scene_io::{Saveable, SaveableAppExt},
+    spectator::CameraMode,
+    world::light::WantsShadows,
+};
+
+/*
+    The light tool lets you place and edit lights while playing.
+
+    L            toggle the tool (shows the light panel)
+    Left click   place a light where the crosshair hits
+    Right click  select the placed light closest to the crosshair hit
+    Tab          change the kind of light to place
+    Delete       remove the selected light
+
+    The panel edits the selected light. Press Q to free the
+    cursor so you can click the panel's buttons.
+
+    Like FpsPlayer, a placed light only stores its settings
+    in PlacedLight. An observer adds the real PointLight or
+    SpotLight, so placed lights are saved with the scene.
+
+    Bevy doesn't have rectangle area lights. An "area" light
+    here is a point light with a radius, which gives the soft
+    shadows and highlights of a larger light source.
+*/
+
+pub struct LightToolPlugin;
+
+impl Plugin for LightToolPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LightTool>();
+        app.register_saveable::<PlacedLight>();
+
+        app.add_observer(setup_placed_light);
+        app.add_systems(Update, (
+            toggle_light_tool,
+            use_light_tool.run_if(light_tool_active),
+            light_panel_buttons.run_if(light_tool_active),
+            rebuild_light_panel,
+            draw_placed_lights.run_if(light_tool_active),
+        ).chain()
+            .run_if(in_state(PauseState::Running))
+            .run_if(in_state(CameraMode::Player))
+            .run_if(gameplay_input));
+        app.add_systems(PostUpdate, sync_placed_lights);
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct LightTool {
+    pub active: bool,
+    pub kind: PlacedLightKind,
+    pub selected: Option<Entity>,
+}
+
+fn light_tool_active(tool: Res<LightTool>) -> bool {
+    tool.active
+}
+
+#[derive(Reflect, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlacedLightKind {
+    #[default]
+    Point,
+    Spot,
+    Area,
+}
+
+impl PlacedLightKind {
+    fn next(self) -> Self {
+        match self {
+            PlacedLightKind::Point => PlacedLightKind::Spot,
+            PlacedLightKind::Spot => PlacedLightKind::Area,
+            PlacedLightKind::Area => PlacedLightKind::Point,
+        }
+    }
+}
+
+#[derive(Component, Reflect, Clone, Debug)]
+#[reflect(Component, Default)]
+pub struct PlacedLight {
+    pub kind: PlacedLightKind,
+    pub color: Color,
+    // In lumens, like bevy's PointLight and SpotLight
+    pub intensity: f32,
+    pub range: f32,
+    pub shadows: bool,
+}
+
+impl Default for PlacedLight {
+    fn default() -> Self {
+        Self {
+            kind: PlacedLightKind::Point,
+            color: Color::WHITE,
+            intensity: 200_000.0,
+            range: 20.0,
+            shadows: true,
+        }
+    }
+}
+
+// How far from the surface lights are placed
+const PLACE_OFFSET: f32 = 0.5;
+const PLACE_REACH: f32 = 50.0;
+const AREA_LIGHT_RADIUS: f32 = 0.5;
+// How close a right click has to be to select a light
+const SELECT_RADIUS: f32 = 2.0;
+const ADJUST_STEP: f32 = 1.25;
+const HUE_STEP: f32 = 30.0;
+
+#[derive(Component)]
+struct LightPanel;
+
+#[derive(Component, Clone, Copy)]
+enum LightPanelButton {
+    CycleKind,
+    Hue(f32),
+    Intensity(f32),
+    Range(f32),
+    ToggleShadows,
+    Delete,
+}
+
+fn setup_placed_light(trigger: On<Add, PlacedLight>, mut commands: Commands) {
+    commands.entity(trigger.entity).insert_if_new((
+        Name::new("Placed Light"),
+        Transform::default(),
+        Visibility::default(),
+        Saveable,
+    ));
+}
+
+// Turns PlacedLight settings into real bevy lights whenever they change
+fn sync_placed_lights(
+    mut commands: Commands,
+    lights: Query<(Entity, &PlacedLight), Changed<PlacedLight>>,
+) {
+    for (entity, placed) in &lights {
+        let mut light_entity = commands.entity(entity);
+        light_entity.remove::<(PointLight, SpotLight, WantsShadows)>();
+
+        match placed.kind {
+            PlacedLightKind::Point | PlacedLightKind::Area => {
+                light_entity.insert(PointLight {
+                    color: placed.color,
+                    intensity: placed.intensity,
+                    range: placed.range,
+                    radius: if placed.kind == PlacedLightKind::Area { AREA_LIGHT_RADIUS } else { 0.0 },
+                    ..default()
+                });
+            }
+            PlacedLightKind::Spot => {
+                light_entity.insert(SpotLight {
+                    color: placed.color,
+                    intensity: placed.intensity,
+                    range: placed.range,
+                    ..default()
+                });
+            }
+        }
+
+        // The shadow budget decides if it really gets shadows
+        if placed.shadows {
+            light_entity.insert(WantsShadows);
+        }
+    }
+}
+
+fn toggle_light_tool(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut tool: ResMut<LightTool>,
+) {
+    if keys.just_pressed(KeyCode::KeyL) {
+        tool.active = !tool.active;
+    }
+    if tool.active && keys.just_pressed(KeyCode::Tab) {
+        tool.kind = tool.kind.next();
+    }
+}
+
+fn use_light_tool(
+    mut commands: Commands,
+    mouse: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut tool: ResMut<LightTool>,
+    mut aim: PlayerAim,
+    lights: Query<(Entity, &GlobalTransform), With<PlacedLight>>,
+    ui_buttons: Query<&Interaction, With<LightPanelButton>>,
+) {
+    if keys.just_pressed(KeyCode::Delete) {
+        if let Some(selected) = tool.selected.take() {
+            commands.entity(selected).despawn();
+        }
+    }
+
+    // Clicks on the panel aren't for placing lights
+    if ui_buttons.iter().any(|interaction| *interaction != Interaction::None) {
+        return;
+    }
+
+    let place = mouse.just_pressed(MouseButton::Left);
+    let select = mouse.just_pressed(MouseButton::Right);
+    if !place && !select {
+        return;
+    }
+
+    let Some((_, hit)) = aim.cast(PLACE_REACH) else { return };
+
+    if select {
+        tool.selected = lights
+            .iter()
+            .map(|(entity, transform)| (entity, transform.translation().distance(hit.point)))
+            .filter(|(_, distance)| *distance <= SELECT_RADIUS)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(entity, _)| entity);
+        return;
+    }
+
+    let normal = hit.normal.normalize_or(Vec3::Y);
+    let position = hit.point + normal * PLACE_OFFSET;
+    // Spot lights shine back at the surface they were placed on
+    let up = if normal.abs().y > 0.99 { Vec3::Z } else { Vec3::Y };
+    let transform = Transform::from_translation(position).looking_to(-normal, up);
+
+    let light = commands.spawn((
+        PlacedLight {
+            kind: tool.kind,
+            ..default()
+        },
+        transform,
+    )).id();
+    tool.selected = Some(light);
+}
+
+fn light_panel_buttons(
+    mut commands: Commands,
+    buttons: Query<(&Interaction, &LightPanelButton), Changed<Interaction>>,
+    mut tool: ResMut<LightTool>,
+    mut lights: Query<&mut PlacedLight>,
+) {
+    for (interaction, button) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Some(selected) = tool.selected else { continue };
+
+        if let LightPanelButton::Delete = button {
+            commands.entity(selected).despawn();
+            tool.selected = None;
+            continue;
+        }
+
+        let Ok(mut light) = lights.get_mut(selected) else { continue };
+        match *button {
+            LightPanelButton::CycleKind => light.kind = light.kind.next(),
+            LightPanelButton::Hue(direction) => {
+                let mut hsla = Hsla::from(light.color);
+                // White has no hue, so give it some colour to rotate
+                if hsla.saturation < 0.1 {
+                    hsla.saturation = 1.0;
+                    hsla.lightness = 0.6;
+                }
+                light.color = hsla.rotate_hue(HUE_STEP * direction).into();
+            }
+            LightPanelButton::Intensity(direction) => light.intensity *= ADJUST_STEP.powf(direction),
+            LightPanelButton::Range(direction) => light.range *= ADJUST_STEP.powf(direction),
+            LightPanelButton::ToggleShadows => light.shadows = !light.shadows,
+            LightPanelButton::Delete => {}
+        }
+    }
+}
+
+// The panel is rebuilt when the tool or the selected light changes
+fn rebuild_light_panel(
+    mut commands: Commands,
+    tool: Res<LightTool>,
+    panels: Query<Entity, With<LightPanel>>,
+    lights: Query<Ref<PlacedLight>>,
+) {
+    let selected = tool.selected.and_then(|entity| lights.get(entity).ok());
+    let light_changed = selected.as_ref().is_some_and(|light| light.is_changed());
+    if !tool.is_changed() && !light_changed {
+        return;
+    }
+
+    for panel in &panels {
+        commands.entity(panel).despawn();
+    }
+    if !tool.active {
+        return;
+    }
+
+    let font = TextFont { font_size: 18.0, ..default() };
+    let mut lines = vec![format!("Light tool: placing {:?} (Tab)", tool.kind)];
+    match &selected {
+        Some(light) => lines.push(format!(
+            "Selected {:?} light, {:.0} lm, {:.1} m, shadows {}",
+            light.kind,
+            light.intensity,
+            light.range,
+            if light.shadows { "on" } else { "off" },
+        )),
+        None => lines.push("Left click to place, right click to select".into()),
+    }
+
+    commands.spawn((
+        Name::new("Light Tool Panel"),
+        LightPanel,
+        Node {
+            position_type: PositionType::Absolute,
+            top: px(12),
+            right: px(12),
+            padding: UiRect::all(px(8)),
+            row_gap: px(4),
+            flex_direction: FlexDirection::Column,
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+    )).with_children(|panel| {
+        for line in lines {
+            panel.spawn((Text::new(line), font.clone()));
+        }
+
+        let Some(light) = selected else { return };
+        panel.spawn((
+            Node { width: px(24), height: px(12), ..default() },
+            BackgroundColor(light.color),
+        ));
+
+        // Each row is a label and its buttons
+        let rows = [
+            ("Kind", vec![("next", LightPanelButton::CycleKind)]),
+            ("Colour", vec![("-", LightPanelButton::Hue(-1.0)), ("+", LightPanelButton::Hue(1.0))]),
+            ("Intensity", vec![("-", LightPanelButton::Intensity(-1.0)), ("+", LightPanelButton::Intensity(1.0))]),
+            ("Range", vec![("-", LightPanelButton::Range(-1.0)), ("+", LightPanelButton::Range(1.0))]),
+            ("Shadows", vec![("toggle", LightPanelButton::ToggleShadows)]),
+            ("Delete", vec![("delete", LightPanelButton::Delete)]),
+        ];
+        for (label, buttons) in rows {
+            panel.spawn(Node { column_gap: px(6), align_items: AlignItems::Center, ..default() })
+                .with_children(|row| {
+                    row.spawn((Text::new(label), font.clone(), Node { width: px(90), ..default() }));
+                    for (text, button) in buttons {
+                        row.spawn((
+                            Button,
+                            button,
+                            Node { padding: UiRect::horizontal(px(8)), ..default() },
+                            BackgroundColor(Color::srgba(0.3, 0.3, 0.3, 0.9)),
+                            children![(Text::new(text), font.clone())],
+                        ));
+                    }
+                });
+        }
+    });
+}
+
+// Lights are invisible, so draw them while the tool is open
+fn draw_placed_lights(
+    mut gizmos: Gizmos,
+    tool: Res<LightTool>,
+    lights: Query<(Entity, &GlobalTransform, &PlacedLight)>,
+) {
+    for (entity, transform, light) in &lights {
+        let color = if tool.selected == Some(entity) { YELLOW.into() } else { light.color };
+        gizmos.sphere(Isometry3d::from_translation(transform.translation()), 0.2, color);
+        if light.kind == PlacedLightKind::Spot {
+            gizmos.arrow(transform.translation(), transform.translation() + transform.forward() * 1.0, color);
+        }
+    }
+}
@@ -19,27 +19,80 @@ use bevy::prelude::*;
 
 // Declare the modules
 mod player;
+mod benchmark;
+mod camera_effects;
+mod console;
 mod world;
+mod input_context;
+mod inspector;
+mod interaction;
+mod inventory;
 mod keyboard_input;
+mod light_tool;
 mod mouse_input;
+mod pause_menu;
+mod prefab;
+mod respawn;
+mod scene_io;
+mod scripting;
+mod sound;
+mod spectator;
+mod stance;
+mod view_model;
 
 // Import world items
-use world::floor::FloorPlugin;
-use world::light::LightPlugin;
+use world::level::LevelPlugin;
+use world::light::LightingPlugin;
+use world::terrain::TerrainPlugin;
 use world::hud_text::HudTextPlugin;
 use world::objects::ObjectsPlugin;
+use world::object_loading::ObjectLoadingPlugin;
+use world::object_pool::ObjectPoolPlugin;
+use world::mechanisms::MechanismsPlugin;
+use world::portal::PortalPlugin;
 
 // Import logic plugins
+use camera_effects::CameraEffectsPlugin;
+use console::ConsolePlugin;
+use input_context::InputContextPlugin;
+use inspector::InspectorPlugin;
+use interaction::InteractionPlugin;
+use inventory::InventoryPlugin;
 use keyboard_input::KeyboardInputPlugin;
+use light_tool::LightToolPlugin;
 use player::PlayerPlugin;
 use mouse_input::MouseInputPlugin;
+use pause_menu::PausePlugin;
+use prefab::PrefabPlugin;
+use respawn::RespawnPlugin;
+use scene_io::SceneIoPlugin;
+use scripting::ScriptingPlugin;
+use sound::SoundPlugin;
+use spectator::SpectatorPlugin;
+use stance::StancePlugin;
+use view_model::ViewModelPlugin;
 
 // Only include the editor setup if the feature is enabled
 #[cfg(feature = "space_editor")]
 mod editor_setup;
+#[cfg(feature = "space_editor")]
+mod editor_tools;
+#[cfg(feature = "space_editor")]
+mod editor_playmode;
 
-fn main()
+fn main() -> AppExit
 {
+    // --benchmark N runs the headless spawn benchmark instead.
+    // A failed benchmark exits with an error code, for scripts running it.
+    match benchmark::BenchmarkSettings::from_args() {
+        Some(Ok(settings)) => return benchmark::run(settings),
+        Some(Err(err)) => {
+            eprintln!("{err}");
+            return AppExit::error();
+        }
+        None => {}
+    }
+
     // Create a new Bevy app
     let mut app = App::new();
 
@@ -47,24 +100,54 @@ fn main()
     app.add_plugins((
             DefaultPlugins,
             PlayerPlugin,
-            FloorPlugin,
-            LightPlugin,
+            LevelPlugin,
+            TerrainPlugin,
+            LightingPlugin,
             KeyboardInputPlugin,
             HudTextPlugin,
             MouseInputPlugin,
             ObjectsPlugin,
+            ObjectLoadingPlugin,
+            ObjectPoolPlugin,
+            PausePlugin,
+            SceneIoPlugin,
+            RespawnPlugin,
+    ));
+
+    // Bevy only takes 15 plugins per tuple,
+    // so the player feature plugins get their own
+    app.add_plugins((
+            LightToolPlugin,
+            SoundPlugin,
+            CameraEffectsPlugin,
+            StancePlugin,
+            SpectatorPlugin,
+            InteractionPlugin,
+            MechanismsPlugin,
+            InventoryPlugin,
+            ViewModelPlugin,
+            PortalPlugin,
+            PrefabPlugin,
+            InputContextPlugin,
+            InspectorPlugin,
+            ConsolePlugin,
+            ScriptingPlugin,
     ));
 
     // Conditionally add the editor setup plugin    
     #[cfg(feature = "space_editor")]
     {
         use editor_setup::EditorSetupPlugin;
+        use editor_tools::EditorToolsPlugin;
+        use editor_playmode::EditorPlaymodePlugin;
         use space_editor::SpaceEditorPlugin;
         // We must add space editor before our editor setup
         // So we can edit its global settings later
         app.add_plugins(SpaceEditorPlugin);
         app.add_plugins(EditorSetupPlugin);
+        app.add_plugins(EditorToolsPlugin);
+        app.add_plugins(EditorPlaymodePlugin);
     }
 
-    app.run();
+    app.run()
 }
\ No newline at end of file
@@ -2,20 +2,24 @@ use std::f32::consts::FRAC_PI_2;
 
 use bevy::{ecs::query::QuerySingleError, input::mouse::AccumulatedMouseMotion, prelude::*, window::{CursorGrabMode, CursorOptions, PrimaryWindow}};
 
-use crate::player::FpsPlayer;
+use crate::{input_context::gameplay_input, pause_menu::PauseState, player::FpsPlayer, spectator::CameraMode};
 
 pub struct MouseInputPlugin;
 
 impl Plugin for MouseInputPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(MouseLookSettings::default());
-        app.add_systems(Update, mouse_focus_toggle);
-        app.add_systems(Update, mouse_player_look);
+        app.register_type::<MouseLookSettings>();
+        app.add_systems(Update, (
+            mouse_focus_toggle,
+            mouse_player_look.run_if(in_state(CameraMode::Player)),
+        ).run_if(in_state(PauseState::Running)).run_if(gameplay_input));
     }
 }
 
 // A Resource is a single global variable, unlike having 100s of entities with components
 #[derive(Resource, Debug, Clone, Reflect)]
+#[reflect(Resource)]
 pub struct MouseLookSettings {
     pub sensitivity: Vec2,
     pub focus: MouseTabFocus,
@@ -30,7 +34,7 @@ impl Default for MouseLookSettings {
     }
 }
 
-#[derive(Reflect, Default, Clone, Debug)]
+#[derive(Reflect, Default, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum MouseTabFocus {
     InGame,
     #[default]
@@ -40,7 +44,7 @@ pub enum MouseTabFocus {
 fn mouse_player_look(
     mut accum_mouse: ResMut<AccumulatedMouseMotion>,    // Tracks how much the mouse has moved every frame.
     settings: Res<MouseLookSettings>,                   // Get the mouse look settings
-    mut fps_players: Query<&mut Transform, With<FpsPlayer>>,    // Get the player transform
+    mut fps_players: Query<(&mut Transform, &FpsPlayer)>,         // Get the player transform
     mut main_cam: Query<&mut Transform, Without<FpsPlayer>>,    // Get the main camera transform
 ) {
     let delta = accum_mouse.delta;  // Get how much the mouse has moved this frame
@@ -54,8 +58,14 @@ fn mouse_player_look(
 
     match settings.focus {
         MouseTabFocus::InGame => {
-             for mut player in &mut fps_players {
-                apply_look(&mut player, delta_yaw, delta_pitch, PITCH_LIMIT);
+             for (mut player_transform, player) in &mut fps_players {
+                // Yaw turns the whole player, pitch only tilts the camera
+                player_transform.rotate_y(delta_yaw);
+
+                let Some(camera_entity) = player.camera_entity() else { continue };
+                if let Ok(mut camera_transform) = main_cam.get_mut(camera_entity) {
+                    apply_pitch(&mut camera_transform, delta_pitch, PITCH_LIMIT);
+                }
             }
         }
         MouseTabFocus::None => {}
@@ -74,37 +84,46 @@ fn mouse_focus_toggle(
 
     let Ok(mut cursor) = window.single_mut() else { return };
 
-    settings.focus = match settings.focus {
-        // If we are not focused,
-        // Then switch to in-game focus
-      MouseTabFocus::None => {
-          cursor.grab_mode = CursorGrabMode::Locked;
-          cursor.visible = false;
+    let focus = match settings.focus {
+        MouseTabFocus::None => MouseTabFocus::InGame,
+        MouseTabFocus::InGame => MouseTabFocus::None,
+    };
+    set_mouse_focus(&mut settings, &mut cursor, focus);
+}
 
-          MouseTabFocus::InGame
-      }
+// Other plugins (like the pause menu) use this to grab
+// or release the cursor so the settings and the window
+// never disagree about who has the mouse.
+pub fn set_mouse_focus(
+    settings: &mut MouseLookSettings,
+    cursor: &mut CursorOptions,
+    focus: MouseTabFocus,
+) {
+    match focus {
+        // In-game focus locks and hides the cursor
+        MouseTabFocus::InGame => {
+            cursor.grab_mode = CursorGrabMode::Locked;
+            cursor.visible = false;
+        }
 
-      // If we are focused in-game,
-      // Then switch to no focus
-      MouseTabFocus::InGame => {
-          cursor.grab_mode = CursorGrabMode::None;
-          cursor.visible = true;
+        // No focus gives the cursor back
+        MouseTabFocus::None => {
+            cursor.grab_mode = CursorGrabMode::None;
+            cursor.visible = true;
+        }
+    }
 
-          MouseTabFocus::None
-      }
-    };
+    settings.focus = focus;
 }
 
-// Apply yaw and pitch to a transform
-fn apply_look(
+// Apply pitch to a transform
+fn apply_pitch(
     transform: &mut Transform,
-    delta_yaw: f32,
     delta_pitch: f32,
     pitch_limit: f32,
 ) {
     let (yaw, pitch, roll) = transform.rotation.to_euler(EulerRot::YXZ);
 
-    let yaw   = yaw + delta_yaw;
     let pitch = (pitch + delta_pitch).clamp(-pitch_limit, pitch_limit);
 
     transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, roll);
@@ -0,0 +1,507 @@
+use bevy::{ecs::system::SystemParam, prelude::*, window::{CursorOptions, PrimaryWindow}};
+
+use crate::{
+    camera_effects::CameraEffectsSettings,
+    input_context::menu_keys,
+    mouse_input::{MouseLookSettings, MouseTabFocus, set_mouse_focus},
+    scene_io::{LoadScene, SaveScene},
+    sound::AudioSettings,
+};
+
+/*
+    The pause menu is our first piece of state management.
+
+    PauseState::Running is the normal game. Gameplay systems
+    (movement, mouse look, spawning) only run in that state
+    with .run_if(in_state(PauseState::Running)), so they don't
+    need to check the menu themselves. (The ones that read input
+    also check input_context.rs, which knows about the editor.)
+
+    Escape (or Start on a gamepad) pauses. While paused we stop
+    Time<Virtual>, so anything that uses time.delta() freezes,
+    and we give the cursor back through MouseLookSettings.
+
+    The menu can be driven three ways:
+    - Keyboard: W/S or arrows to move, A/D or arrows to adjust, Enter/Space to press
+    - Mouse: hover to select, click to press
+    - Gamepad: D-pad to move and adjust, South to press, East to go back
+*/
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<PauseState>();
+        app.init_resource::<MenuPage>();
+        app.init_resource::<MenuSelection>();
+        app.init_resource::<FocusBeforePause>();
+
+        // Escape belongs to the editor or the console while they have the input
+        app.add_systems(Update, toggle_pause.run_if(menu_keys));
+        app.add_systems(OnEnter(PauseState::Paused), (pause_game, spawn_pause_menu));
+        app.add_systems(OnExit(PauseState::Paused), (resume_game, despawn_pause_menu));
+        app.add_systems(Update, (
+            rebuild_pause_menu,
+            menu_mouse_input,
+            menu_keyboard_and_gamepad_input,
+            highlight_selected_item,
+        ).chain().run_if(in_state(PauseState::Paused)));
+    }
+}
+
+#[derive(States, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PauseState {
+    #[default]
+    Running,
+    Paused,
+}
+
+// Which list of buttons the menu is showing
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MenuPage {
+    #[default]
+    Main,
+    Settings,
+}
+
+// Index of the highlighted item, shared by all input methods
+#[derive(Resource, Default)]
+struct MenuSelection {
+    index: usize,
+}
+
+// We remember if the mouse was captured so resume can put it back
+#[derive(Resource, Default)]
+struct FocusBeforePause(MouseTabFocus);
+
+// Remembers which page the spawned menu is showing
+#[derive(Component)]
+struct PauseMenuRoot(MenuPage);
+
+// Every selectable row in the menu.
+// The index is the row's place in the selection order.
+#[derive(Component)]
+struct MenuItem {
+    index: usize,
+    action: MenuAction,
+}
+
+// The small - and + buttons on a settings row
+#[derive(Component)]
+struct MenuAdjustButton {
+    action: MenuAction,
+    direction: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MenuAction {
+    Resume,
+    Settings,
+    SaveScene,
+    LoadScene,
+    Quit,
+    Back,
+    MouseSensitivity,
+    MasterVolume,
+    EffectsVolume,
+    AmbientVolume,
+    HeadBob,
+    LandingDip,
+    FovKick,
+    CameraShake,
+}
+
+// Every resource the settings page can change
+#[derive(SystemParam)]
+struct MenuSettings<'w> {
+    mouse: ResMut<'w, MouseLookSettings>,
+    audio: ResMut<'w, AudioSettings>,
+    camera: ResMut<'w, CameraEffectsSettings>,
+}
+
+const SENSITIVITY_STEP: f32 = 1.1;
+const VOLUME_STEP: f32 = 0.1;
+const MENU_BUTTON_COLOR: Color = Color::srgba(0.15, 0.15, 0.15, 0.9);
+const MENU_SELECTED_COLOR: Color = Color::srgba(0.35, 0.35, 0.6, 0.9);
+
+fn toggle_pause(
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    state: Res<State<PauseState>>,
+    mut next_state: ResMut<NextState<PauseState>>,
+    mut page: ResMut<MenuPage>,
+) {
+    let start_pressed = gamepads.iter().any(|gamepad| gamepad.just_pressed(GamepadButton::Start));
+    if !keys.just_pressed(KeyCode::Escape) && !start_pressed {
+        return;
+    }
+
+    match state.get() {
+        PauseState::Running => next_state.set(PauseState::Paused),
+        // Escape on a sub page goes back to the main page first
+        PauseState::Paused if *page != MenuPage::Main => *page = MenuPage::Main,
+        PauseState::Paused => next_state.set(PauseState::Running),
+    }
+}
+
+fn pause_game(
+    mut time: ResMut<Time<Virtual>>,
+    mut settings: ResMut<MouseLookSettings>,
+    mut focus_before_pause: ResMut<FocusBeforePause>,
+    mut window: Query<&mut CursorOptions, With<PrimaryWindow>>,
+) {
+    time.pause();
+
+    focus_before_pause.0 = settings.focus;
+    if let Ok(mut cursor) = window.single_mut() {
+        set_mouse_focus(&mut settings, &mut cursor, MouseTabFocus::None);
+    }
+}
+
+fn resume_game(
+    mut time: ResMut<Time<Virtual>>,
+    mut settings: ResMut<MouseLookSettings>,
+    focus_before_pause: Res<FocusBeforePause>,
+    mut page: ResMut<MenuPage>,
+    mut window: Query<&mut CursorOptions, With<PrimaryWindow>>,
+) {
+    time.unpause();
+    *page = MenuPage::Main;
+
+    if let Ok(mut cursor) = window.single_mut() {
+        set_mouse_focus(&mut settings, &mut cursor, focus_before_pause.0);
+    }
+}
+
+fn spawn_pause_menu(
+    mut commands: Commands,
+    page: Res<MenuPage>,
+    settings: MenuSettings,
+    mut selection: ResMut<MenuSelection>,
+) {
+    selection.index = 0;
+    spawn_menu_page(&mut commands, *page, &settings);
+}
+
+fn despawn_pause_menu(
+    mut commands: Commands,
+    menus: Query<Entity, With<PauseMenuRoot>>,
+) {
+    for menu in &menus {
+        commands.entity(menu).despawn();
+    }
+}
+
+// The menu is small, so when the page changes
+// we simply throw it away and build it again.
+fn rebuild_pause_menu(
+    mut commands: Commands,
+    page: Res<MenuPage>,
+    settings: MenuSettings,
+    menus: Query<(Entity, &PauseMenuRoot)>,
+    mut selection: ResMut<MenuSelection>,
+) {
+    if menus.iter().all(|(_, root)| root.0 == *page) {
+        return;
+    }
+    for (menu, _) in &menus {
+        commands.entity(menu).despawn();
+    }
+    selection.index = 0;
+    spawn_menu_page(&mut commands, *page, &settings);
+}
+
+fn spawn_menu_page(commands: &mut Commands, page: MenuPage, settings: &MenuSettings) {
+    let (title, actions): (&str, &[MenuAction]) = match page {
+        MenuPage::Main => ("Paused", &[
+            MenuAction::Resume,
+            MenuAction::Settings,
+            MenuAction::SaveScene,
+            MenuAction::LoadScene,
+            MenuAction::Quit,
+        ]),
+        MenuPage::Settings => ("Settings", &[
+            MenuAction::MouseSensitivity,
+            MenuAction::MasterVolume,
+            MenuAction::EffectsVolume,
+            MenuAction::AmbientVolume,
+            MenuAction::HeadBob,
+            MenuAction::LandingDip,
+            MenuAction::FovKick,
+            MenuAction::CameraShake,
+            MenuAction::Back,
+        ]),
+    };
+
+    commands.spawn((
+        Name::new("Pause Menu"),
+        PauseMenuRoot(page),
+        Node {
+            position_type: PositionType::Absolute,
+            width: percent(100),
+            height: percent(100),
+            flex_direction: FlexDirection::Column,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            row_gap: px(8),
+            ..default()
+        },
+        // Dim the game behind the menu
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+        GlobalZIndex(200),
+    )).with_children(|menu| {
+        menu.spawn((
+            Text::new(title),
+            TextFont { font_size: 40.0, ..default() },
+            Node { margin: UiRect::bottom(px(16)), ..default() },
+        ));
+
+        for (index, action) in actions.iter().copied().enumerate() {
+            spawn_menu_item(menu, index, action, settings);
+        }
+    });
+}
+
+fn spawn_menu_item(
+    menu: &mut ChildSpawnerCommands,
+    index: usize,
+    action: MenuAction,
+    settings: &MenuSettings,
+) {
+    let font = TextFont { font_size: 24.0, ..default() };
+
+    let mut row = menu.spawn((
+        Button,
+        MenuItem { index, action },
+        Node {
+            width: px(320),
+            height: px(44),
+            padding: UiRect::horizontal(px(12)),
+            justify_content: JustifyContent::SpaceBetween,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        BackgroundColor(MENU_BUTTON_COLOR),
+    ));
+
+    row.with_children(|row| {
+        row.spawn((Text::new(menu_label(action, settings)), font.clone()));
+
+        // Settings rows get - and + buttons for the mouse
+        if is_adjustable(action) {
+            row.spawn(Node { column_gap: px(6), ..default() }).with_children(|buttons| {
+                for (label, direction) in [("-", -1.0), ("+", 1.0)] {
+                    buttons.spawn((
+                        Button,
+                        MenuAdjustButton { action, direction },
+                        Node {
+                            width: px(32),
+                            justify_content: JustifyContent::Center,
+                            ..default()
+                        },
+                        BackgroundColor(MENU_SELECTED_COLOR),
+                        children![(Text::new(label), font.clone())],
+                    ));
+                }
+            });
+        }
+    });
+}
+
+fn menu_label(action: MenuAction, settings: &MenuSettings) -> String {
+    match action {
+        MenuAction::Resume => "Resume".into(),
+        MenuAction::Settings => "Settings".into(),
+        MenuAction::SaveScene => "Save Scene".into(),
+        MenuAction::LoadScene => "Load Scene".into(),
+        MenuAction::Quit => "Quit".into(),
+        MenuAction::Back => "Back".into(),
+        MenuAction::MouseSensitivity => {
+            let default_x = MouseLookSettings::default().sensitivity.x;
+            format!("Sensitivity {:.0}%", settings.mouse.sensitivity.x / default_x * 100.0)
+        }
+        MenuAction::MasterVolume => format!("Master Volume {:.0}%", settings.audio.master * 100.0),
+        MenuAction::EffectsVolume => format!("Effects Volume {:.0}%", settings.audio.effects * 100.0),
+        MenuAction::AmbientVolume => format!("Ambient Volume {:.0}%", settings.audio.ambient * 100.0),
+        MenuAction::HeadBob => toggle_label("Head Bob", settings.camera.head_bob),
+        MenuAction::LandingDip => toggle_label("Landing Dip", settings.camera.landing_dip),
+        MenuAction::FovKick => toggle_label("Sprint FOV", settings.camera.fov_kick),
+        MenuAction::CameraShake => toggle_label("Camera Shake", settings.camera.shake),
+    }
+}
+
+fn toggle_label(name: &str, enabled: bool) -> String {
+    format!("{name} {}", if enabled { "On" } else { "Off" })
+}
+
+// Rows with - and + buttons
+fn is_adjustable(action: MenuAction) -> bool {
+    matches!(
+        action,
+        MenuAction::MouseSensitivity
+            | MenuAction::MasterVolume
+            | MenuAction::EffectsVolume
+            | MenuAction::AmbientVolume
+    )
+}
+
+// On/off rows, pressing or adjusting them flips them
+fn is_toggle(action: MenuAction) -> bool {
+    matches!(
+        action,
+        MenuAction::HeadBob
+            | MenuAction::LandingDip
+            | MenuAction::FovKick
+            | MenuAction::CameraShake
+    )
+}
+
+fn menu_mouse_input(
+    mut commands: Commands,
+    items: Query<(&Interaction, &MenuItem), Changed<Interaction>>,
+    adjust_buttons: Query<(&Interaction, &MenuAdjustButton), Changed<Interaction>>,
+    mut selection: ResMut<MenuSelection>,
+    mut settings: MenuSettings,
+    mut page: ResMut<MenuPage>,
+    mut next_state: ResMut<NextState<PauseState>>,
+    mut app_exit: MessageWriter<AppExit>,
+) {
+    for (interaction, item) in &items {
+        match interaction {
+            Interaction::Hovered => selection.index = item.index,
+            Interaction::Pressed => {
+                selection.index = item.index;
+                press_menu_action(item.action, &mut commands, &mut settings, &mut page, &mut next_state, &mut app_exit);
+            }
+            Interaction::None => {}
+        }
+    }
+
+    for (interaction, button) in &adjust_buttons {
+        if *interaction == Interaction::Pressed {
+            adjust_menu_action(button.action, button.direction, &mut settings);
+        }
+    }
+}
+
+fn menu_keyboard_and_gamepad_input(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    items: Query<&MenuItem>,
+    mut selection: ResMut<MenuSelection>,
+    mut settings: MenuSettings,
+    mut page: ResMut<MenuPage>,
+    mut next_state: ResMut<NextState<PauseState>>,
+    mut app_exit: MessageWriter<AppExit>,
+) {
+    let gamepad_pressed = |button| gamepads.iter().any(|gamepad| gamepad.just_pressed(button));
+    let key_pressed = |a, b| keys.just_pressed(a) || keys.just_pressed(b);
+
+    let up = key_pressed(KeyCode::ArrowUp, KeyCode::KeyW) || gamepad_pressed(GamepadButton::DPadUp);
+    let down = key_pressed(KeyCode::ArrowDown, KeyCode::KeyS) || gamepad_pressed(GamepadButton::DPadDown);
+    let left = key_pressed(KeyCode::ArrowLeft, KeyCode::KeyA) || gamepad_pressed(GamepadButton::DPadLeft);
+    let right = key_pressed(KeyCode::ArrowRight, KeyCode::KeyD) || gamepad_pressed(GamepadButton::DPadRight);
+    let press = key_pressed(KeyCode::Enter, KeyCode::Space) || gamepad_pressed(GamepadButton::South);
+    let back = gamepad_pressed(GamepadButton::East);
+
+    let item_count = items.iter().count();
+    if item_count == 0 {
+        return;
+    }
+
+    if up {
+        selection.index = (selection.index + item_count - 1) % item_count;
+    }
+    if down {
+        selection.index = (selection.index + 1) % item_count;
+    }
+
+    let Some(selected) = items.iter().find(|item| item.index == selection.index) else { return };
+
+    if left || right {
+        adjust_menu_action(selected.action, if right { 1.0 } else { -1.0 }, &mut settings);
+    }
+
+    if press {
+        press_menu_action(selected.action, &mut commands, &mut settings, &mut page, &mut next_state, &mut app_exit);
+    } else if back {
+        press_menu_action(MenuAction::Back, &mut commands, &mut settings, &mut page, &mut next_state, &mut app_exit);
+    }
+}
+
+fn press_menu_action(
+    action: MenuAction,
+    commands: &mut Commands,
+    settings: &mut MenuSettings,
+    page: &mut MenuPage,
+    next_state: &mut NextState<PauseState>,
+    app_exit: &mut MessageWriter<AppExit>,
+) {
+    match action {
+        MenuAction::Resume => next_state.set(PauseState::Running),
+        MenuAction::Settings => *page = MenuPage::Settings,
+        MenuAction::SaveScene => commands.trigger(SaveScene),
+        MenuAction::LoadScene => commands.trigger(LoadScene),
+        MenuAction::Quit => {
+            app_exit.write(AppExit::Success);
+        }
+        MenuAction::Back => match page {
+            MenuPage::Main => next_state.set(PauseState::Running),
+            MenuPage::Settings => *page = MenuPage::Main,
+        },
+        // Pressing a setting steps it up, like the + button
+        MenuAction::MouseSensitivity
+        | MenuAction::MasterVolume
+        | MenuAction::EffectsVolume
+        | MenuAction::AmbientVolume
+        | MenuAction::HeadBob
+        | MenuAction::LandingDip
+        | MenuAction::FovKick
+        | MenuAction::CameraShake => adjust_menu_action(action, 1.0, settings),
+    }
+}
+
+fn adjust_menu_action(action: MenuAction, direction: f32, settings: &mut MenuSettings) {
+    let step_volume = |volume: &mut f32| *volume = (*volume + VOLUME_STEP * direction).clamp(0.0, 1.0);
+
+    match action {
+        MenuAction::MouseSensitivity => settings.mouse.sensitivity *= SENSITIVITY_STEP.powf(direction),
+        MenuAction::MasterVolume => step_volume(&mut settings.audio.master),
+        MenuAction::EffectsVolume => step_volume(&mut settings.audio.effects),
+        MenuAction::AmbientVolume => step_volume(&mut settings.audio.ambient),
+        MenuAction::HeadBob => settings.camera.head_bob = !settings.camera.head_bob,
+        MenuAction::LandingDip => settings.camera.landing_dip = !settings.camera.landing_dip,
+        MenuAction::FovKick => settings.camera.fov_kick = !settings.camera.fov_kick,
+        MenuAction::CameraShake => settings.camera.shake = !settings.camera.shake,
+        _ => {}
+    }
+}
+
+fn highlight_selected_item(
+    selection: Res<MenuSelection>,
+    settings: MenuSettings,
+    mut items: Query<(&MenuItem, &mut BackgroundColor, &Children)>,
+    mut texts: Query<&mut Text>,
+) {
+    for (item, mut background, children) in &mut items {
+        let color = if item.index == selection.index {
+            MENU_SELECTED_COLOR
+        } else {
+            MENU_BUTTON_COLOR
+        };
+        if background.0 != color {
+            background.0 = color;
+        }
+
+        // Keep setting values up to date as they change
+        let settings_changed = settings.mouse.is_changed()
+            || settings.audio.is_changed()
+            || settings.camera.is_changed();
+        if settings_changed && (is_adjustable(item.action) || is_toggle(item.action)) {
+            if let Some(mut text) = children.first().and_then(|child| texts.get_mut(*child).ok()) {
+                text.0 = menu_label(item.action, &settings);
+            }
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use bevy::{camera::visibility::RenderLayers, color::palettes::css::RED, prelude::*, render::Render};
+use bevy::{camera::visibility::RenderLayers, color::palettes::css::RED, ecs::system::SystemParam, picking::mesh_picking::ray_cast::RayMeshHit, prelude::*, render::Render};
+
+use crate::{camera_effects::CameraEffects, inventory::Inventory, scene_io::Saveable, view_model::ViewModel, world::portal::PORTAL_LAYER};
 
 /*
     I've come across a good way to handle cameras and
@@ -26,21 +28,178 @@ impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
        app.add_systems(Startup, spawn_character_startup);
         app.add_observer(spawn_character_trigger);
+        app.add_systems(PostUpdate, (
+            migrate_scaled_player_roots,
+            update_player_layout,
+        ).chain().before(TransformSystems::Propagate));
+        app.add_systems(Last, clear_teleported);
    }
 }
 
 // We save a link to the camera and feet entities
 // so that we can easily access them later if needed.
-#[derive(Component, Reflect, Default)]
-#[reflect(Component)]
+//
+// The player root sits at the player's feet and is never scaled.
+// eye_height and head_size place the children explicitly,
+// so scaling the root is no longer needed to make the player bigger.
+#[derive(Component, Reflect)]
+#[reflect(Component, Default)]
 pub struct FpsPlayer {
     camera_entity: Option<Entity>,
     head_entity: Option<Entity>,
-   
-    // ADD CROSSHAIR ENTITY HERE LATER
     crosshair_entity: Option<Entity>,
+
+    // Height of the camera above the root (feet) in meters
+    pub eye_height: f32,
+    // Eye heights while crouching and lying down
+    pub crouch_eye_height: f32,
+    pub prone_eye_height: f32,
+    // Size of the head cube around the camera in meters
+    pub head_size: f32,
+
+    // Standing, crouching or prone, see stance.rs
+    pub stance: Stance,
+}
+
+impl Default for FpsPlayer {
+    fn default() -> Self {
+        Self {
+            camera_entity: None,
+            head_entity: None,
+            crosshair_entity: None,
+            eye_height: 1.5,
+            crouch_eye_height: 0.9,
+            prone_eye_height: 0.3,
+            head_size: 0.35,
+            stance: Stance::Stand,
+        }
+    }
+}
+
+impl FpsPlayer {
+    pub fn camera_entity(&self) -> Option<Entity> {
+        self.camera_entity
+    }
+
+    pub fn head_entity(&self) -> Option<Entity> {
+        self.head_entity
+    }
+
+    pub fn crosshair_entity(&self) -> Option<Entity> {
+        self.crosshair_entity
+    }
+
+    // Where the eyes should be for a stance
+    pub fn stance_eye_height(&self, stance: Stance) -> f32 {
+        match stance {
+            Stance::Stand => self.eye_height,
+            Stance::Crouch => self.crouch_eye_height,
+            Stance::Prone => self.prone_eye_height,
+        }
+    }
+}
+
+#[derive(Reflect, Default, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stance {
+    #[default]
+    Stand,
+    Crouch,
+    Prone,
+}
+
+impl Stance {
+    // How fast we move compared to standing
+    pub fn speed_multiplier(self) -> f32 {
+        match self {
+            Stance::Stand => 1.0,
+            Stance::Crouch => 0.5,
+            Stance::Prone => 0.25,
+        }
+    }
+}
+
+// The eye height the camera is at right now.
+// It moves smoothly towards the stance's eye height,
+// and the camera and head follow it.
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+#[reflect(Component)]
+pub struct PlayerEyeHeight(pub f32);
+
+// A capsule standing on the player's feet.
+// There's no physics engine yet, so for now it's what
+// the stance check uses to see if we fit somewhere.
+#[derive(Component, Reflect, Clone, Copy, Debug)]
+#[reflect(Component)]
+pub struct PlayerCollider {
+    pub radius: f32,
+    pub height: f32,
+}
+
+// The collider reaches this far above the eyes
+pub const HEAD_CLEARANCE: f32 = 0.2;
+
+// Added when the player is moved instantly (respawning, portals, tp)
+// so systems watching the player move don't see it as a fast fall.
+// Removed again at the end of the frame.
+#[derive(Component, Default)]
+pub struct Teleported;
+
+// Anything that needs to know what the crosshair is pointing
+// at (placing things, interacting) can take this as a system
+// parameter instead of finding the camera and raycasting itself.
+#[derive(SystemParam)]
+pub struct PlayerAim<'w, 's> {
+    players: Query<'w, 's, &'static FpsPlayer>,
+    transforms: Query<'w, 's, &'static GlobalTransform>,
+    layers: Query<'w, 's, &'static RenderLayers>,
+    ray_cast: MeshRayCast<'w, 's>,
+}
+
+impl PlayerAim<'_, '_> {
+    // The ray from the player camera through the crosshair
+    pub fn ray(&self) -> Option<Ray3d> {
+        let player = self.players.iter().next()?;
+        let camera = self.transforms.get(player.camera_entity?).ok()?;
+        Some(Ray3d::new(camera.translation(), camera.forward()))
+    }
+
+    // The closest mesh under the crosshair within max_distance,
+    // ignoring the player's own head and crosshair meshes
+    // and anything the player camera can't see, like the view model.
+    pub fn cast(&mut self, max_distance: f32) -> Option<(Entity, RayMeshHit)> {
+        let ray = self.ray()?;
+        self.cast_ray(ray, max_distance)
+    }
+
+    // Same as cast, but along any ray
+    pub fn cast_ray(&mut self, ray: Ray3d, max_distance: f32) -> Option<(Entity, RayMeshHit)> {
+        let player = self.players.iter().next()?;
+        let own_parts = [player.head_entity, player.crosshair_entity];
+
+        let layers = &self.layers;
+        let filter = |entity: Entity| {
+            !own_parts.contains(&Some(entity))
+                && layers.get(entity).ok().is_none_or(|layers| layers.intersects(&RenderLayers::default()))
+        };
+        let settings = MeshRayCastSettings::default().with_filter(&filter);
+        self.ray_cast
+            .cast_ray(ray, &settings)
+            .iter()
+            .find(|(_, hit)| hit.distance <= max_distance)
+            .cloned()
+    }
 }
 
+// The crosshair is a tiny dot just in front of the camera
+const CROSSHAIR_DISTANCE: f32 = 0.2;
+const CROSSHAIR_SCALE: f32 = 0.002;
+
+// How fast the player moved last frame in meters per second.
+// Movement writes it, and respawning resets it to zero.
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+#[reflect(Component)]
+pub struct PlayerVelocity(pub Vec3);
+
 // Notice how simple spawn character setup is.
 // We just spawn an entity with the FpsPlayer component.
 // Or when we are in the editor, we can add FpsPlayer
@@ -55,19 +214,33 @@ fn spawn_character_startup(
 
     commands.spawn((
         Name::new("Startup FPS Player"),
-        FpsPlayer {
-            camera_entity: None,
-            head_entity: None,
-            crosshair_entity: None,
-        }
+        FpsPlayer::default(),
     ));
 }
 
+// Despawns the camera, head and crosshair and adds FpsPlayer
+// again with empty links, so the observer below spawns fresh ones.
+// Handy when the children were deleted or messed up in the editor.
+pub fn rebuild_player_children(commands: &mut Commands, player_entity: Entity, player: &FpsPlayer) {
+    // The crosshair goes with the camera
+    for child in [player.camera_entity, player.head_entity].into_iter().flatten() {
+        commands.entity(child).try_despawn();
+    }
+
+    commands.entity(player_entity).remove::<FpsPlayer>().insert(FpsPlayer {
+        camera_entity: None,
+        head_entity: None,
+        crosshair_entity: None,
+        ..*player
+    });
+}
+
 // Here is where the real action happens.
 fn spawn_character_trigger(
    trigger: On<Add, FpsPlayer>,
    mut commands: Commands,
    mut players: Query<&mut FpsPlayer>,
+   parents: Query<&ChildOf>,
    mut meshes: ResMut<Assets<Mesh>>,
    mut materials: ResMut<Assets<StandardMaterial>>,
    mut material_2d: ResMut<Assets<ColorMaterial>>
@@ -85,6 +258,27 @@ fn spawn_character_trigger(
        return;
    };
 
+   // A loaded scene can hand us links to entities that
+   // were never saved, or that now belong to something else.
+   // Only keep a link if it still points at our own child.
+   let is_child_of = |child: Option<Entity>, parent: Option<Entity>| {
+       match (child, parent) {
+           (Some(child), Some(parent)) => parents
+               .get(child)
+               .is_ok_and(|child_of| child_of.parent() == parent),
+           _ => false,
+       }
+   };
+   if !is_child_of(player.camera_entity, Some(player_root)) {
+       player.camera_entity = None;
+   }
+   if !is_child_of(player.head_entity, Some(player_root)) {
+       player.head_entity = None;
+   }
+   if !is_child_of(player.crosshair_entity, player.camera_entity) {
+       player.crosshair_entity = None;
+   }
+
    // Spawn the root player entity
    // Note the insert_if_new function.
    // When using insert, all the listed components
@@ -95,24 +289,43 @@ fn spawn_character_trigger(
    // saved or designated, the trigger won't overide it.
    let player_root = commands.entity(player_root).insert_if_new((
        Name::new("Player"), // Create a component Name and stores value Player
-       Transform {
-          translation: Vec3::new(0.0,1.5,0.0),
-          rotation: Quat::IDENTITY, //Placeholder for default
-          scale: Vec3::splat(3.5),
-       },
+       // The root is at the feet, the camera is eye_height above it
+       Transform::default(),
        Visibility::default(),
+       PlayerVelocity::default(),
+       PlayerEyeHeight(player.stance_eye_height(player.stance)),
+       PlayerCollider {
+           radius: 0.3,
+           height: player.stance_eye_height(player.stance) + HEAD_CLEARANCE,
+       },
+       // Empty hotbar, or whatever was saved with the player
+       Inventory::default(),
+       // Lets the pause menu save and load the player
+       Saveable,
    )).id();
 
    // If we already have a known camera, we won't
-   // create a new one. Future improvement could be
-   // to check if the existing camera is valid.
+   // create a new one.
    if player.camera_entity.is_none() {
 
        let player_cam = commands.spawn((
           Name::new("Player Camera"),
           Camera3d::default(),
-          // Camera transform will be at origin of FpsPlayer
-          Transform::from_xyz(0.0,0.0,0.0).looking_at(Vec3::ZERO, Vec3::Y),
+          // Camera sits at eye height above the FpsPlayer root.
+          // The root turns left and right, the camera looks up and down.
+          Transform::from_xyz(0.0, player.stance_eye_height(player.stance), 0.0),
+          // We hear spatial sounds from the camera.
+          // The gap is the distance between the ears in meters.
+          SpatialListener::new(0.2),
+          // The world, and the portal surfaces only we can see
+          RenderLayers::from_layers(&[0, PORTAL_LAYER]),
+          // Head bob, landing dip, sprint FOV and shake
+          CameraEffects::default(),
+          // The held item, drawn by its own camera
+          ViewModel::default(),
+          // The HUD and menus draw over this camera, not the
+          // view model's (the spectator camera takes it over)
+          IsDefaultUiCamera,
 
           // For space_editor compatibility
           #[cfg(feature = "space_editor")]
@@ -136,9 +349,9 @@ fn spawn_character_trigger(
        // especially when we get to portals and multiplayer
        let player_head = commands.spawn((
           Name::new("Player Head"),
-          Mesh3d(meshes.add(Cuboid::new(0.1, 0.1, 0.1))),
+          Mesh3d(meshes.add(Cuboid::from_length(1.0))),
           MeshMaterial3d(materials.add(Color::srgba(0.0, 0.5, 0.0, 1.0))),
-          Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)),
+          head_transform(&player, player.stance_eye_height(player.stance)),
        )).id();
 
        commands.entity(player_root).add_child(player_head);
@@ -164,11 +377,7 @@ fn spawn_character_trigger(
                     unlit: true, // Make it ignore lighting so it stays bright red
                     ..default()
                 })),
-                Transform{
-                    translation: Vec3::new(0.0, 0.0, -0.2), // Place it slightly in front of camera
-                    rotation: Quat::IDENTITY,
-                    scale: Vec3::splat(0.002),
-                }
+                crosshair_transform(),
             )).id();
             if let Some(camera_entity) = player.camera_entity {
                     commands.entity(camera_entity).add_child(crosshair);
@@ -201,4 +410,77 @@ fn spawn_character_trigger(
    // for the playmode camera view, and the crosshair lags
    // behind a frame. Not a big deal for now.
 
-}
\ No newline at end of file
+}
+
+fn head_transform(player: &FpsPlayer, eye_height: f32) -> Transform {
+    Transform::from_xyz(0.0, eye_height, 0.0)
+        .with_scale(Vec3::splat(player.head_size))
+}
+
+fn crosshair_transform() -> Transform {
+    // Place it slightly in front of camera
+    Transform::from_xyz(0.0, 0.0, -CROSSHAIR_DISTANCE)
+        .with_scale(Vec3::splat(CROSSHAIR_SCALE))
+}
+
+// Keeps the camera, head and crosshair where FpsPlayer says
+// they should be, for example after head_size is edited
+// or while the eyes move to a new stance.
+pub fn update_player_layout(
+    players: Query<(&FpsPlayer, &PlayerEyeHeight), Or<(Changed<FpsPlayer>, Changed<PlayerEyeHeight>)>>,
+    mut transforms: Query<&mut Transform, Without<FpsPlayer>>,
+) {
+    for (player, eye_height) in &players {
+        if let Some(mut camera) = player.camera_entity.and_then(|e| transforms.get_mut(e).ok()) {
+            camera.translation = Vec3::new(0.0, eye_height.0, 0.0);
+            camera.scale = Vec3::ONE;
+        }
+        if let Some(mut head) = player.head_entity.and_then(|e| transforms.get_mut(e).ok()) {
+            *head = head_transform(player, eye_height.0);
+        }
+        if let Some(mut crosshair) = player.crosshair_entity.and_then(|e| transforms.get_mut(e).ok()) {
+            *crosshair = crosshair_transform();
+        }
+    }
+}
+
+// Older saved scenes had the player root scaled by 3.5,
+// with the camera sitting at the root and the root holding
+// both yaw and pitch. We turn those into the new layout:
+// an unscaled root at the feet with yaw only, and the
+// pitch moved onto the camera. The eye stays where it was,
+// so the scene looks the same after loading.
+fn clear_teleported(mut commands: Commands, teleported: Query<Entity, With<Teleported>>) {
+    for entity in &teleported {
+        commands.entity(entity).remove::<Teleported>();
+    }
+}
+
+// Only players that were just loaded or spawned are migrated,
+// scaling the player later on (editor, inspector, scripts)
+// is left alone.
+fn migrate_scaled_player_roots(
+    mut players: Query<(&mut Transform, &mut FpsPlayer), Added<FpsPlayer>>,
+    mut cameras: Query<&mut Transform, Without<FpsPlayer>>,
+) {
+    for (mut root, mut player) in &mut players {
+        if root.scale == Vec3::ONE {
+            continue;
+        }
+
+        let eye_position = root.translation;
+        let (yaw, pitch, _) = root.rotation.to_euler(EulerRot::YXZ);
+
+        root.scale = Vec3::ONE;
+        root.rotation = Quat::from_rotation_y(yaw);
+        root.translation = eye_position - Vec3::Y * player.eye_height;
+
+        if let Some(mut camera) = player.camera_entity.and_then(|e| cameras.get_mut(e).ok()) {
+            camera.rotation = Quat::from_rotation_x(pitch);
+        }
+
+        // Makes update_player_layout put the children back in place
+        player.set_changed();
+        info!("Migrated scaled FpsPlayer root to eye_height {}", player.eye_height);
+    }
+}
@@ -0,0 +1,285 @@
+use std::{any::TypeId, fmt};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    prelude::*,
+    reflect::{PartialReflect, TypeRegistry, TypeRegistryArc, serde::TypedReflectDeserializer},
+    scene::{DynamicEntity, SceneInstanceReady},
+};
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, Visitor};
+
+use crate::{
+    inventory::{ItemCatalog, ItemDefinition},
+    scene_io::Saveable,
+};
+
+/*
+    A prefab is a group of entities saved to a file that can
+    be spawned as many times as you like, like a boat with
+    a light on it, or a door with its switch.
+
+    Prefabs are bevy scene files (the same RON format the
+    pause menu saves to), named *.prefab.scn.ron and kept in
+    assets/prefabs/. Each entity lists its reflected components,
+    ChildOf links build the hierarchy, and GLTF models are
+    referenced by path through SpawnedObject, so the observers
+    build the rest when it's spawned, like everything else.
+
+    Since it's a normal .scn.ron file, space_editor can open
+    and save it. Saving from the editor also writes components
+    the game doesn't know about (editor markers, GlobalTransform,
+    visibility that is computed every frame...). Bevy's own scene
+    loader fails on those, so the PrefabLoader here skips anything
+    that isn't a registered component and logs what it skipped.
+
+    To spawn one, use its path anywhere a model path goes:
+
+        SpawnedObject { scene_path: "prefabs/boat_pair.prefab.scn.ron".into() }
+
+    That also means prefabs can be items in the ItemCatalog,
+    dropped from the inventory, or spawned with E by setting
+    the SpawnKeyItem resource to a prefab item.
+
+    Only the entity with SpawnedObject is saved with the scene,
+    it spawns the prefab again when loaded.
+*/
+
+pub struct PrefabPlugin;
+
+impl Plugin for PrefabPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset_loader::<PrefabLoader>();
+        app.add_observer(dont_save_scene_instances);
+
+        app.world_mut()
+            .get_resource_or_init::<ItemCatalog>()
+            .register("boat_pair", ItemDefinition::new("Boat Pair", "prefabs/boat_pair.prefab.scn.ron", 4));
+    }
+}
+
+pub const PREFAB_EXTENSION: &str = ".prefab.scn.ron";
+
+pub fn is_prefab_path(path: &str) -> bool {
+    path.ends_with(PREFAB_EXTENSION)
+}
+
+// Loads *.prefab.scn.ron files into a DynamicScene,
+// which DynamicSceneRoot spawns under the entity it's on
+#[derive(TypePath)]
+struct PrefabLoader {
+    type_registry: TypeRegistryArc,
+}
+
+impl FromWorld for PrefabLoader {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            type_registry: world.resource::<AppTypeRegistry>().0.clone(),
+        }
+    }
+}
+
+impl AssetLoader for PrefabLoader {
+    type Asset = DynamicScene;
+    type Settings = ();
+    type Error = BevyError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<DynamicScene, BevyError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        let registry = self.type_registry.read();
+        let mut skipped = Vec::new();
+        let mut deserializer = bevy::scene::ron::de::Deserializer::from_bytes(&bytes)?;
+        let entities = PrefabSeed { registry: &registry, skipped: &mut skipped }
+            .deserialize(&mut deserializer)?;
+
+        if !skipped.is_empty() {
+            skipped.sort();
+            skipped.dedup();
+            debug!("{} skipped components: {}", load_context.path().display(), skipped.join(", "));
+        }
+
+        Ok(DynamicScene {
+            resources: Vec::new(),
+            entities,
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["prefab.scn.ron"]
+    }
+}
+
+// Reads the same layout as bevy's scene files:
+// ( resources: { ... }, entities: { id: ( components: { "type::Path": value } ) } )
+// Resources are ignored, prefabs are only entities.
+struct PrefabSeed<'a> {
+    registry: &'a TypeRegistry,
+    skipped: &'a mut Vec<String>,
+}
+
+impl<'de> DeserializeSeed<'de> for PrefabSeed<'_> {
+    type Value = Vec<DynamicEntity>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_struct("Scene", &["resources", "entities"], self)
+    }
+}
+
+impl<'de> Visitor<'de> for PrefabSeed<'_> {
+    type Value = Vec<DynamicEntity>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a scene")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut entities = Vec::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if key != "entities" {
+                map.next_value::<IgnoredAny>()?;
+                continue;
+            }
+            entities = map.next_value_seed(EntitiesSeed { registry: self.registry, skipped: &mut *self.skipped })?;
+        }
+        Ok(entities)
+    }
+}
+
+struct EntitiesSeed<'a> {
+    registry: &'a TypeRegistry,
+    skipped: &'a mut Vec<String>,
+}
+
+impl<'de> DeserializeSeed<'de> for EntitiesSeed<'_> {
+    type Value = Vec<DynamicEntity>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for EntitiesSeed<'_> {
+    type Value = Vec<DynamicEntity>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of entities")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut entities = Vec::new();
+        while let Some(entity) = map.next_key::<Entity>()? {
+            let components = map.next_value_seed(EntitySeed { registry: self.registry, skipped: &mut *self.skipped })?;
+            entities.push(DynamicEntity { entity, components });
+        }
+        Ok(entities)
+    }
+}
+
+struct EntitySeed<'a> {
+    registry: &'a TypeRegistry,
+    skipped: &'a mut Vec<String>,
+}
+
+impl<'de> DeserializeSeed<'de> for EntitySeed<'_> {
+    type Value = Vec<Box<dyn PartialReflect>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_struct("Entity", &["components"], self)
+    }
+}
+
+impl<'de> Visitor<'de> for EntitySeed<'_> {
+    type Value = Vec<Box<dyn PartialReflect>>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an entity")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut components = Vec::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if key != "components" {
+                map.next_value::<IgnoredAny>()?;
+                continue;
+            }
+            components = map.next_value_seed(ComponentsSeed { registry: self.registry, skipped: &mut *self.skipped })?;
+        }
+        Ok(components)
+    }
+}
+
+struct ComponentsSeed<'a> {
+    registry: &'a TypeRegistry,
+    skipped: &'a mut Vec<String>,
+}
+
+impl<'de> DeserializeSeed<'de> for ComponentsSeed<'_> {
+    type Value = Vec<Box<dyn PartialReflect>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ComponentsSeed<'_> {
+    type Value = Vec<Box<dyn PartialReflect>>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of components")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut components = Vec::new();
+        while let Some(type_path) = map.next_key::<String>()? {
+            let registration = self
+                .registry
+                .get_with_type_path(&type_path)
+                .filter(|registration| is_prefab_component(registration.type_id()) && registration.data::<ReflectComponent>().is_some());
+
+            match registration {
+                Some(registration) => {
+                    components.push(map.next_value_seed(TypedReflectDeserializer::new(registration, self.registry))?);
+                }
+                None => {
+                    map.next_value::<IgnoredAny>()?;
+                    self.skipped.push(type_path);
+                }
+            }
+        }
+        Ok(components)
+    }
+}
+
+// These are worked out every frame, so there's no point loading them
+fn is_prefab_component(type_id: TypeId) -> bool {
+    ![
+        TypeId::of::<GlobalTransform>(),
+        TypeId::of::<InheritedVisibility>(),
+        TypeId::of::<ViewVisibility>(),
+    ]
+    .contains(&type_id)
+}
+
+// The entities a saved entity's scene spawns (the prefab's entities,
+// or a GLTF's) are rebuilt when it loads, so they shouldn't be saved too
+fn dont_save_scene_instances(
+    trigger: On<SceneInstanceReady>,
+    mut commands: Commands,
+    saveables: Query<(), With<Saveable>>,
+    children: Query<&Children>,
+) {
+    if saveables.get(trigger.entity).is_err() {
+        return;
+    }
+    for descendant in children.iter_descendants(trigger.entity) {
+        if saveables.contains(descendant) {
+            commands.entity(descendant).remove::<Saveable>();
+        }
+    }
+}
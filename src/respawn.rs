@@ -0,0 +1,179 @@
+use bevy::prelude::*;
+
+use crate::{
+    camera_effects::CameraShake,
+    input_context::gameplay_input,
+    pause_menu::PauseState,
+    player::{FpsPlayer, PlayerVelocity, Teleported},
+    spectator::CameraMode,
+};
+
+/*
+    Spawn points are just entities with a SpawnPoint component
+    and a Transform. Levels spawn them, but you can also add
+    one to any entity in the editor.
+
+    The player is sent back to a spawn point when:
+    - R is pressed
+    - They fall below a KillPlane
+    - They leave every PlayBounds box (if the level has any)
+    - Something calls commands.trigger(RespawnPlayer::default())
+
+    A designated spawn point always wins (if there are
+    several, any one of them). Otherwise we pick the spawn
+    point closest to where the player was. Spawn points can
+    be children of other entities, we go by where they are
+    in the world.
+*/
+
+pub struct RespawnPlugin;
+
+impl Plugin for RespawnPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(RespawnSettings::default());
+        app.register_type::<SpawnPoint>();
+        app.register_type::<KillPlane>();
+        app.register_type::<PlayBounds>();
+
+        app.add_observer(respawn_player);
+        app.add_systems(Update, (
+            respawn_on_key.run_if(in_state(CameraMode::Player)).run_if(gameplay_input),
+            respawn_out_of_bounds,
+        ).run_if(in_state(PauseState::Running)));
+    }
+}
+
+#[derive(Resource, Debug, Clone, Reflect)]
+pub struct RespawnSettings {
+    pub respawn_key: KeyCode,
+    // Used when the level doesn't have a KillPlane
+    pub default_kill_height: f32,
+    // Used when there are no spawn points at all
+    pub fallback_spawn: Vec3,
+}
+
+impl Default for RespawnSettings {
+    fn default() -> Self {
+        Self {
+            respawn_key: KeyCode::KeyR,
+            default_kill_height: -50.0,
+            fallback_spawn: Vec3::ZERO,
+        }
+    }
+}
+
+// The spawn point's Transform gives the position,
+// and its rotation around y gives the look direction.
+#[derive(Component, Reflect, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct SpawnPoint {
+    pub designated: bool,
+}
+
+// Anything that falls below this height is out of bounds
+#[derive(Component, Reflect, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct KillPlane {
+    pub height: f32,
+}
+
+// A box around its Transform the player has to stay inside
+#[derive(Component, Reflect, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct PlayBounds {
+    pub half_extents: Vec3,
+}
+
+// Leave spawn_point as None to let the rules above choose
+#[derive(Event, Debug, Clone, Copy, Default)]
+pub struct RespawnPlayer {
+    pub spawn_point: Option<Entity>,
+}
+
+fn respawn_on_key(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<RespawnSettings>,
+) {
+    if keys.just_pressed(settings.respawn_key) {
+        commands.trigger(RespawnPlayer::default());
+    }
+}
+
+fn respawn_out_of_bounds(
+    mut commands: Commands,
+    settings: Res<RespawnSettings>,
+    players: Query<&GlobalTransform, With<FpsPlayer>>,
+    kill_planes: Query<&KillPlane>,
+    bounds: Query<(&PlayBounds, &GlobalTransform)>,
+) {
+    let kill_height = kill_planes
+        .iter()
+        .map(|plane| plane.height)
+        .reduce(f32::max)
+        .unwrap_or(settings.default_kill_height);
+
+    for player in &players {
+        let position = player.translation();
+
+        let below_kill_plane = position.y < kill_height;
+        let outside_bounds = !bounds.is_empty()
+            && !bounds.iter().any(|(bounds, bounds_transform)| {
+                let local = bounds_transform.affine().inverse().transform_point3(position);
+                local.abs().cmple(bounds.half_extents).all()
+            });
+
+        if below_kill_plane || outside_bounds {
+            info!("Player is out of bounds at {position}, respawning");
+            commands.trigger(RespawnPlayer::default());
+            // A little jolt so the teleport doesn't feel like a cut
+            commands.trigger(CameraShake::new(0.4));
+            return;
+        }
+    }
+}
+
+fn respawn_player(
+    trigger: On<RespawnPlayer>,
+    mut commands: Commands,
+    settings: Res<RespawnSettings>,
+    spawn_points: Query<(Entity, &SpawnPoint, &GlobalTransform), Without<FpsPlayer>>,
+    mut players: Query<(Entity, &mut Transform, &FpsPlayer, Option<&mut PlayerVelocity>)>,
+    mut cameras: Query<&mut Transform, (Without<FpsPlayer>, Without<SpawnPoint>)>,
+) {
+    for (entity, mut player_transform, player, velocity) in &mut players {
+        let current = player_transform.translation;
+
+        let requested = trigger.spawn_point.and_then(|entity| spawn_points.get(entity).ok());
+        let designated = || spawn_points.iter().find(|(_, spawn_point, _)| spawn_point.designated);
+        let nearest = || {
+            spawn_points.iter().min_by(|(_, _, a), (_, _, b)| {
+                a.translation()
+                    .distance_squared(current)
+                    .total_cmp(&b.translation().distance_squared(current))
+            })
+        };
+
+        let (position, yaw) = match requested.or_else(designated).or_else(nearest) {
+            Some((_, _, spawn_transform)) => {
+                let (_, rotation, translation) = spawn_transform.to_scale_rotation_translation();
+                let (yaw, _, _) = rotation.to_euler(EulerRot::YXZ);
+                (translation, yaw)
+            }
+            None => (settings.fallback_spawn, 0.0),
+        };
+
+        // Reset the look direction too, so we face
+        // the way the spawn point faces with no pitch.
+        player_transform.translation = position;
+        player_transform.rotation = Quat::from_rotation_y(yaw);
+        if let Some(mut camera) = player.camera_entity().and_then(|e| cameras.get_mut(e).ok()) {
+            camera.rotation = Quat::IDENTITY;
+        }
+
+        if let Some(mut velocity) = velocity {
+            velocity.0 = Vec3::ZERO;
+        }
+        commands.entity(entity).insert(Teleported);
+    }
+}
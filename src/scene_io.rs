@@ -0,0 +1,179 @@
+use std::{any::TypeId, fs, path::PathBuf};
+
+use bevy::{
+    ecs::entity::EntityHashMap,
+    reflect::GetTypeRegistration,
+    prelude::*,
+    scene::{DynamicSceneBuilder, SceneFilter, serde::SceneDeserializer},
+};
+use serde::de::DeserializeSeed;
+
+use crate::player::FpsPlayer;
+
+/*
+    Saving and loading follows the same idea as FpsPlayer.
+
+    We don't save every entity in the world. Cameras, meshes,
+    materials and ui would not serialize in a useful way.
+    Instead we only save entities marked with Saveable, and only
+    the "spawn information" components on them (Name, Transform,
+    FpsPlayer, SpawnedObject, ...).
+
+    When the scene is loaded back, the observers for those
+    components rebuild the rest (cameras, meshes, scene roots).
+
+    Other plugins can add their own components to the save
+    file with app.register_saveable::<MyComponent>().
+*/
+
+pub struct SceneIoPlugin;
+
+impl Plugin for SceneIoPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SceneSaveSettings::default());
+        app.init_resource::<SaveableComponents>();
+
+        app.register_saveable::<Saveable>();
+        app.register_saveable::<Name>();
+        app.register_saveable::<Transform>();
+        app.register_saveable::<FpsPlayer>();
+
+        app.add_observer(on_save_scene);
+        app.add_observer(on_load_scene);
+    }
+}
+
+// Where the scene file lives on disk.
+// This is a plain file path, not an asset path, since
+// we write to it at runtime.
+#[derive(Resource, Debug, Clone, Reflect)]
+pub struct SceneSaveSettings {
+    pub path: PathBuf,
+}
+
+impl Default for SceneSaveSettings {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("saves/scene.scn.ron"),
+        }
+    }
+}
+
+// Marker for entities that should end up in the save file.
+#[derive(Component, Reflect, Default, Clone)]
+#[reflect(Component, Default)]
+pub struct Saveable;
+
+// The list of component types that are written to the save file.
+#[derive(Resource, Default)]
+pub struct SaveableComponents(Vec<TypeId>);
+
+impl SaveableComponents {
+    pub(crate) fn filter(&self) -> SceneFilter {
+        self.0
+            .iter()
+            .fold(SceneFilter::deny_all(), |filter, type_id| filter.allow_by_id(*type_id))
+    }
+}
+
+pub trait SaveableAppExt {
+    // Registers the type for reflection and adds it to the save file
+    fn register_saveable<T: Component + GetTypeRegistration>(&mut self) -> &mut Self;
+}
+
+impl SaveableAppExt for App {
+    fn register_saveable<T: Component + GetTypeRegistration>(&mut self) -> &mut Self {
+        self.register_type::<T>();
+        self.world_mut()
+            .get_resource_or_init::<SaveableComponents>()
+            .0
+            .push(TypeId::of::<T>());
+        self
+    }
+}
+
+// Trigger these from anywhere with commands.trigger(SaveScene)
+#[derive(Event, Debug, Clone, Copy)]
+pub struct SaveScene;
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct LoadScene;
+
+fn on_save_scene(_trigger: On<SaveScene>, mut commands: Commands) {
+    // Building a DynamicScene needs the whole world,
+    // so we queue a command that gets exclusive access.
+    commands.queue(|world: &mut World| {
+        if let Err(err) = save_scene(world) {
+            error!("Failed to save scene: {err}");
+        }
+    });
+}
+
+fn on_load_scene(_trigger: On<LoadScene>, mut commands: Commands) {
+    commands.queue(|world: &mut World| {
+        if let Err(err) = load_scene(world) {
+            error!("Failed to load scene: {err}");
+        }
+    });
+}
+
+fn save_scene(world: &mut World) -> Result<(), String> {
+    let path = world.resource::<SceneSaveSettings>().path.clone();
+
+    let entities: Vec<Entity> = world
+        .query_filtered::<Entity, With<Saveable>>()
+        .iter(world)
+        .collect();
+
+    let filter = world.resource::<SaveableComponents>().filter();
+    let scene = DynamicSceneBuilder::from_world(world)
+        .with_component_filter(filter)
+        .deny_all_resources()
+        .extract_entities(entities.into_iter())
+        .build();
+
+    let type_registry = world.resource::<AppTypeRegistry>().read();
+    let serialized = scene.serialize(&type_registry).map_err(|err| err.to_string())?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+    }
+    fs::write(&path, serialized).map_err(|err| err.to_string())?;
+
+    info!("Saved {} entities to {}", scene.entities.len(), path.display());
+    Ok(())
+}
+
+fn load_scene(world: &mut World) -> Result<(), String> {
+    let path = world.resource::<SceneSaveSettings>().path.clone();
+    let contents = fs::read_to_string(&path).map_err(|err| format!("{}: {err}", path.display()))?;
+
+    let scene = {
+        let type_registry = world.resource::<AppTypeRegistry>().read();
+        let mut deserializer =
+            bevy::scene::ron::de::Deserializer::from_str(&contents).map_err(|err| err.to_string())?;
+        SceneDeserializer { type_registry: &type_registry }
+            .deserialize(&mut deserializer)
+            .map_err(|err| err.to_string())?
+    };
+
+    // Remove what we saved last time before writing the scene back.
+    // Despawn is recursive so cameras and models go with their parents.
+    let old: Vec<Entity> = world
+        .query_filtered::<Entity, With<Saveable>>()
+        .iter(world)
+        .collect();
+    for entity in old {
+        if let Ok(entity) = world.get_entity_mut(entity) {
+            entity.despawn();
+        }
+    }
+
+    let mut entity_map = EntityHashMap::default();
+    scene
+        .write_to_world(world, &mut entity_map)
+        .map_err(|err| err.to_string())?;
+
+    info!("Loaded {} entities from {}", entity_map.len(), path.display());
+    Ok(())
+}
@@ -0,0 +1,763 @@
+use std::{
+    any::TypeId,
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadState, io::Reader},
+    prelude::*,
+    reflect::{DynamicEnum, DynamicVariant, PartialReflect, ReflectKind, ReflectMut, ReflectRef, TypeInfo, TypeRegistration, TypeRegistry, VariantInfo, VariantType},
+};
+use rhai::{AST, CallFnOptions, Dynamic, Engine, EvalAltResult, FLOAT, INT, Map, Scope};
+use serde::Deserialize;
+
+use crate::{
+    console::ConsoleLog,
+    interaction::Interact,
+    inspector::{is_choice, is_game_type},
+    scene_io::SaveableAppExt,
+    world::mechanisms::{Activate, TriggerEnter, TriggerExit},
+};
+
+/*
+    Small bits of gameplay logic can be written as Rhai scripts
+    (https://rhai.rs) in assets/scripts/, so a level designer
+    doesn't have to rebuild the game to try something.
+
+    Add a Script component with the path of the script to any
+    entity, in the editor or a level file:
+
+        Switch(position: (2.5, 1.2, 0.1), targets: [], script: Some("scripts/combination.rhai")),
+
+    The top of the script runs once when it has loaded, so
+    variables declared there keep their values between calls.
+    Then these functions are called if the script has them:
+
+        on_spawn()               after the script has loaded
+        on_interact(player)      F was pressed on it (needs an Interactable)
+        on_trigger_enter(other)  something went into its TriggerVolume
+        on_trigger_exit(other)   something came out
+        on_activate(active)      a switch or trigger volume targets it
+
+    Inside them `this` is a map of the entity's components: the
+    game's own reflected components (Door, Switch, ...), plus
+    Transform and Visibility. Change a field and it's written
+    back to the component after the function returns:
+
+        this.Door.open = !this.Door.open;
+        this.Transform.translation.y += 0.5;
+        this.Transform.rotation = rotate_y(this.Transform.rotation, 90.0);
+        this.Visibility = "Hidden";
+
+    this.entity is the entity's id, and the player and other
+    arguments are ids too. Scripts can also call:
+
+        print(text)              to the console and the log
+        activate(name, active)   send Activate to the entities with that Name
+                                 (next frame, so scripts activating each
+                                 other take turns instead of recursing)
+        despawn(entity)
+        rotate_x/y/z(rotation, degrees)
+
+    Scripts are assets, so editing one while the game runs
+    reloads it (bevy's file_watcher feature). A reloaded script
+    starts over: its top runs again, then on_spawn.
+
+    Mistakes are printed to the console (`) and the log, with
+    the line they are on.
+*/
+
+pub struct ScriptingPlugin;
+
+impl Plugin for ScriptingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<ScriptAsset>();
+        app.init_asset_loader::<ScriptLoader>();
+        app.init_resource::<ScriptEngine>();
+        app.init_resource::<ScriptActivations>();
+        app.register_saveable::<Script>();
+
+        app.add_observer(load_script);
+        app.add_observer(script_on_interact);
+        app.add_observer(script_on_trigger_enter);
+        app.add_observer(script_on_trigger_exit);
+        app.add_observer(script_on_activate);
+
+        app.add_systems(Update, (send_script_activations, restart_reloaded_scripts, start_scripts).chain());
+    }
+}
+
+#[derive(Component, Reflect, Deserialize, Debug, Clone, Default)]
+#[reflect(Component, Default)]
+#[serde(default)]
+pub struct Script {
+    // Relative to assets/, ex: "scripts/combination.rhai"
+    pub path: String,
+}
+
+impl Script {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+// The loaded script and the variables declared at its top
+#[derive(Component)]
+struct ScriptInstance {
+    handle: Handle<ScriptAsset>,
+    scope: Scope<'static>,
+    state: ScriptState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScriptState {
+    // Waiting for the file to load, or reloaded
+    Loading,
+    Running,
+    // Didn't load or compile, it waits until the file changes
+    Failed,
+}
+
+// A .rhai file, compiled when it's loaded
+#[derive(Asset, TypePath)]
+pub struct ScriptAsset {
+    ast: AST,
+}
+
+impl ScriptAsset {
+    fn has_function(&self, name: &str, params: usize) -> bool {
+        self.ast.iter_functions().any(|function| function.name == name && function.params.len() == params)
+    }
+}
+
+#[derive(Default, TypePath)]
+struct ScriptLoader {
+    // Only used to compile, the functions scripts can call
+    // are looked up when they run
+    engine: Engine,
+}
+
+impl AssetLoader for ScriptLoader {
+    type Asset = ScriptAsset;
+    type Settings = ();
+    type Error = BevyError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<ScriptAsset, BevyError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let ast = self.engine.compile(String::from_utf8(bytes)?)?;
+        Ok(ScriptAsset { ast })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["rhai"]
+    }
+}
+
+// Runs the scripts. The functions it gives them can't touch
+// the World, so they add to a list that's applied after the
+// script returns.
+#[derive(Resource)]
+struct ScriptEngine {
+    engine: Engine,
+    actions: Arc<Mutex<Vec<ScriptAction>>>,
+}
+
+enum ScriptAction {
+    Print(String),
+    Activate { target: String, active: bool },
+    Despawn(Entity),
+}
+
+// activate() calls waiting for the next frame. Sending them
+// straight away would run on_activate inside the script that
+// called activate, and two scripts activating each other
+// would never return.
+#[derive(Resource, Default)]
+struct ScriptActivations(Vec<(String, bool)>);
+
+// A script that loops forever stops after this many steps
+// instead of freezing the game
+const MAX_SCRIPT_OPERATIONS: u64 = 1_000_000;
+
+impl Default for ScriptEngine {
+    fn default() -> Self {
+        let actions: Arc<Mutex<Vec<ScriptAction>>> = Arc::default();
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_SCRIPT_OPERATIONS);
+
+        let queue = actions.clone();
+        engine.on_print(move |text| queue.lock().unwrap().push(ScriptAction::Print(text.to_string())));
+        let queue = actions.clone();
+        engine.register_fn("activate", move |target: &str, active: bool| {
+            queue.lock().unwrap().push(ScriptAction::Activate { target: target.to_string(), active });
+        });
+        let queue = actions.clone();
+        engine.register_fn("despawn", move |entity: INT| {
+            if let Some(entity) = script_entity(entity) {
+                queue.lock().unwrap().push(ScriptAction::Despawn(entity));
+            }
+        });
+
+        engine.register_fn("rotate_x", |rotation: Map, degrees: FLOAT| rotate(&rotation, Quat::from_rotation_x(degrees.to_radians() as f32)));
+        engine.register_fn("rotate_y", |rotation: Map, degrees: FLOAT| rotate(&rotation, Quat::from_rotation_y(degrees.to_radians() as f32)));
+        engine.register_fn("rotate_z", |rotation: Map, degrees: FLOAT| rotate(&rotation, Quat::from_rotation_z(degrees.to_radians() as f32)));
+
+        Self { engine, actions }
+    }
+}
+
+// Entities are passed to scripts as numbers
+fn entity_arg(entity: Entity) -> Dynamic {
+    Dynamic::from_int(entity.to_bits() as INT)
+}
+
+fn script_entity(value: INT) -> Option<Entity> {
+    Entity::try_from_bits(value as u64)
+}
+
+// Rotations are maps with x, y, z and w, like the other structs
+fn rotate(rotation: &Map, by: Quat) -> Map {
+    let part = |name: &str| rotation.get(name).and_then(|value| script_float(value).ok()).unwrap_or(0.0) as f32;
+    let rotated = (by * Quat::from_xyzw(part("x"), part("y"), part("z"), part("w")).normalize()).normalize();
+    [("x", rotated.x), ("y", rotated.y), ("z", rotated.z), ("w", rotated.w)]
+        .into_iter()
+        .map(|(name, value)| (name.into(), Dynamic::from_float(value as FLOAT)))
+        .collect()
+}
+
+fn load_script(
+    trigger: On<Add, Script>,
+    mut commands: Commands,
+    scripts: Query<&Script>,
+    asset_server: Res<AssetServer>,
+) {
+    let Ok(script) = scripts.get(trigger.entity) else { return };
+    commands.entity(trigger.entity).insert(ScriptInstance {
+        handle: asset_server.load(script.path.clone()),
+        scope: Scope::new(),
+        state: ScriptState::Loading,
+    });
+}
+
+// The events only queue the call, scripts need the whole World
+fn queue_hook(commands: &mut Commands, entity: Entity, hook: &'static str, args: Vec<Dynamic>) {
+    commands.queue(move |world: &mut World| run_script_hook(world, entity, hook, args));
+}
+
+fn script_on_interact(trigger: On<Interact>, mut commands: Commands, scripts: Query<(), With<ScriptInstance>>) {
+    if scripts.contains(trigger.entity) {
+        queue_hook(&mut commands, trigger.entity, "on_interact", vec![entity_arg(trigger.interactor)]);
+    }
+}
+
+fn script_on_trigger_enter(trigger: On<TriggerEnter>, mut commands: Commands, scripts: Query<(), With<ScriptInstance>>) {
+    if scripts.contains(trigger.entity) {
+        queue_hook(&mut commands, trigger.entity, "on_trigger_enter", vec![entity_arg(trigger.other)]);
+    }
+}
+
+fn script_on_trigger_exit(trigger: On<TriggerExit>, mut commands: Commands, scripts: Query<(), With<ScriptInstance>>) {
+    if scripts.contains(trigger.entity) {
+        queue_hook(&mut commands, trigger.entity, "on_trigger_exit", vec![entity_arg(trigger.other)]);
+    }
+}
+
+fn script_on_activate(trigger: On<Activate>, mut commands: Commands, scripts: Query<(), With<ScriptInstance>>) {
+    if scripts.contains(trigger.entity) {
+        queue_hook(&mut commands, trigger.entity, "on_activate", vec![Dynamic::from_bool(trigger.active)]);
+    }
+}
+
+fn send_script_activations(
+    mut commands: Commands,
+    mut activations: ResMut<ScriptActivations>,
+    names: Query<(Entity, &Name)>,
+) {
+    // Hooks these run can activate more, those wait a frame again
+    for (target, active) in std::mem::take(&mut activations.0) {
+        for (entity, _) in names.iter().filter(|(_, name)| name.as_str() == target) {
+            commands.trigger(Activate { entity, active });
+        }
+    }
+}
+
+// A changed file starts the scripts using it over
+fn restart_reloaded_scripts(
+    mut events: MessageReader<AssetEvent<ScriptAsset>>,
+    mut instances: Query<&mut ScriptInstance>,
+    asset_server: Res<AssetServer>,
+) {
+    for event in events.read() {
+        let AssetEvent::Modified { id } = event else { continue };
+        if let Some(path) = asset_server.get_path(*id) {
+            info!("Reloaded script {path}");
+        }
+        for mut instance in &mut instances {
+            if instance.handle.id() == *id {
+                instance.state = ScriptState::Loading;
+            }
+        }
+    }
+}
+
+fn start_scripts(world: &mut World, instances: &mut QueryState<(Entity, &ScriptInstance)>) {
+    let scripts = world.resource::<Assets<ScriptAsset>>();
+    let asset_server = world.resource::<AssetServer>();
+    let mut loaded = Vec::new();
+    let mut failed = Vec::new();
+    for (entity, instance) in instances.iter(world) {
+        if instance.state != ScriptState::Loading {
+            continue;
+        }
+        if scripts.contains(&instance.handle) {
+            loaded.push(entity);
+        } else if let Some(LoadState::Failed(err)) = asset_server.get_load_state(&instance.handle) {
+            failed.push((entity, err.to_string()));
+        }
+    }
+
+    for (entity, err) in failed {
+        if let Some(mut instance) = world.get_mut::<ScriptInstance>(entity) {
+            instance.state = ScriptState::Failed;
+        }
+        report_script_error(world, entity, err);
+    }
+
+    for entity in loaded {
+        // Fresh variables, then run the top of the script to declare them
+        if let Some(mut instance) = world.get_mut::<ScriptInstance>(entity) {
+            instance.scope = Scope::new();
+            instance.state = ScriptState::Running;
+        }
+        let started = with_script(world, entity, |engine, scope, ast, _| engine.run_ast_with_scope(scope, ast));
+        if started {
+            run_script_hook(world, entity, "on_spawn", Vec::new());
+        }
+    }
+}
+
+fn run_script_hook(world: &mut World, entity: Entity, hook: &str, args: Vec<Dynamic>) {
+    let Some(instance) = world.get::<ScriptInstance>(entity) else { return };
+    if instance.state != ScriptState::Running {
+        return;
+    }
+    let has_hook = world
+        .resource::<Assets<ScriptAsset>>()
+        .get(&instance.handle)
+        .is_some_and(|script| script.has_function(hook, args.len()));
+    if !has_hook {
+        return;
+    }
+
+    with_script(world, entity, |engine, scope, ast, this| {
+        // The top of the script already ran when it started
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(this);
+        engine.call_fn_with_options::<Dynamic>(options, scope, ast, hook, args).map(drop)
+    });
+}
+
+// Runs part of the entity's script with `this` set to its components,
+// writes the components back and applies what the script asked for.
+// Returns false if the script failed.
+fn with_script(
+    world: &mut World,
+    entity: Entity,
+    run: impl FnOnce(&Engine, &mut Scope<'static>, &AST, &mut Dynamic) -> Result<(), Box<EvalAltResult>>,
+) -> bool {
+    let Some(instance) = world.get::<ScriptInstance>(entity) else { return false };
+    let Some(script) = world.resource::<Assets<ScriptAsset>>().get(&instance.handle) else { return false };
+    let ast = script.ast.clone();
+    let Some(mut instance) = world.get_mut::<ScriptInstance>(entity) else { return false };
+    let mut scope = std::mem::take(&mut instance.scope);
+
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+    let mut this = read_components(world, &registry, entity);
+
+    let result = run(&world.resource::<ScriptEngine>().engine, &mut scope, &ast, &mut this);
+
+    if let Some(mut instance) = world.get_mut::<ScriptInstance>(entity) {
+        instance.scope = scope;
+    }
+    let result = result
+        .map_err(|err| err.to_string())
+        .and_then(|()| write_components(world, &registry, entity, &this));
+    apply_script_actions(world);
+
+    match result {
+        Ok(()) => true,
+        Err(err) => {
+            report_script_error(world, entity, err);
+            false
+        }
+    }
+}
+
+fn report_script_error(world: &mut World, entity: Entity, err: String) {
+    let path = world.get::<Script>(entity).map_or("script", |script| script.path.as_str());
+    let message = format!("{path} on {entity}: {err}");
+    error!("{message}");
+    if let Some(mut log) = world.get_resource_mut::<ConsoleLog>() {
+        log.print(message);
+    }
+}
+
+fn apply_script_actions(world: &mut World) {
+    let actions = std::mem::take(&mut *world.resource::<ScriptEngine>().actions.lock().unwrap());
+    for action in actions {
+        match action {
+            ScriptAction::Print(text) => {
+                info!("Script: {text}");
+                if let Some(mut log) = world.get_resource_mut::<ConsoleLog>() {
+                    log.print(text);
+                }
+            }
+            ScriptAction::Activate { target, active } => {
+                world.resource_mut::<ScriptActivations>().0.push((target, active));
+            }
+            ScriptAction::Despawn(entity) => {
+                if let Ok(entity) = world.get_entity_mut(entity) {
+                    entity.despawn();
+                }
+            }
+        }
+    }
+}
+
+// The components a script can see in `this`
+fn script_components<'a>(world: &World, registry: &'a TypeRegistry, entity: Entity) -> Vec<&'a TypeRegistration> {
+    let Ok(components) = world.inspect_entity(entity) else { return Vec::new() };
+    let engine_types = [TypeId::of::<Transform>(), TypeId::of::<Visibility>()];
+    components
+        .filter_map(|info| info.type_id())
+        .filter_map(|type_id| registry.get(type_id))
+        .filter(|registration| registration.data::<ReflectComponent>().is_some())
+        .filter(|registration| is_game_type(registration) || engine_types.contains(&registration.type_id()))
+        .collect()
+}
+
+fn component_name(registration: &TypeRegistration) -> &str {
+    registration.type_info().type_path_table().short_path()
+}
+
+fn read_components(world: &World, registry: &TypeRegistry, entity: Entity) -> Dynamic {
+    let mut this = Map::new();
+    this.insert("entity".into(), entity_arg(entity));
+    let Ok(entity_ref) = world.get_entity(entity) else { return this.into() };
+
+    for registration in script_components(world, registry, entity) {
+        let component = registration
+            .data::<ReflectComponent>()
+            .and_then(|reflect| reflect.reflect(entity_ref));
+        if let Some(value) = component.and_then(|component| to_dynamic(component.as_partial_reflect())) {
+            this.insert(component_name(registration).into(), value);
+        }
+    }
+    this.into()
+}
+
+fn write_components(world: &mut World, registry: &TypeRegistry, entity: Entity, this: &Dynamic) -> Result<(), String> {
+    let this = this.as_map_ref().map_err(|_| "this was replaced with something that isn't a map".to_string())?;
+    let Ok(entity_ref) = world.get_entity(entity) else { return Ok(()) };
+
+    // Apply the script's values to copies first, so a mistake in
+    // one field doesn't leave the entity half written
+    let mut writes = Vec::new();
+    for registration in script_components(world, registry, entity) {
+        let name = component_name(registration);
+        let Some(value) = this.get(name) else { continue };
+        let Some(reflect) = registration.data::<ReflectComponent>() else { continue };
+        let Some(component) = reflect.reflect(entity_ref) else { continue };
+
+        let mut copy = component
+            .reflect_clone()
+            .map_err(|_| format!("this.{name} can't be copied, so scripts can't change it"))?
+            .into_partial_reflect();
+        let changed = apply_dynamic(copy.as_mut(), value).map_err(|err| format!("this.{name}{err}"))?;
+        // Only components the script changed are written and marked as changed
+        if changed {
+            writes.push((reflect, copy));
+        }
+    }
+
+    for (reflect, copy) in writes {
+        let Ok(entity_mut) = world.get_entity_mut(entity) else { return Ok(()) };
+        if let Some(mut component) = reflect.reflect_mut(entity_mut) {
+            component.apply(copy.as_ref());
+        }
+    }
+    Ok(())
+}
+
+// Structs become maps, lists and tuples become arrays, enums like
+// Visibility become their variant's name. Anything else (handles,
+// entities...) is left out.
+fn to_dynamic(value: &dyn PartialReflect) -> Option<Dynamic> {
+    let item = |value: &dyn PartialReflect| to_dynamic(value).unwrap_or(Dynamic::UNIT);
+    match value.reflect_ref() {
+        ReflectRef::Struct(struct_ref) => Some(Dynamic::from_map(
+            struct_ref
+                .iter_fields()
+                .enumerate()
+                .filter_map(|(index, field)| Some((struct_ref.name_at(index)?.into(), to_dynamic(field)?)))
+                .collect(),
+        )),
+        ReflectRef::TupleStruct(tuple_ref) => Some(Dynamic::from_array(tuple_ref.iter_fields().map(item).collect())),
+        ReflectRef::Tuple(tuple_ref) => Some(Dynamic::from_array(tuple_ref.iter_fields().map(item).collect())),
+        ReflectRef::List(list_ref) => Some(Dynamic::from_array(list_ref.iter().map(item).collect())),
+        ReflectRef::Array(array_ref) => Some(Dynamic::from_array(array_ref.iter().map(item).collect())),
+        ReflectRef::Enum(enum_ref) => match enum_ref.variant_type() {
+            VariantType::Unit => Some(enum_ref.variant_name().to_string().into()),
+            // Like DoorMotion::Swing { degrees }, only the current variant's fields
+            VariantType::Struct => Some(Dynamic::from_map(
+                enum_ref
+                    .iter_fields()
+                    .filter_map(|field| Some((field.name()?.into(), to_dynamic(field.value())?)))
+                    .collect(),
+            )),
+            VariantType::Tuple => Some(Dynamic::from_array(enum_ref.iter_fields().map(|field| item(field.value())).collect())),
+        },
+        ReflectRef::Opaque(_) => opaque_to_dynamic(value),
+        _ => None,
+    }
+}
+
+fn opaque_to_dynamic(value: &dyn PartialReflect) -> Option<Dynamic> {
+    macro_rules! integers {
+        ($($ty:ty),*) => {
+            $(if let Some(number) = value.try_downcast_ref::<$ty>() {
+                return INT::try_from(*number).ok().map(Dynamic::from_int);
+            })*
+        };
+    }
+    integers!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+    if let Some(number) = value.try_downcast_ref::<f32>() {
+        return Some(Dynamic::from_float(*number as FLOAT));
+    }
+    if let Some(number) = value.try_downcast_ref::<f64>() {
+        return Some(Dynamic::from_float(*number));
+    }
+    if let Some(flag) = value.try_downcast_ref::<bool>() {
+        return Some(Dynamic::from_bool(*flag));
+    }
+    value.try_downcast_ref::<String>().map(|text| text.clone().into())
+}
+
+// The other way around. Returns whether anything changed, or
+// where the script put a value that doesn't fit.
+fn apply_dynamic(value: &mut dyn PartialReflect, dynamic: &Dynamic) -> Result<bool, String> {
+    // What to_dynamic left out of arrays
+    if dynamic.is_unit() {
+        return Ok(false);
+    }
+
+    match value.reflect_kind() {
+        ReflectKind::Struct => {
+            let fields = dynamic.as_map_ref().map_err(|_| expects("a map", dynamic))?;
+            let ReflectMut::Struct(struct_mut) = value.reflect_mut() else { return Ok(false) };
+            let mut changed = false;
+            for (name, field_value) in fields.iter() {
+                let field = struct_mut.field_mut(name).ok_or_else(|| format!(" has no field '{name}'"))?;
+                changed |= apply_dynamic(field, field_value).map_err(|err| format!(".{name}{err}"))?;
+            }
+            Ok(changed)
+        }
+        ReflectKind::TupleStruct | ReflectKind::Tuple | ReflectKind::List | ReflectKind::Array => {
+            let items = dynamic.as_array_ref().map_err(|_| expects("an array", dynamic))?;
+            let mut changed = false;
+            for (index, item) in items.iter().enumerate() {
+                let field = match value.reflect_mut() {
+                    ReflectMut::TupleStruct(tuple_mut) => tuple_mut.field_mut(index),
+                    ReflectMut::Tuple(tuple_mut) => tuple_mut.field_mut(index),
+                    ReflectMut::List(list_mut) => list_mut.get_mut(index),
+                    ReflectMut::Array(array_mut) => array_mut.get_mut(index),
+                    _ => None,
+                };
+                let field = field.ok_or_else(|| format!(" has no item [{index}]"))?;
+                changed |= apply_dynamic(field, item).map_err(|err| format!("[{index}]{err}"))?;
+            }
+            Ok(changed)
+        }
+        ReflectKind::Enum => apply_enum(value, dynamic),
+        ReflectKind::Opaque => apply_opaque(value, dynamic),
+        _ => Ok(false),
+    }
+}
+
+fn apply_enum(value: &mut dyn PartialReflect, dynamic: &Dynamic) -> Result<bool, String> {
+    // A name picks a unit variant, like this.Visibility = "Hidden"
+    if let Ok(name) = dynamic.as_immutable_string_ref() {
+        let ReflectRef::Enum(enum_ref) = value.reflect_ref() else { return Ok(false) };
+        if enum_ref.variant_name() == name.as_str() {
+            return Ok(false);
+        }
+        let Some(TypeInfo::Enum(info)) = value.get_represented_type_info() else { return Ok(false) };
+        let units: Vec<&str> = info
+            .iter()
+            .filter(|variant| matches!(variant, VariantInfo::Unit(_)))
+            .map(|variant| variant.name())
+            .collect();
+        let variant = units
+            .iter()
+            .find(|variant| variant.eq_ignore_ascii_case(name.as_str()))
+            .ok_or_else(|| format!(": expects one of {}", units.join(", ")))?;
+        value.apply(&DynamicEnum::new(*variant, DynamicVariant::Unit));
+        return Ok(true);
+    }
+    if is_choice(value) {
+        return Err(expects("a variant name", dynamic));
+    }
+
+    // Otherwise the fields of the variant it already is
+    let mut changed = false;
+    if let Ok(fields) = dynamic.as_map_ref() {
+        for (name, field_value) in fields.iter() {
+            let ReflectMut::Enum(enum_mut) = value.reflect_mut() else { return Ok(false) };
+            let field = enum_mut.field_mut(name).ok_or_else(|| format!(" has no field '{name}'"))?;
+            changed |= apply_dynamic(field, field_value).map_err(|err| format!(".{name}{err}"))?;
+        }
+    } else if let Ok(items) = dynamic.as_array_ref() {
+        for (index, item) in items.iter().enumerate() {
+            let ReflectMut::Enum(enum_mut) = value.reflect_mut() else { return Ok(false) };
+            let field = enum_mut.field_at_mut(index).ok_or_else(|| format!(" has no item [{index}]"))?;
+            changed |= apply_dynamic(field, item).map_err(|err| format!("[{index}]{err}"))?;
+        }
+    } else {
+        return Err(expects("a variant name or its fields", dynamic));
+    }
+    Ok(changed)
+}
+
+fn apply_opaque(value: &mut dyn PartialReflect, dynamic: &Dynamic) -> Result<bool, String> {
+    // Sets the value if it's one of these types and returns whether it changed
+    macro_rules! set {
+        ($ty:ty, $new:expr) => {
+            if let Some(current) = value.try_downcast_mut::<$ty>() {
+                let new: $ty = $new;
+                let changed = *current != new;
+                *current = new;
+                return Ok(changed);
+            }
+        };
+    }
+    macro_rules! integers {
+        ($($ty:ty),*) => {
+            $(set!($ty, {
+                let number = dynamic.as_int().map_err(|_| expects("a whole number", dynamic))?;
+                <$ty>::try_from(number).map_err(|_| format!(": {number} doesn't fit in a {}", stringify!($ty)))?
+            });)*
+        };
+    }
+
+    set!(f32, {
+        let number = script_float(dynamic)?;
+        let narrowed = number as f32;
+        if !narrowed.is_finite() {
+            return Err(format!(": {number} doesn't fit in a f32"));
+        }
+        narrowed
+    });
+    set!(f64, script_float(dynamic)?);
+    integers!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+    set!(bool, dynamic.as_bool().map_err(|_| expects("true or false", dynamic))?);
+    set!(String, dynamic.clone().into_string().map_err(|_| expects("a string", dynamic))?);
+    Ok(false)
+}
+
+// Whole numbers are fine where a float goes, `y = 2` instead of `y = 2.0`.
+// inf and nan aren't, a script dividing by zero shouldn't break the transform.
+fn script_float(dynamic: &Dynamic) -> Result<FLOAT, String> {
+    let number = dynamic
+        .as_float()
+        .or_else(|_| dynamic.as_int().map(|number| number as FLOAT))
+        .map_err(|_| expects("a number", dynamic))?;
+    if !number.is_finite() {
+        return Err(format!(": expects a finite number, got {number}"));
+    }
+    Ok(number)
+}
+
+fn expects(what: &str, dynamic: &Dynamic) -> String {
+    format!(": expects {what}, got {}", dynamic.type_name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Component, Reflect, Default)]
+    #[reflect(Component)]
+    struct Lamp {
+        on: bool,
+        brightness: f32,
+    }
+
+    fn lamp_world() -> (World, TypeRegistry, Entity) {
+        let mut world = World::new();
+        let entity = world.spawn((Lamp { on: false, brightness: 1.0 }, Transform::default())).id();
+        let mut registry = TypeRegistry::default();
+        registry.register::<Lamp>();
+        registry.register::<Transform>();
+        (world, registry, entity)
+    }
+
+    // `this` as the script left it. Rhai only allows `this` in
+    // functions, so the statements change `components` instead.
+    fn run_on_this(world: &World, registry: &TypeRegistry, entity: Entity, statements: &str) -> Dynamic {
+        let mut scope = Scope::new();
+        scope.push("components", read_components(world, registry, entity));
+        Engine::new().run_with_scope(&mut scope, statements).unwrap();
+        scope.get_value("components").unwrap()
+    }
+
+    #[test]
+    fn write_components_writes_and_marks_changes() {
+        let (mut world, registry, entity) = lamp_world();
+        world.clear_trackers();
+        let this = run_on_this(&world, &registry, entity, "components.Lamp.on = true; components.Lamp.brightness = 2;");
+
+        assert_eq!(write_components(&mut world, &registry, entity, &this), Ok(()));
+        let lamp = world.entity(entity).get_ref::<Lamp>().unwrap();
+        assert!(lamp.on);
+        assert_eq!(lamp.brightness, 2.0);
+        assert!(lamp.is_changed());
+        assert!(!world.entity(entity).get_ref::<Transform>().unwrap().is_changed());
+    }
+
+    #[test]
+    fn write_components_writes_nothing_when_a_field_is_wrong() {
+        let (mut world, registry, entity) = lamp_world();
+        world.clear_trackers();
+        let this = run_on_this(
+            &world,
+            &registry,
+            entity,
+            "components.Transform.translation.y = 3.0; components.Lamp.on = true; components.Lamp.brightness = \"bright\";",
+        );
+
+        let err = write_components(&mut world, &registry, entity, &this).unwrap_err();
+        assert!(err.starts_with("this.Lamp.brightness"));
+        let lamp = world.entity(entity).get_ref::<Lamp>().unwrap();
+        assert!(!lamp.on);
+        assert!(!lamp.is_changed());
+        assert_eq!(world.entity(entity).get::<Transform>().unwrap().translation.y, 0.0);
+    }
+
+    #[test]
+    fn script_floats_must_be_finite() {
+        assert!(script_float(&Dynamic::from_float(FLOAT::INFINITY)).is_err());
+        assert!(script_float(&Dynamic::from_float(FLOAT::NAN)).is_err());
+        assert_eq!(script_float(&Dynamic::from_int(2)), Ok(2.0));
+
+        let mut brightness = 1.0f32;
+        assert!(apply_opaque(&mut brightness, &Dynamic::from_float(1e300)).is_err());
+        assert_eq!(brightness, 1.0);
+    }
+}
@@ -0,0 +1,260 @@
+use bevy::{audio::{AudioSinkPlayback, Volume}, prelude::*};
+
+use crate::{
+    keyboard_input::{update_keyboard_movement, SPRINT_SPEED},
+    pause_menu::PauseState,
+    player::{FpsPlayer, PlayerVelocity},
+};
+
+/*
+    All sound in the game is spatial. The player camera has
+    a SpatialListener (added in player.rs), so sounds get
+    quieter with distance and pan between the left and right ear.
+
+    There are three kinds of sounds for now:
+    - Footsteps, played every few meters the player walks
+    - One shot effects, anything can play one with
+      commands.trigger(PlaySound::at("sounds/spawn.ogg", position))
+    - Ambient emitters, looping sounds placed in level files
+      or in the editor with the AmbientEmitter component
+
+    Every sound belongs to a SoundCategory, and AudioSettings
+    holds a volume for each category plus a master volume.
+    The settings page of the pause menu changes them.
+
+    The sound files live in assets/sounds/. Bevy plays .ogg
+    files out of the box. They aren't in the repo, add your own
+    footstep.ogg and spawn.ogg there. Without them the game is
+    just quiet: a sound that fails to load is skipped, and
+    ones already waiting for it are despawned.
+*/
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AudioSettings>();
+        app.register_type::<AudioSettings>();
+        app.register_type::<AmbientEmitter>();
+        app.register_type::<GameSound>();
+
+        app.add_observer(play_sound);
+        app.add_observer(setup_ambient_emitter);
+        app.add_systems(Update, (
+            play_footsteps
+                .after(update_keyboard_movement)
+                .run_if(in_state(PauseState::Running)),
+            apply_audio_volumes,
+            despawn_failed_sounds,
+        ));
+    }
+}
+
+pub const FOOTSTEP_SOUND: &str = "sounds/footstep.ogg";
+pub const SPAWN_SOUND: &str = "sounds/spawn.ogg";
+
+// Horizontal meters walked between two footsteps.
+// Head bob uses it too so the bob lines up with the steps.
+pub const STRIDE_LENGTH: f32 = 2.5;
+// Slower than this (m/s) and we're standing still
+const MIN_FOOTSTEP_SPEED: f32 = 0.5;
+// Sprinting footsteps get louder up to this speed (m/s)
+const LOUDEST_FOOTSTEP_SPEED: f32 = SPRINT_SPEED;
+
+// Volumes go from 0 (muted) to 1 (full volume)
+#[derive(Resource, Debug, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct AudioSettings {
+    pub master: f32,
+    pub effects: f32,
+    pub ambient: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master: 1.0,
+            effects: 1.0,
+            ambient: 0.7,
+        }
+    }
+}
+
+impl AudioSettings {
+    // How loud a sound in this category should play
+    pub fn volume(&self, category: SoundCategory) -> f32 {
+        let category_volume = match category {
+            SoundCategory::Effects => self.effects,
+            SoundCategory::Ambient => self.ambient,
+        };
+        self.master * category_volume
+    }
+}
+
+#[derive(Reflect, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundCategory {
+    #[default]
+    Effects,
+    Ambient,
+}
+
+// Added to every sound we play so the volume
+// can follow AudioSettings while it's playing.
+// volume is the sound's own volume before the settings are applied.
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component)]
+pub struct GameSound {
+    pub category: SoundCategory,
+    pub volume: f32,
+}
+
+// A looping sound at this entity's Transform
+#[derive(Component, Reflect, Debug, Clone)]
+#[reflect(Component, Default)]
+pub struct AmbientEmitter {
+    pub path: String,
+    pub volume: f32,
+}
+
+impl Default for AmbientEmitter {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            volume: 1.0,
+        }
+    }
+}
+
+// Plays a sound effect once at a position in the world
+#[derive(Event, Debug, Clone)]
+pub struct PlaySound {
+    pub path: String,
+    pub position: Vec3,
+    pub volume: f32,
+}
+
+impl PlaySound {
+    pub fn at(path: impl Into<String>, position: Vec3) -> Self {
+        Self {
+            path: path.into(),
+            position,
+            volume: 1.0,
+        }
+    }
+
+    pub fn with_volume(mut self, volume: f32) -> Self {
+        self.volume = volume;
+        self
+    }
+}
+
+fn play_sound(
+    trigger: On<PlaySound>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<AudioSettings>,
+) {
+    let sound: Handle<AudioSource> = asset_server.load(trigger.path.clone());
+    // The asset server already logged why
+    if asset_server.load_state(&sound).is_failed() {
+        return;
+    }
+    let volume = settings.volume(SoundCategory::Effects) * trigger.volume;
+
+    commands.spawn((
+        Name::new("Sound"),
+        AudioPlayer(sound),
+        // DESPAWN removes the entity once the sound is done
+        PlaybackSettings::DESPAWN
+            .with_spatial(true)
+            .with_volume(Volume::Linear(volume)),
+        GameSound { category: SoundCategory::Effects, volume: trigger.volume },
+        Transform::from_translation(trigger.position),
+    ));
+}
+
+// One shot sounds only despawn when they finish playing,
+// which a sound that failed to load never does
+fn despawn_failed_sounds(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    sounds: Query<(Entity, &AudioPlayer, &GameSound)>,
+) {
+    for (entity, player, sound) in &sounds {
+        if sound.category == SoundCategory::Effects && asset_server.load_state(&player.0).is_failed() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn setup_ambient_emitter(
+    trigger: On<Add, AmbientEmitter>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<AudioSettings>,
+    emitters: Query<&AmbientEmitter>,
+) {
+    let Ok(emitter) = emitters.get(trigger.entity) else { return };
+
+    let sound: Handle<AudioSource> = asset_server.load(emitter.path.clone());
+    let volume = settings.volume(SoundCategory::Ambient) * emitter.volume;
+
+    commands.entity(trigger.entity).insert((
+        AudioPlayer(sound),
+        PlaybackSettings::LOOP
+            .with_spatial(true)
+            .with_volume(Volume::Linear(volume)),
+        GameSound { category: SoundCategory::Ambient, volume: emitter.volume },
+    )).insert_if_new((
+        Name::new("Ambient Sound"),
+        Transform::default(),
+    ));
+}
+
+// Footsteps follow how far the player has walked, so walking
+// faster plays them more often. Only horizontal movement counts,
+// flying up and down is silent.
+fn play_footsteps(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut distance_walked: Local<f32>,
+    players: Query<(&GlobalTransform, &PlayerVelocity), With<FpsPlayer>>,
+) {
+    for (transform, velocity) in &players {
+        let horizontal_speed = velocity.0.with_y(0.0).length();
+
+        if horizontal_speed < MIN_FOOTSTEP_SPEED {
+            // The first step after standing still comes quickly
+            *distance_walked = STRIDE_LENGTH * 0.75;
+            continue;
+        }
+
+        *distance_walked += horizontal_speed * time.delta_secs();
+        if *distance_walked < STRIDE_LENGTH {
+            continue;
+        }
+        *distance_walked = 0.0;
+
+        let loudness = (horizontal_speed / LOUDEST_FOOTSTEP_SPEED).clamp(0.4, 1.0);
+        // The player root is at the feet
+        commands.trigger(PlaySound::at(FOOTSTEP_SOUND, transform.translation()).with_volume(loudness));
+    }
+}
+
+// Sounds that are already playing follow volume changes too
+fn apply_audio_volumes(
+    settings: Res<AudioSettings>,
+    mut spatial_sinks: Query<(&GameSound, &mut SpatialAudioSink)>,
+    mut sinks: Query<(&GameSound, &mut AudioSink)>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    for (sound, mut sink) in &mut spatial_sinks {
+        sink.set_volume(Volume::Linear(settings.volume(sound.category) * sound.volume));
+    }
+    for (sound, mut sink) in &mut sinks {
+        sink.set_volume(Volume::Linear(settings.volume(sound.category) * sound.volume));
+    }
+}
@@ -0,0 +1,298 @@
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::{
+    input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll, MouseScrollUnit},
+    prelude::*,
+};
+
+use crate::{
+    console::{ConsoleAppExt, ConsoleArgs, ConsoleResult},
+    input_context::gameplay_input,
+    mouse_input::{MouseLookSettings, MouseTabFocus},
+    pause_menu::PauseState,
+    player::{FpsPlayer, PlayerVelocity},
+};
+
+/*
+    Spectator (noclip) mode detaches a free camera from the
+    player so you can fly around and look at the scene.
+
+    N       switch between the player and the spectator camera
+    WASD    fly where the camera looks
+    Space/X fly up and down
+    Ctrl    fly faster
+    Scroll  change the fly speed
+
+    The player body stays exactly where it was. CameraMode is
+    a state like PauseState, and the systems that move or act
+    as the player only run in CameraMode::Player with
+    .run_if(in_state(CameraMode::Player)).
+
+    The spectator camera flies through everything. When we
+    switch back it's despawned and the player camera is
+    turned back on.
+*/
+
+pub struct SpectatorPlugin;
+
+impl Plugin for SpectatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<CameraMode>();
+        app.insert_resource(SpectatorSettings::default());
+        app.register_type::<SpectatorSettings>();
+
+        app.add_systems(Update, toggle_spectator.run_if(in_state(PauseState::Running)).run_if(gameplay_input));
+        app.add_systems(OnEnter(CameraMode::Spectator), enter_spectator);
+        app.add_systems(OnExit(CameraMode::Spectator), exit_spectator);
+        app.add_console_command("noclip", "noclip  switch to the spectator camera and back (same as N)", noclip_command);
+        app.add_systems(Update, (
+            spectator_fly_speed,
+            spectator_look,
+            spectator_fly,
+            update_spectator_hud,
+        ).chain().run_if(in_state(CameraMode::Spectator).and(in_state(PauseState::Running)).and(gameplay_input)));
+    }
+}
+
+#[derive(States, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CameraMode {
+    #[default]
+    Player,
+    Spectator,
+}
+
+impl CameraMode {
+    fn toggled(self) -> Self {
+        match self {
+            CameraMode::Player => CameraMode::Spectator,
+            CameraMode::Spectator => CameraMode::Player,
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct SpectatorSettings {
+    pub toggle_key: KeyCode,
+    // Meters per second, the scroll wheel changes it
+    pub fly_speed: f32,
+    pub min_fly_speed: f32,
+    pub max_fly_speed: f32,
+    // Ctrl multiplies the fly speed by this
+    pub fast_multiplier: f32,
+}
+
+impl Default for SpectatorSettings {
+    fn default() -> Self {
+        Self {
+            toggle_key: KeyCode::KeyN,
+            fly_speed: 10.0,
+            min_fly_speed: 0.5,
+            max_fly_speed: 200.0,
+            fast_multiplier: 4.0,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct SpectatorCamera;
+
+#[derive(Component)]
+struct SpectatorHud;
+
+// Every scroll notch changes the speed by this factor
+const SCROLL_SPEED_STEP: f32 = 1.2;
+// Touchpads scroll in pixels, about this many make one notch
+const PIXELS_PER_SCROLL_LINE: f32 = 40.0;
+const PITCH_LIMIT: f32 = FRAC_PI_2 - 0.01;
+
+fn toggle_spectator(
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<SpectatorSettings>,
+    mode: Res<State<CameraMode>>,
+    mut next_mode: ResMut<NextState<CameraMode>>,
+) {
+    if !keys.just_pressed(settings.toggle_key) {
+        return;
+    }
+    next_mode.set(mode.get().toggled());
+}
+
+fn noclip_command(
+    In(_): In<ConsoleArgs>,
+    mode: Res<State<CameraMode>>,
+    mut next_mode: ResMut<NextState<CameraMode>>,
+) -> ConsoleResult {
+    let next = mode.get().toggled();
+    next_mode.set(next);
+    Ok(match next {
+        CameraMode::Spectator => "noclip on".to_string(),
+        CameraMode::Player => "noclip off".to_string(),
+    })
+}
+
+fn enter_spectator(
+    mut commands: Commands,
+    mut players: Query<(&FpsPlayer, &mut PlayerVelocity)>,
+    mut cameras: Query<(&mut Camera, &GlobalTransform, Option<&Projection>)>,
+) {
+    // Movement stops while we spectate, so the player
+    // shouldn't keep making footsteps or bobbing
+    for (_, mut velocity) in &mut players {
+        velocity.0 = Vec3::ZERO;
+    }
+
+    // Start where the player is looking from
+    let mut transform = Transform::from_xyz(0.0, 2.0, 5.0);
+    let mut projection = Projection::default();
+
+    if let Some(camera_entity) = players.iter().find_map(|(player, _)| player.camera_entity()) {
+        if let Ok((mut camera, global_transform, player_projection)) = cameras.get_mut(camera_entity) {
+            transform = global_transform.compute_transform().with_scale(Vec3::ONE);
+            if let Some(player_projection) = player_projection {
+                projection = player_projection.clone();
+            }
+            camera.is_active = false;
+            commands.entity(camera_entity).remove::<IsDefaultUiCamera>();
+        }
+    }
+
+    commands.spawn((
+        Name::new("Spectator Camera"),
+        SpectatorCamera,
+        Camera3d::default(),
+        Camera { order: 1, ..default() },
+        // The UI follows us while the player camera is off
+        IsDefaultUiCamera,
+        projection,
+        transform,
+    ));
+
+    commands.spawn((
+        Name::new("Spectator HUD"),
+        SpectatorHud,
+        Text::default(),
+        TextFont { font_size: 20.0, ..default() },
+        Node {
+            position_type: PositionType::Absolute,
+            top: px(12),
+            left: px(12),
+            ..default()
+        },
+    ));
+}
+
+fn exit_spectator(
+    mut commands: Commands,
+    spectators: Query<Entity, Or<(With<SpectatorCamera>, With<SpectatorHud>)>>,
+    players: Query<&FpsPlayer>,
+    mut cameras: Query<&mut Camera>,
+) {
+    for entity in &spectators {
+        commands.entity(entity).despawn();
+    }
+
+    for player in &players {
+        let Some(camera_entity) = player.camera_entity() else { continue };
+        if let Ok(mut camera) = cameras.get_mut(camera_entity) {
+            camera.is_active = true;
+            commands.entity(camera_entity).insert(IsDefaultUiCamera);
+        }
+    }
+}
+
+fn spectator_fly_speed(
+    scroll: Res<AccumulatedMouseScroll>,
+    mut settings: ResMut<SpectatorSettings>,
+) {
+    if scroll.delta.y == 0.0 {
+        return;
+    }
+
+    let notches = match scroll.unit {
+        MouseScrollUnit::Line => scroll.delta.y,
+        MouseScrollUnit::Pixel => scroll.delta.y / PIXELS_PER_SCROLL_LINE,
+    };
+    settings.fly_speed = (settings.fly_speed * SCROLL_SPEED_STEP.powf(notches))
+        .clamp(settings.min_fly_speed, settings.max_fly_speed);
+}
+
+// Same as the player's mouse look, but yaw and pitch
+// both go on the spectator camera
+fn spectator_look(
+    mouse: Res<AccumulatedMouseMotion>,
+    look_settings: Res<MouseLookSettings>,
+    mut spectators: Query<&mut Transform, With<SpectatorCamera>>,
+) {
+    if look_settings.focus != MouseTabFocus::InGame || mouse.delta == Vec2::ZERO {
+        return;
+    }
+
+    for mut transform in &mut spectators {
+        let (yaw, pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+        let yaw = yaw - mouse.delta.x * look_settings.sensitivity.x;
+        let pitch = (pitch - mouse.delta.y * look_settings.sensitivity.y).clamp(-PITCH_LIMIT, PITCH_LIMIT);
+        transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.0);
+    }
+}
+
+fn spectator_fly(
+    keys: Res<ButtonInput<KeyCode>>,
+    time: Res<Time<Real>>,
+    settings: Res<SpectatorSettings>,
+    mut spectators: Query<&mut Transform, With<SpectatorCamera>>,
+) {
+    let mut movement = Vec3::ZERO;
+    if keys.pressed(KeyCode::KeyW) {
+        movement.z += 1.0;
+    }
+    if keys.pressed(KeyCode::KeyS) {
+        movement.z -= 1.0;
+    }
+    if keys.pressed(KeyCode::KeyA) {
+        movement.x -= 1.0;
+    }
+    if keys.pressed(KeyCode::KeyD) {
+        movement.x += 1.0;
+    }
+    if keys.pressed(KeyCode::Space) {
+        movement.y += 1.0;
+    }
+    if keys.pressed(KeyCode::KeyX) {
+        movement.y -= 1.0;
+    }
+    if movement == Vec3::ZERO {
+        return;
+    }
+
+    let mut speed = settings.fly_speed;
+    if keys.pressed(KeyCode::ControlLeft) {
+        speed *= settings.fast_multiplier;
+    }
+
+    // Real time so the spectator still flies at normal
+    // speed when the game's time is slowed down
+    let distance = speed * time.delta_secs();
+    for mut transform in &mut spectators {
+        // Forward follows the pitch too, up and down stay world up and down
+        let direction = transform.forward() * movement.z
+            + transform.right() * movement.x
+            + Vec3::Y * movement.y;
+        transform.translation += direction.normalize_or_zero() * distance;
+    }
+}
+
+fn update_spectator_hud(
+    settings: Res<SpectatorSettings>,
+    mut huds: Query<(&mut Text, Ref<SpectatorHud>)>,
+) {
+    for (mut text, hud) in &mut huds {
+        if !settings.is_changed() && !hud.is_added() {
+            continue;
+        }
+        text.0 = format!(
+            "Spectator: {:.1} m/s (scroll to change, {:?} to go back)",
+            settings.fly_speed, settings.toggle_key,
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use bevy::prelude::*;
+
+use crate::{
+    input_context::gameplay_input,
+    pause_menu::PauseState,
+    player::{FpsPlayer, HEAD_CLEARANCE, PlayerAim, PlayerCollider, PlayerEyeHeight, Stance},
+    spectator::CameraMode,
+};
+
+/*
+    The player can stand, crouch or lie prone.
+
+    Hold Shift to crouch, press C to go prone (and C again to get up).
+
+    The stance lives on FpsPlayer, so it's saved with the player.
+    Each stance has its own eye height (also on FpsPlayer)
+    and its own speed (Stance::speed_multiplier, used by
+    keyboard_input.rs).
+
+    We don't snap the camera to the new height. PlayerEyeHeight
+    moves towards it a little every frame and the camera, head
+    and PlayerCollider follow it.
+
+    Getting up needs room above us. Before standing up we
+    cast a few rays up from around the collider, and if one
+    of them hits something we stay down until there's space.
+*/
+
+pub struct StancePlugin;
+
+impl Plugin for StancePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<PlayerEyeHeight>();
+        app.register_type::<PlayerCollider>();
+
+        app.add_systems(Update, (
+            change_stance.run_if(in_state(CameraMode::Player)).run_if(gameplay_input),
+            move_eyes_to_stance,
+        ).chain().run_if(in_state(PauseState::Running)));
+    }
+}
+
+pub const CROUCH_KEY: KeyCode = KeyCode::ShiftLeft;
+pub const PRONE_KEY: KeyCode = KeyCode::KeyC;
+
+// Meters per second the eyes move between stances
+const EYE_HEIGHT_SPEED: f32 = 3.0;
+// Rays for the headroom check start a little above the feet
+// so they don't hit the ground we're standing on
+const HEADROOM_RAY_START: f32 = 0.1;
+
+fn change_stance(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut wants_prone: Local<bool>,
+    mut players: Query<(&mut FpsPlayer, &GlobalTransform, &PlayerCollider, &PlayerEyeHeight)>,
+    mut aim: PlayerAim,
+) {
+    if keys.just_pressed(PRONE_KEY) {
+        *wants_prone = !*wants_prone;
+    }
+
+    let wanted = if *wants_prone {
+        Stance::Prone
+    } else if keys.pressed(CROUCH_KEY) {
+        Stance::Crouch
+    } else {
+        Stance::Stand
+    };
+
+    for (mut player, transform, collider, eye_height) in &mut players {
+        if player.stance == wanted {
+            continue;
+        }
+
+        // Going down always works. Going up needs headroom.
+        let target_height = player.stance_eye_height(wanted) + HEAD_CLEARANCE;
+        let getting_up = target_height > eye_height.0 + HEAD_CLEARANCE;
+        if getting_up && !has_headroom(&mut aim, transform.translation(), collider.radius, target_height) {
+            continue;
+        }
+
+        player.stance = wanted;
+    }
+}
+
+// Rays from the middle and four sides of the collider,
+// up to where the top of the new stance would be
+fn has_headroom(aim: &mut PlayerAim, feet: Vec3, radius: f32, height: f32) -> bool {
+    let offsets = [
+        Vec3::ZERO,
+        Vec3::X * radius,
+        Vec3::NEG_X * radius,
+        Vec3::Z * radius,
+        Vec3::NEG_Z * radius,
+    ];
+    let ray_length = height - HEADROOM_RAY_START;
+
+    offsets.iter().all(|offset| {
+        let start = feet + *offset + Vec3::Y * HEADROOM_RAY_START;
+        aim.cast_ray(Ray3d::new(start, Dir3::Y), ray_length).is_none()
+    })
+}
+
+fn move_eyes_to_stance(
+    time: Res<Time>,
+    mut players: Query<(&FpsPlayer, &mut PlayerEyeHeight, &mut PlayerCollider)>,
+) {
+    for (player, mut eye_height, mut collider) in &mut players {
+        let target = player.stance_eye_height(player.stance);
+        if eye_height.0 == target {
+            continue;
+        }
+
+        let step = EYE_HEIGHT_SPEED * time.delta_secs();
+        eye_height.0 = if (target - eye_height.0).abs() <= step {
+            target
+        } else {
+            eye_height.0 + step * (target - eye_height.0).signum()
+        };
+        collider.height = eye_height.0 + HEAD_CLEARANCE;
+    }
+}
@@ -0,0 +1,222 @@
+use bevy::{
+    camera::visibility::RenderLayers,
+    light::NotShadowCaster,
+    prelude::*,
+    scene::SceneInstanceReady,
+};
+
+use crate::{
+    inventory::{Inventory, ItemCatalog},
+    player::FpsPlayer,
+};
+
+/*
+    The view model is the item you see in your hands.
+
+    If we just parented the item's model to the camera it would
+    poke through walls whenever you stand close to one, and it
+    would stretch with the sprint FOV kick. So the player camera
+    gets a second camera as a child:
+
+        Player Camera        renders the world (layer 0)
+        ├── View Model Camera    renders only VIEW_MODEL_LAYER, on top,
+        │                        with its own FOV and a tiny near plane
+        └── View Model Anchor    at ViewModel::offset
+            └── the held item's SceneRoot
+
+    The view model camera draws after the player camera and clears
+    only the depth, so the item is always drawn over the world.
+
+    The held item is whatever is selected in the player's
+    Inventory. When the selection changes, the old model is
+    despawned and the new item's scene from the ItemCatalog
+    is spawned under the anchor.
+
+    Lights only light the layers they're on. Directional lights
+    are added to VIEW_MODEL_LAYER so the sun lights the held item,
+    other lights only reach it through the ambient light.
+*/
+
+pub struct ViewModelPlugin;
+
+impl Plugin for ViewModelPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<ViewModel>();
+
+        app.add_observer(setup_view_model);
+        app.add_observer(move_view_model_to_layer);
+        app.add_observer(light_view_model_layer);
+        app.add_systems(Update, (
+            sync_view_model,
+            swap_held_item,
+        ));
+    }
+}
+
+// Meshes on this layer are only seen by the view model camera
+pub const VIEW_MODEL_LAYER: usize = 1;
+
+// Put this on a camera to give it a view model,
+// the FpsPlayer camera gets one by default
+#[derive(Component, Reflect, Clone, Debug)]
+#[reflect(Component, Default)]
+#[require(ViewModelRuntime)]
+pub struct ViewModel {
+    // Where the held item sits relative to the camera
+    pub offset: Transform,
+    // Vertical FOV of the view model camera in degrees.
+    // Lower looks flatter, like holding the item further out.
+    pub fov: f32,
+}
+
+impl Default for ViewModel {
+    fn default() -> Self {
+        Self {
+            offset: Transform::from_xyz(0.35, -0.3, -0.7)
+                .with_rotation(Quat::from_rotation_y(-0.3))
+                .with_scale(Vec3::splat(0.2)),
+            fov: 50.0,
+        }
+    }
+}
+
+#[derive(Component, Default)]
+struct ViewModelRuntime {
+    camera: Option<Entity>,
+    anchor: Option<Entity>,
+    // The item id being shown and its model
+    item: Option<String>,
+    model: Option<Entity>,
+}
+
+// On the held item's SceneRoot
+#[derive(Component)]
+struct ViewModelItem;
+
+fn view_model_projection(view_model: &ViewModel) -> Projection {
+    Projection::Perspective(PerspectiveProjection {
+        fov: view_model.fov.to_radians(),
+        // Close enough that the item is never cut off
+        near: 0.01,
+        ..default()
+    })
+}
+
+fn setup_view_model(
+    trigger: On<Add, ViewModel>,
+    mut commands: Commands,
+    mut view_models: Query<(&ViewModel, &mut ViewModelRuntime)>,
+) {
+    let Ok((view_model, mut runtime)) = view_models.get_mut(trigger.entity) else { return };
+
+    let camera = commands.spawn((
+        Name::new("View Model Camera"),
+        Camera3d::default(),
+        Camera {
+            // After the player camera, keep its picture and only clear depth
+            order: 1,
+            clear_color: ClearColorConfig::None,
+            ..default()
+        },
+        view_model_projection(view_model),
+        RenderLayers::layer(VIEW_MODEL_LAYER),
+        ChildOf(trigger.entity),
+    )).id();
+
+    let anchor = commands.spawn((
+        Name::new("View Model Anchor"),
+        view_model.offset,
+        Visibility::default(),
+        ChildOf(trigger.entity),
+    )).id();
+
+    runtime.camera = Some(camera);
+    runtime.anchor = Some(anchor);
+}
+
+// Keeps the view model camera in step with its parent camera
+fn sync_view_model(
+    view_models: Query<(Ref<ViewModel>, Ref<Camera>, &ViewModelRuntime)>,
+    mut cameras: Query<(&mut Camera, &mut Projection), Without<ViewModel>>,
+    mut anchors: Query<&mut Transform>,
+) {
+    for (view_model, parent_camera, runtime) in &view_models {
+        // The spectator camera and the editor turn the player camera off
+        if parent_camera.is_changed() {
+            if let Some((mut camera, _)) = runtime.camera.and_then(|e| cameras.get_mut(e).ok()) {
+                if camera.is_active != parent_camera.is_active {
+                    camera.is_active = parent_camera.is_active;
+                }
+            }
+        }
+
+        if !view_model.is_changed() {
+            continue;
+        }
+        if let Some((_, mut projection)) = runtime.camera.and_then(|e| cameras.get_mut(e).ok()) {
+            *projection = view_model_projection(&view_model);
+        }
+        if let Some(mut anchor) = runtime.anchor.and_then(|e| anchors.get_mut(e).ok()) {
+            *anchor = view_model.offset;
+        }
+    }
+}
+
+fn swap_held_item(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    catalog: Res<ItemCatalog>,
+    players: Query<(&FpsPlayer, Ref<Inventory>)>,
+    mut view_models: Query<&mut ViewModelRuntime>,
+) {
+    for (player, inventory) in &players {
+        let Some(mut runtime) = player.camera_entity().and_then(|e| view_models.get_mut(e).ok()) else { continue };
+        let Some(anchor) = runtime.anchor else { continue };
+
+        let held = inventory.selected_stack().map(|stack| stack.item.as_str());
+        if runtime.item.as_deref() == held {
+            continue;
+        }
+
+        if let Some(model) = runtime.model.take() {
+            commands.entity(model).despawn();
+        }
+
+        // Items without a model are held with empty hands
+        if let Some(definition) = held.and_then(|item| catalog.get(item)) {
+            let model = commands.spawn((
+                Name::new("Held Item"),
+                ViewModelItem,
+                SceneRoot(asset_server.load(definition.scene_path.clone())),
+                RenderLayers::layer(VIEW_MODEL_LAYER),
+                NotShadowCaster,
+                ChildOf(anchor),
+            )).id();
+            runtime.model = Some(model);
+        }
+        runtime.item = held.map(str::to_string);
+    }
+}
+
+// The scene's meshes are spawned later, on the default layer,
+// so move them all to the view model layer once it's ready
+fn move_view_model_to_layer(
+    trigger: On<SceneInstanceReady>,
+    mut commands: Commands,
+    items: Query<(), With<ViewModelItem>>,
+    children: Query<&Children>,
+) {
+    if items.get(trigger.entity).is_err() {
+        return;
+    }
+    for descendant in children.iter_descendants(trigger.entity) {
+        commands.entity(descendant).insert((
+            RenderLayers::layer(VIEW_MODEL_LAYER),
+            NotShadowCaster,
+        ));
+    }
+}
+
+fn light_view_model_layer(trigger: On<Add, DirectionalLight>, mut commands: Commands) {
+    commands.entity(trigger.entity).insert_if_new(RenderLayers::from_layers(&[0, VIEW_MODEL_LAYER]));
+}
@@ -1,23 +1,18 @@
 use bevy::prelude::*;
 
-pub struct FloorPlugin;
-
-impl Plugin for FloorPlugin {
-    fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_floor);
-    }
-}
-
-fn spawn_floor(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    let floor = (
-        Mesh3d(meshes.add(Circle::new(4.0))),
+// The floor used to be spawned by a startup system.
+// Now levels list their floors, and this builds the
+// components for one so the level loader stays short.
+pub fn floor_bundle(
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    radius: f32,
+    position: Vec3,
+) -> impl Bundle {
+    (
+        Mesh3d(meshes.add(Circle::new(radius))),
         MeshMaterial3d(materials.add(Color::WHITE)),
-        Transform::from_rotation(Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2)),
-    );
-
-    commands.spawn(floor);
-}
\ No newline at end of file
+        Transform::from_translation(position)
+            .with_rotation(Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2)),
+    )
+}
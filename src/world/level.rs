@@ -0,0 +1,500 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadState, io::Reader},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::{
+    light_tool::PlacedLight,
+    respawn::{KillPlane, PlayBounds, RespawnPlayer, SpawnPoint},
+    scripting::Script,
+    sound::AmbientEmitter,
+    world::{
+        floor::floor_bundle,
+        light::{TimeOfDay, WantsShadows, point_light_bundle, sun_bundle},
+        mechanisms::{Door, Switch, TriggerShape, TriggerVolume},
+        object_pool::release_object,
+        objects::SpawnedObject,
+        portal::Portal,
+        terrain::Terrain,
+    },
+};
+
+/*
+    Levels used to be built by startup systems with the
+    sizes and positions written straight into the code.
+
+    Now a level is a small RON file in assets/levels/.
+    It can point at a GLTF scene for the static geometry,
+    and lists the gameplay entities that go with it
+    (spawn points, lights, the sun, floors, terrain, spawnable zones,
+    doors, switches, trigger volumes, portals and the kill plane / play bounds
+    used for respawning).
+
+    Everything the level spawns gets a LevelEntity marker,
+    so unloading is just despawning every LevelEntity
+    (plus the objects and lights placed while playing).
+
+    Load a level from anywhere with:
+    commands.trigger(LoadLevel::new("levels/default.level.ron"));
+
+    Pick the startup level from the command line with:
+    cargo run -- --level levels/my_level.level.ron
+*/
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<LevelDefinition>();
+        app.init_asset_loader::<LevelLoader>();
+
+        app.insert_resource(LevelSettings::from_args());
+        app.init_resource::<CurrentLevel>();
+        app.register_type::<LevelEntity>();
+        app.register_type::<SpawnZone>();
+
+        app.add_observer(load_level);
+        app.add_systems(Startup, load_startup_level);
+        app.add_systems(Update, spawn_loaded_level);
+    }
+}
+
+// The level that is loaded when the game starts
+#[derive(Resource, Debug, Clone)]
+pub struct LevelSettings {
+    pub startup_level: String,
+}
+
+impl LevelSettings {
+    const DEFAULT_LEVEL: &str = "levels/default.level.ron";
+
+    fn from_args() -> Self {
+        let mut args = std::env::args().skip_while(|arg| arg != "--level").skip(1);
+        Self {
+            startup_level: args.next().unwrap_or_else(|| Self::DEFAULT_LEVEL.to_string()),
+        }
+    }
+}
+
+// Tracks the level we asked for and whether it's been spawned yet.
+// Level files load in the background like any other asset.
+#[derive(Resource, Default)]
+pub struct CurrentLevel {
+    pub path: Option<String>,
+    handle: Handle<LevelDefinition>,
+    spawned: bool,
+}
+
+// Added to everything a level spawns
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct LevelEntity;
+
+// Objects can only be spawned inside these boxes.
+// If a level has no zones, objects can be spawned anywhere.
+#[derive(Component, Reflect, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct SpawnZone {
+    pub half_extents: Vec3,
+}
+
+impl SpawnZone {
+    pub fn contains(&self, zone_transform: &GlobalTransform, point: Vec3) -> bool {
+        let local = zone_transform.affine().inverse().transform_point3(point);
+        local.abs().cmple(self.half_extents).all()
+    }
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct LoadLevel {
+    pub path: String,
+}
+
+impl LoadLevel {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+// What a .level.ron file looks like
+#[derive(Asset, TypePath, Deserialize, Debug, Clone)]
+pub struct LevelDefinition {
+    // GLTF scene with the level geometry, ex: "models/level.gltf#Scene0"
+    #[serde(default)]
+    pub scene: Option<String>,
+    // Where the player starts. Respawns go to the nearest
+    // spawn point unless it's marked designated.
+    // Spawn positions are where the player's feet go.
+    #[serde(default)]
+    pub player_spawn: PlayerSpawnDefinition,
+    #[serde(default)]
+    pub entities: Vec<LevelEntityDefinition>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PlayerSpawnDefinition {
+    pub position: Vec3,
+    // Rotation around the y axis in degrees
+    #[serde(default)]
+    pub yaw: f32,
+    #[serde(default)]
+    pub designated: bool,
+}
+
+impl Default for PlayerSpawnDefinition {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            yaw: 0.0,
+            designated: false,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub enum LevelEntityDefinition {
+    Floor {
+        radius: f32,
+        #[serde(default)]
+        position: Vec3,
+    },
+    PointLight {
+        position: Vec3,
+        #[serde(default = "default_light_intensity")]
+        intensity: f32,
+        #[serde(default = "default_light_range")]
+        range: f32,
+        #[serde(default)]
+        shadows: bool,
+    },
+    SpawnZone {
+        center: Vec3,
+        half_extents: Vec3,
+    },
+    SpawnPoint {
+        position: Vec3,
+        #[serde(default)]
+        yaw: f32,
+        #[serde(default)]
+        designated: bool,
+    },
+    KillPlane {
+        height: f32,
+    },
+    PlayBounds {
+        center: Vec3,
+        half_extents: Vec3,
+    },
+    // Streams chunked terrain around the player, see world/terrain.rs
+    Terrain(Terrain),
+    // A sun that follows the time of day, see world/light.rs
+    Sun {
+        #[serde(default = "default_start_hour")]
+        hour: f32,
+        #[serde(default = "default_day_length")]
+        day_length_secs: f32,
+        #[serde(default)]
+        paused: bool,
+    },
+    // Sends Activate to its targets while the player
+    // or a spawned object is inside, see world/mechanisms.rs
+    TriggerVolume {
+        center: Vec3,
+        shape: TriggerShape,
+        #[serde(default)]
+        targets: Vec<String>,
+        // A Rhai script in assets/scripts/, see scripting.rs
+        #[serde(default)]
+        script: Option<String>,
+    },
+    // position is the bottom corner on the hinge side.
+    // Switches and trigger volumes find it by name.
+    Door {
+        name: String,
+        position: Vec3,
+        #[serde(default)]
+        yaw: f32,
+        #[serde(default)]
+        door: Door,
+        #[serde(default)]
+        script: Option<String>,
+    },
+    Switch {
+        position: Vec3,
+        #[serde(default)]
+        yaw: f32,
+        #[serde(default)]
+        on: bool,
+        targets: Vec<String>,
+        #[serde(default)]
+        script: Option<String>,
+    },
+    // position is the bottom centre of the opening, which faces +Z
+    // before the yaw. Links to another portal by name, see world/portal.rs
+    Portal {
+        name: String,
+        position: Vec3,
+        #[serde(default)]
+        yaw: f32,
+        #[serde(default)]
+        portal: Portal,
+    },
+    // A looping spatial sound, see sound.rs
+    AmbientSound {
+        path: String,
+        position: Vec3,
+        #[serde(default = "default_sound_volume")]
+        volume: f32,
+    },
+}
+
+fn default_sound_volume() -> f32 {
+    AmbientEmitter::default().volume
+}
+
+fn default_start_hour() -> f32 {
+    TimeOfDay::default().hour
+}
+
+fn default_day_length() -> f32 {
+    TimeOfDay::default().day_length_secs
+}
+
+// Same values as bevy's PointLight::default()
+fn default_light_intensity() -> f32 {
+    PointLight::default().intensity
+}
+
+fn default_light_range() -> f32 {
+    PointLight::default().range
+}
+
+#[derive(Default, TypePath)]
+struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    type Asset = LevelDefinition;
+    type Settings = ();
+    type Error = BevyError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<LevelDefinition, BevyError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let level = bevy::scene::ron::de::from_bytes(&bytes)?;
+        Ok(level)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+fn load_startup_level(mut commands: Commands, settings: Res<LevelSettings>) {
+    commands.trigger(LoadLevel::new(settings.startup_level.clone()));
+}
+
+fn load_level(
+    trigger: On<LoadLevel>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut current_level: ResMut<CurrentLevel>,
+    level_entities: Query<Entity, With<LevelEntity>>,
+    spawned_objects: Query<Entity, (With<SpawnedObject>, Without<LevelEntity>)>,
+    placed_lights: Query<Entity, (With<PlacedLight>, Without<LevelEntity>)>,
+) {
+    // Unload the previous level first
+    for entity in &level_entities {
+        commands.entity(entity).despawn();
+    }
+    // Objects and lights placed while playing belong to the old level too
+    for entity in &spawned_objects {
+        release_object(&mut commands, entity);
+    }
+    for entity in &placed_lights {
+        commands.entity(entity).despawn();
+    }
+    // The previous level's Sun set these, a level without one
+    // shouldn't keep its sky
+    commands.insert_resource(ClearColor::default());
+    commands.insert_resource(AmbientLight::default());
+
+    info!("Loading level {}", trigger.path);
+    *current_level = CurrentLevel {
+        path: Some(trigger.path.clone()),
+        handle: asset_server.load(trigger.path.clone()),
+        spawned: false,
+    };
+}
+
+// Waits for the level file to finish loading, then spawns it
+fn spawn_loaded_level(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    levels: Res<Assets<LevelDefinition>>,
+    mut current_level: ResMut<CurrentLevel>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut time_of_day: ResMut<TimeOfDay>,
+) {
+    if current_level.spawned || current_level.path.is_none() {
+        return;
+    }
+
+    let Some(level) = levels.get(&current_level.handle) else {
+        if let Some(LoadState::Failed(err)) = asset_server.get_load_state(&current_level.handle) {
+            error!("Failed to load level {:?}: {err}", current_level.path);
+            current_level.spawned = true;
+        }
+        return;
+    };
+    current_level.spawned = true;
+
+    if let Some(scene) = &level.scene {
+        commands.spawn((
+            Name::new("Level Scene"),
+            LevelEntity,
+            SceneRoot(asset_server.load(scene.clone())),
+        ));
+    }
+
+    for definition in &level.entities {
+        match definition.clone() {
+            LevelEntityDefinition::Floor { radius, position } => {
+                commands.spawn((
+                    Name::new("Floor"),
+                    LevelEntity,
+                    floor_bundle(&mut meshes, &mut materials, radius, position),
+                ));
+            }
+            LevelEntityDefinition::PointLight { position, intensity, range, shadows } => {
+                commands.spawn((
+                    Name::new("Point Light"),
+                    LevelEntity,
+                    point_light_bundle(position, intensity, range),
+                )).insert_if(WantsShadows, || shadows);
+            }
+            LevelEntityDefinition::SpawnZone { center, half_extents } => {
+                commands.spawn((
+                    Name::new("Spawn Zone"),
+                    LevelEntity,
+                    SpawnZone { half_extents },
+                    Transform::from_translation(center),
+                ));
+            }
+            LevelEntityDefinition::SpawnPoint { position, yaw, designated } => {
+                commands.spawn(spawn_point_bundle(position, yaw, designated));
+            }
+            LevelEntityDefinition::KillPlane { height } => {
+                commands.spawn((
+                    Name::new("Kill Plane"),
+                    LevelEntity,
+                    KillPlane { height },
+                ));
+            }
+            LevelEntityDefinition::PlayBounds { center, half_extents } => {
+                commands.spawn((
+                    Name::new("Play Bounds"),
+                    LevelEntity,
+                    PlayBounds { half_extents },
+                    Transform::from_translation(center),
+                ));
+            }
+            LevelEntityDefinition::Sun { hour, day_length_secs, paused } => {
+                *time_of_day = TimeOfDay { hour, day_length_secs, paused };
+                commands.spawn((LevelEntity, sun_bundle()));
+            }
+            LevelEntityDefinition::TriggerVolume { center, shape, targets, script } => {
+                commands.spawn((
+                    Name::new("Trigger Volume"),
+                    LevelEntity,
+                    TriggerVolume { shape, targets },
+                    Transform::from_translation(center),
+                )).insert_script(script);
+            }
+            LevelEntityDefinition::Door { name, position, yaw, door, script } => {
+                commands.spawn((
+                    Name::new(name),
+                    LevelEntity,
+                    door,
+                    Transform::from_translation(position)
+                        .with_rotation(Quat::from_rotation_y(yaw.to_radians())),
+                )).insert_script(script);
+            }
+            LevelEntityDefinition::Switch { position, yaw, on, targets, script } => {
+                commands.spawn((
+                    Name::new("Switch"),
+                    LevelEntity,
+                    Switch { on, targets },
+                    Transform::from_translation(position)
+                        .with_rotation(Quat::from_rotation_y(yaw.to_radians())),
+                )).insert_script(script);
+            }
+            LevelEntityDefinition::Portal { name, position, yaw, portal } => {
+                commands.spawn((
+                    Name::new(name),
+                    LevelEntity,
+                    portal,
+                    Transform::from_translation(position)
+                        .with_rotation(Quat::from_rotation_y(yaw.to_radians())),
+                ));
+            }
+            LevelEntityDefinition::AmbientSound { path, position, volume } => {
+                commands.spawn((
+                    Name::new("Ambient Sound"),
+                    LevelEntity,
+                    AmbientEmitter { path, volume },
+                    Transform::from_translation(position),
+                ));
+            }
+            LevelEntityDefinition::Terrain(terrain) => {
+                commands.spawn((
+                    Name::new("Terrain"),
+                    LevelEntity,
+                    terrain,
+                ));
+            }
+        }
+    }
+
+    let spawn = &level.player_spawn;
+    let player_spawn = commands
+        .spawn(spawn_point_bundle(spawn.position, spawn.yaw, spawn.designated))
+        .id();
+
+    // Move the player to the level's spawn point
+    commands.trigger(RespawnPlayer { spawn_point: Some(player_spawn) });
+}
+
+trait InsertScript {
+    fn insert_script(&mut self, script: Option<String>) -> &mut Self;
+}
+
+impl InsertScript for EntityCommands<'_> {
+    // Level entries with a script get a Script component
+    fn insert_script(&mut self, script: Option<String>) -> &mut Self {
+        if let Some(path) = script {
+            self.insert(Script::new(path));
+        }
+        self
+    }
+}
+
+fn spawn_point_bundle(position: Vec3, yaw: f32, designated: bool) -> impl Bundle {
+    let transform = Transform::from_translation(position)
+        .with_rotation(Quat::from_rotation_y(yaw.to_radians()));
+    (
+        Name::new("Spawn Point"),
+        LevelEntity,
+        SpawnPoint { designated },
+        transform,
+        // Respawning uses the GlobalTransform, and the player
+        // is sent here before transforms are propagated
+        GlobalTransform::from(transform),
+    )
+}
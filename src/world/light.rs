@@ -1,25 +1,232 @@
-use bevy::prelude::*;
+use std::f32::consts::PI;
 
-pub struct LightPlugin;
+use bevy::{light::{CascadeShadowConfigBuilder, light_consts::lux}, prelude::*};
 
-impl Plugin for LightPlugin {
+use crate::player::FpsPlayer;
+
+/*
+    The lighting plugin does three jobs:
+
+    1. A Sun (directional light with cascaded shadows) that
+       moves with TimeOfDay. Its angle, colour and brightness,
+       plus the ambient light and sky colour, all follow the hour.
+
+    2. TimeOfDay advances with virtual time, so the pause
+       menu also pauses the day.
+
+    3. Shadows are expensive, so point and spot lights don't
+       turn them on themselves. Lights that want shadows get a
+       WantsShadows marker, and ShadowBudget turns shadows on
+       for only the N lights that matter most to the camera.
+*/
+
+pub struct LightingPlugin;
+
+impl Plugin for LightingPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_light);
+        app.insert_resource(TimeOfDay::default());
+        app.insert_resource(ShadowBudget::default());
+        app.register_type::<TimeOfDay>();
+        app.register_type::<ShadowBudget>();
+        app.register_type::<Sun>();
+        app.register_type::<WantsShadows>();
+
+        app.add_systems(Update, (
+            advance_time_of_day,
+            update_sun,
+            apply_shadow_budget,
+        ).chain());
     }
 }
-fn spawn_light(mut commands: Commands)
-{
-   // In the future we will go over limiting the
-   // amount of lights with shadows, as they can
-   // be expensive to render.
 
-   let light = (
+#[derive(Resource, Debug, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct TimeOfDay {
+    // 0..24, 12 is noon
+    pub hour: f32,
+    // How many real seconds a full day takes
+    pub day_length_secs: f32,
+    pub paused: bool,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            hour: 10.0,
+            day_length_secs: 600.0,
+            paused: false,
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct ShadowBudget {
+    // How many point/spot lights can cast shadows at once
+    pub max_shadowed_lights: usize,
+}
+
+impl Default for ShadowBudget {
+    fn default() -> Self {
+        Self { max_shadowed_lights: 4 }
+    }
+}
+
+// The directional light driven by TimeOfDay
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Sun;
+
+// This point or spot light would like to cast shadows
+// if the shadow budget allows it
+#[derive(Component, Reflect, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct WantsShadows;
+
+// Levels list their lights, this builds one of them.
+// Add WantsShadows next to it if it should cast shadows.
+pub fn point_light_bundle(
+    position: Vec3,
+    intensity: f32,
+    range: f32,
+) -> impl Bundle {
+   // Shadows start off. apply_shadow_budget decides
+   // which of the lights that want them get them.
+   (
       PointLight {
-         shadows_enabled: true,
+         intensity,
+         range,
+         shadows_enabled: false,
          ..default()
       },
-      Transform::from_xyz(4.0,8.0,4.0),
-   );
+      Transform::from_translation(position),
+   )
+}
 
-   commands.spawn(light);
-}
\ No newline at end of file
+pub fn sun_bundle() -> impl Bundle {
+    (
+        Name::new("Sun"),
+        Sun,
+        DirectionalLight {
+            shadows_enabled: true,
+            illuminance: lux::AMBIENT_DAYLIGHT,
+            ..default()
+        },
+        // Cascades keep shadows sharp close to the player
+        // while still covering the terrain far away
+        CascadeShadowConfigBuilder {
+            num_cascades: 4,
+            first_cascade_far_bound: 8.0,
+            maximum_distance: 150.0,
+            ..default()
+        }
+        .build(),
+    )
+}
+
+fn advance_time_of_day(time: Res<Time>, mut time_of_day: ResMut<TimeOfDay>) {
+    if time_of_day.paused || time_of_day.day_length_secs <= 0.0 {
+        return;
+    }
+    let hours = time.delta_secs() / time_of_day.day_length_secs * 24.0;
+    time_of_day.hour = (time_of_day.hour + hours).rem_euclid(24.0);
+}
+
+fn update_sun(
+    time_of_day: Res<TimeOfDay>,
+    mut suns: Query<(&mut Transform, &mut DirectionalLight), With<Sun>>,
+    new_suns: Query<(), Added<Sun>>,
+    mut ambient: ResMut<AmbientLight>,
+    mut clear_color: ResMut<ClearColor>,
+) {
+    if suns.is_empty() || (!time_of_day.is_changed() && new_suns.is_empty()) {
+        return;
+    }
+
+    // 6 is sunrise, 12 noon, 18 sunset. Elevation is -1 at midnight and 1 at noon.
+    let day_angle = (time_of_day.hour - 6.0) / 24.0 * 2.0 * PI;
+    let elevation = day_angle.sin();
+    let daylight = elevation.clamp(0.0, 1.0);
+    // Strongest close to the horizon, for orange sunrises and sunsets
+    let golden = (1.0 - (elevation.abs() * 4.0)).clamp(0.0, 1.0);
+
+    let noon_color = Color::srgb(1.0, 0.98, 0.92);
+    let sunset_color = Color::srgb(1.0, 0.55, 0.3);
+    let sun_color = noon_color.mix(&sunset_color, golden);
+
+    for (mut transform, mut light) in &mut suns {
+        // Rise in the east (+x), set in the west (-x)
+        *transform = Transform::default().looking_to(
+            Vec3::new(-day_angle.cos(), -elevation, 0.3).normalize(),
+            Vec3::Y,
+        );
+        light.color = sun_color;
+        light.illuminance = lux::AMBIENT_DAYLIGHT * daylight.powf(0.5);
+    }
+
+    let night_sky = Color::srgb(0.01, 0.01, 0.03);
+    let day_sky = Color::srgb(0.45, 0.65, 0.95);
+    let sky = night_sky.mix(&day_sky, daylight.powf(0.5)).mix(&sunset_color, golden * 0.4);
+    clear_color.0 = sky;
+
+    ambient.color = sky;
+    ambient.brightness = 40.0 + 400.0 * daylight;
+}
+
+// Score every light that wants shadows by how bright it is
+// at the camera, then give shadows to the best few.
+fn apply_shadow_budget(
+    budget: Res<ShadowBudget>,
+    players: Query<&FpsPlayer>,
+    cameras: Query<&GlobalTransform, With<Camera3d>>,
+    mut point_lights: Query<(Entity, &mut PointLight, &GlobalTransform), With<WantsShadows>>,
+    mut spot_lights: Query<(Entity, &mut SpotLight, &GlobalTransform), With<WantsShadows>>,
+) {
+    let Some(camera) = players
+        .iter()
+        .find_map(|player| player.camera_entity())
+        .and_then(|camera| cameras.get(camera).ok())
+    else {
+        return;
+    };
+    let eye = camera.translation();
+
+    let relevance = |intensity: f32, range: f32, position: Vec3| {
+        let distance = position.distance(eye);
+        if distance > range {
+            0.0
+        } else {
+            intensity / (1.0 + distance * distance)
+        }
+    };
+
+    let mut scored: Vec<(Entity, f32)> = point_lights
+        .iter()
+        .map(|(entity, light, transform)| (entity, relevance(light.intensity, light.range, transform.translation())))
+        .chain(spot_lights.iter().map(|(entity, light, transform)| {
+            (entity, relevance(light.intensity, light.range, transform.translation()))
+        }))
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let shadowed: Vec<Entity> = scored
+        .iter()
+        .filter(|(_, score)| *score > 0.0)
+        .take(budget.max_shadowed_lights)
+        .map(|(entity, _)| *entity)
+        .collect();
+
+    // Only write when it changes, so we don't mark every light as changed each frame
+    for (entity, mut light, _) in &mut point_lights {
+        let enabled = shadowed.contains(&entity);
+        if light.shadows_enabled != enabled {
+            light.shadows_enabled = enabled;
+        }
+    }
+    for (entity, mut light, _) in &mut spot_lights {
+        let enabled = shadowed.contains(&entity);
+        if light.shadows_enabled != enabled {
+            light.shadows_enabled = enabled;
+        }
+    }
+}
@@ -0,0 +1,420 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    interaction::{Interact, Interactable},
+    pause_menu::PauseState,
+    player::{FpsPlayer, PlayerCollider},
+    scene_io::SaveableAppExt,
+    world::objects::SpawnedObject,
+};
+
+/*
+    Mechanisms are the small gameplay pieces levels are built from:
+
+    - TriggerVolume: a box or sphere that notices when the
+      player or a spawned object goes in or out. It triggers
+      TriggerEnter and TriggerExit on itself.
+    - Door: a panel that slides or swings open. F opens it
+      if it's interactable.
+    - Switch: a button on the wall. F flips it.
+
+    Switches and trigger volumes have a list of targets.
+    Targets are entity Names, so they can be written in level
+    files. When a switch flips, or something enters or leaves
+    a trigger volume, each target gets an Activate event.
+    Doors open when activated and close when deactivated.
+
+    Like FpsPlayer, each of these is a single component.
+    Add it to an entity in the editor or a level file and
+    an observer builds the rest (meshes, Interactable, ...).
+*/
+
+pub struct MechanismsPlugin;
+
+impl Plugin for MechanismsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_saveable::<TriggerVolume>();
+        app.register_saveable::<Door>();
+        app.register_saveable::<Switch>();
+
+        app.add_observer(setup_trigger_volume);
+        app.add_observer(setup_door);
+        app.add_observer(setup_switch);
+        app.add_observer(use_door);
+        app.add_observer(use_switch);
+        app.add_observer(activate_door);
+        app.add_observer(log_trigger_enter);
+        app.add_observer(log_trigger_exit);
+
+        app.add_systems(Update, (
+            detect_trigger_volumes,
+            update_switches,
+            animate_doors,
+        ).chain().run_if(in_state(PauseState::Running)));
+    }
+}
+
+#[derive(Component, Reflect, Deserialize, Debug, Clone)]
+#[reflect(Component, Default)]
+#[require(TriggerOccupants)]
+#[serde(default)]
+pub struct TriggerVolume {
+    pub shape: TriggerShape,
+    // Names of the entities to activate while something is inside
+    pub targets: Vec<String>,
+}
+
+impl Default for TriggerVolume {
+    fn default() -> Self {
+        Self {
+            shape: TriggerShape::Box { half_extents: Vec3::ONE },
+            targets: Vec::new(),
+        }
+    }
+}
+
+// The shape is around the entity's Transform, and
+// follows its rotation and scale too
+#[derive(Reflect, Deserialize, Debug, Clone, Copy)]
+pub enum TriggerShape {
+    Box { half_extents: Vec3 },
+    Sphere { radius: f32 },
+}
+
+impl Default for TriggerShape {
+    fn default() -> Self {
+        TriggerShape::Box { half_extents: Vec3::ONE }
+    }
+}
+
+impl TriggerShape {
+    fn contains(&self, transform: &GlobalTransform, point: Vec3) -> bool {
+        let local = transform.affine().inverse().transform_point3(point);
+        match *self {
+            TriggerShape::Box { half_extents } => local.abs().cmple(half_extents).all(),
+            TriggerShape::Sphere { radius } => local.length() <= radius,
+        }
+    }
+}
+
+// What's inside a trigger volume right now
+#[derive(Component, Default)]
+struct TriggerOccupants(Vec<Entity>);
+
+// Triggered on a TriggerVolume when something goes in
+#[derive(EntityEvent, Debug, Clone, Copy)]
+pub struct TriggerEnter {
+    pub entity: Entity,
+    // The player or spawned object that went in
+    pub other: Entity,
+}
+
+// Triggered on a TriggerVolume when something comes out
+#[derive(EntityEvent, Debug, Clone, Copy)]
+pub struct TriggerExit {
+    pub entity: Entity,
+    pub other: Entity,
+}
+
+// Sent to the targets of switches and trigger volumes
+#[derive(EntityEvent, Debug, Clone, Copy)]
+pub struct Activate {
+    pub entity: Entity,
+    pub active: bool,
+}
+
+#[derive(Component, Reflect, Deserialize, Debug, Clone)]
+#[reflect(Component, Default)]
+#[serde(default)]
+pub struct Door {
+    pub open: bool,
+    pub motion: DoorMotion,
+    // Size of the door panel in meters
+    pub size: Vec3,
+    // Seconds it takes to open or close
+    pub open_time: f32,
+    // Locked doors don't open with F, but switches still work
+    pub locked: bool,
+    // Turn off for doors only switches and triggers should open
+    pub interactable: bool,
+}
+
+impl Default for Door {
+    fn default() -> Self {
+        Self {
+            open: false,
+            motion: DoorMotion::default(),
+            size: Vec3::new(1.0, 2.0, 0.1),
+            open_time: 0.6,
+            locked: false,
+            interactable: true,
+        }
+    }
+}
+
+// The door's Transform is its bottom corner on the hinge
+// side. The panel goes along +x from there.
+#[derive(Reflect, Deserialize, Debug, Clone, Copy)]
+pub enum DoorMotion {
+    // Swings around the hinge, positive is counter clockwise seen from above
+    Swing { degrees: f32 },
+    // Moves by offset (in the door's space) when open
+    Slide { offset: Vec3 },
+}
+
+impl Default for DoorMotion {
+    fn default() -> Self {
+        DoorMotion::Swing { degrees: 90.0 }
+    }
+}
+
+// The panel mesh and how far open it is, 0 closed and 1 open
+#[derive(Component)]
+struct DoorRuntime {
+    panel: Entity,
+    progress: f32,
+    // The size the panel mesh was built with
+    size: Vec3,
+}
+
+#[derive(Component, Reflect, Deserialize, Debug, Clone, Default)]
+#[reflect(Component, Default)]
+#[serde(default)]
+pub struct Switch {
+    pub on: bool,
+    // Names of the entities to activate while it's on
+    pub targets: Vec<String>,
+}
+
+#[derive(Component)]
+struct SwitchRuntime {
+    material: Handle<StandardMaterial>,
+}
+
+const SWITCH_SIZE: Vec3 = Vec3::new(0.15, 0.25, 0.08);
+const SWITCH_ON_COLOR: Color = Color::srgb(0.1, 0.8, 0.2);
+const SWITCH_OFF_COLOR: Color = Color::srgb(0.8, 0.1, 0.1);
+
+fn setup_trigger_volume(trigger: On<Add, TriggerVolume>, mut commands: Commands) {
+    commands.entity(trigger.entity).insert_if_new((
+        Name::new("Trigger Volume"),
+        Transform::default(),
+    ));
+}
+
+fn setup_door(
+    trigger: On<Add, Door>,
+    mut commands: Commands,
+    doors: Query<&Door>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let Ok(door) = doors.get(trigger.entity) else { return };
+
+    let panel = commands.spawn((
+        Name::new("Door Panel"),
+        Mesh3d(meshes.add(Cuboid::from_size(door.size))),
+        MeshMaterial3d(materials.add(Color::srgb(0.45, 0.3, 0.2))),
+        door_panel_transform(door, if door.open { 1.0 } else { 0.0 }),
+        ChildOf(trigger.entity),
+    )).id();
+
+    let mut door_entity = commands.entity(trigger.entity);
+    door_entity.insert(DoorRuntime {
+        panel,
+        progress: if door.open { 1.0 } else { 0.0 },
+        size: door.size,
+    }).insert_if_new((
+        Name::new("Door"),
+        Transform::default(),
+        Visibility::default(),
+    ));
+    if door.interactable {
+        door_entity.insert_if_new(Interactable::new(door_verb(door)));
+    }
+}
+
+fn setup_switch(
+    trigger: On<Add, Switch>,
+    mut commands: Commands,
+    switches: Query<&Switch>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let Ok(switch) = switches.get(trigger.entity) else { return };
+
+    let material = materials.add(if switch.on { SWITCH_ON_COLOR } else { SWITCH_OFF_COLOR });
+    commands.spawn((
+        Name::new("Switch Button"),
+        Mesh3d(meshes.add(Cuboid::from_size(SWITCH_SIZE))),
+        MeshMaterial3d(material.clone()),
+        ChildOf(trigger.entity),
+    ));
+
+    commands.entity(trigger.entity).insert(SwitchRuntime { material }).insert_if_new((
+        Name::new("Switch"),
+        Transform::default(),
+        Visibility::default(),
+        Interactable::new("flip the switch"),
+    ));
+}
+
+fn door_verb(door: &Door) -> &'static str {
+    match (door.open, door.locked) {
+        (true, _) => "close",
+        (false, false) => "open",
+        (false, true) => "open (locked)",
+    }
+}
+
+// Where the panel goes when the door is `progress` open
+fn door_panel_transform(door: &Door, progress: f32) -> Transform {
+    let closed = Vec3::new(door.size.x * 0.5, door.size.y * 0.5, 0.0);
+    match door.motion {
+        DoorMotion::Swing { degrees } => {
+            let rotation = Quat::from_rotation_y(degrees.to_radians() * progress);
+            Transform::from_translation(rotation * closed).with_rotation(rotation)
+        }
+        DoorMotion::Slide { offset } => Transform::from_translation(closed + offset * progress),
+    }
+}
+
+fn use_door(trigger: On<Interact>, mut doors: Query<&mut Door>) {
+    let Ok(mut door) = doors.get_mut(trigger.entity) else { return };
+    if door.interactable && !door.locked {
+        door.open = !door.open;
+    }
+}
+
+fn use_switch(trigger: On<Interact>, mut switches: Query<&mut Switch>) {
+    if let Ok(mut switch) = switches.get_mut(trigger.entity) {
+        switch.on = !switch.on;
+    }
+}
+
+fn activate_door(trigger: On<Activate>, mut doors: Query<&mut Door>) {
+    if let Ok(mut door) = doors.get_mut(trigger.entity) {
+        if door.open != trigger.active {
+            door.open = trigger.active;
+        }
+    }
+}
+
+// Sends Activate to every entity whose Name is in targets
+fn activate_targets(
+    commands: &mut Commands,
+    names: &Query<(Entity, &Name)>,
+    targets: &[String],
+    active: bool,
+) {
+    for (entity, name) in names {
+        if targets.iter().any(|target| target == name.as_str()) {
+            commands.trigger(Activate { entity, active });
+        }
+    }
+}
+
+fn detect_trigger_volumes(
+    mut commands: Commands,
+    mut volumes: Query<(Entity, &TriggerVolume, &GlobalTransform, &mut TriggerOccupants)>,
+    tracked: Query<
+        (Entity, &GlobalTransform, Option<&PlayerCollider>),
+        Or<(With<FpsPlayer>, With<SpawnedObject>)>,
+    >,
+    names: Query<(Entity, &Name)>,
+) {
+    for (volume_entity, volume, volume_transform, mut occupants) in &mut volumes {
+        let inside: Vec<Entity> = tracked
+            .iter()
+            .filter(|(_, transform, collider)| {
+                // The player's root is at the feet, test the middle of the body instead
+                let center_height = collider.map_or(0.0, |collider| collider.height * 0.5);
+                volume.shape.contains(volume_transform, transform.translation() + Vec3::Y * center_height)
+            })
+            .map(|(entity, _, _)| entity)
+            .collect();
+
+        let was_empty = occupants.0.is_empty();
+
+        for &other in inside.iter().filter(|entity| !occupants.0.contains(entity)) {
+            commands.trigger(TriggerEnter { entity: volume_entity, other });
+        }
+        // Despawned entities count as leaving too
+        for &other in occupants.0.iter().filter(|entity| !inside.contains(entity)) {
+            commands.trigger(TriggerExit { entity: volume_entity, other });
+        }
+
+        if was_empty != inside.is_empty() {
+            activate_targets(&mut commands, &names, &volume.targets, !inside.is_empty());
+        }
+        occupants.0 = inside;
+    }
+}
+
+fn log_trigger_enter(trigger: On<TriggerEnter>) {
+    debug!("{} entered trigger volume {}", trigger.other, trigger.entity);
+}
+
+fn log_trigger_exit(trigger: On<TriggerExit>) {
+    debug!("{} left trigger volume {}", trigger.other, trigger.entity);
+}
+
+fn update_switches(
+    mut commands: Commands,
+    switches: Query<(Ref<Switch>, &SwitchRuntime)>,
+    names: Query<(Entity, &Name)>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (switch, runtime) in &switches {
+        // A new switch shouldn't change its targets, the level set them up already
+        if !switch.is_changed() || switch.is_added() {
+            continue;
+        }
+        if let Some(material) = materials.get_mut(&runtime.material) {
+            material.base_color = if switch.on { SWITCH_ON_COLOR } else { SWITCH_OFF_COLOR };
+        }
+        activate_targets(&mut commands, &names, &switch.targets, switch.on);
+    }
+}
+
+fn animate_doors(
+    time: Res<Time>,
+    mut doors: Query<(Ref<Door>, &mut DoorRuntime, Option<&mut Interactable>)>,
+    mut panels: Query<(&mut Transform, &mut Mesh3d)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for (door, mut runtime, interactable) in &mut doors {
+        let Ok((mut panel_transform, mut panel_mesh)) = panels.get_mut(runtime.panel) else { continue };
+
+        if door.is_changed() {
+            if runtime.size != door.size {
+                runtime.size = door.size;
+                *panel_mesh = Mesh3d(meshes.add(Cuboid::from_size(door.size)));
+            }
+            if let Some(mut interactable) = interactable {
+                let verb = door_verb(&door);
+                if interactable.verb != verb {
+                    interactable.verb = verb.to_string();
+                }
+            }
+        }
+
+        let target = if door.open { 1.0 } else { 0.0 };
+        if runtime.progress == target {
+            continue;
+        }
+        let step = time.delta_secs() / door.open_time.max(0.01);
+        runtime.progress = if (target - runtime.progress).abs() <= step {
+            target
+        } else {
+            runtime.progress + step * (target - runtime.progress).signum()
+        };
+
+        // Ease in and out so the door doesn't start and stop with a jolt
+        let eased = (1.0 - (runtime.progress * PI).cos()) * 0.5;
+        *panel_transform = door_panel_transform(&door, eased);
+    }
+}
@@ -2,9 +2,15 @@ pub(crate) mod light;
 pub(crate) mod floor;
 pub(crate) mod hud_text;
 pub(crate) mod objects;
+pub(crate) mod object_loading;
+pub(crate) mod object_pool;
+pub(crate) mod level;
+pub(crate) mod terrain;
+pub(crate) mod mechanisms;
+pub(crate) mod portal;
 
 // pub mod light makes items inside of light
 // accessilbe to any crates that depend on my_keyboard_project
 
 // while pub(crate) mod light makes items inside
-// of light accessilbe only to this crate (my_keyboard_project)
\ No newline at end of file
+// of light accessilbe only to this crate (my_keyboard_project)
@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+
+use bevy::{
+    asset::{AssetPath, LoadState, UntypedAssetId},
+    prelude::*,
+};
+
+use crate::{
+    console::ConsoleLog,
+    world::{object_pool::InstancedObject, objects::SpawnedObject},
+};
+
+/*
+    SpawnedObject only stores a path, and the observer in
+    objects.rs loads it into a SceneRoot. If the file was
+    missing or broken you used to get an invisible entity and
+    one line in the log.
+
+    Now every SpawnedObject has an ObjectSceneStatus that
+    follows its scene's load state:
+
+    - Loading   waiting for the asset server
+    - Loaded    the model is there
+    - Failed    a magenta placeholder cube is shown instead,
+                so the object can still be seen and picked up
+
+    Paths that failed are listed in red in the top left corner
+    (and printed to the console and the log once), until they
+    load.
+
+    Models reload when the file changes on disk (bevy's
+    file_watcher feature, see Cargo.toml). Bevy respawns every
+    SceneRoot using the changed scene, and object_pool.rs does
+    the same for instanced objects, so all the spawned boats
+    update at once. A model that failed to load is tried again
+    when its file changes too, and when it loads the
+    placeholders are replaced by the real thing.
+*/
+
+pub struct ObjectLoadingPlugin;
+
+impl Plugin for ObjectLoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ObjectLoadErrors>();
+        app.init_resource::<PlaceholderAssets>();
+        app.add_systems(Startup, spawn_load_error_text);
+        app.add_systems(Update, (
+            track_object_scenes,
+            log_reloaded_object_scenes,
+            update_load_error_text,
+        ).chain());
+    }
+}
+
+// Required by SpawnedObject
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ObjectSceneStatus {
+    #[default]
+    Loading,
+    Loaded,
+    Failed { placeholder: Entity },
+}
+
+// Scene paths that failed to load and why, shown on the HUD
+#[derive(Resource, Default)]
+pub struct ObjectLoadErrors(BTreeMap<String, String>);
+
+// One mesh and material shared by every placeholder
+#[derive(Resource)]
+struct PlaceholderAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+impl FromWorld for PlaceholderAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mesh = world.resource_mut::<Assets<Mesh>>().add(Cuboid::from_length(0.5));
+        let material = world.resource_mut::<Assets<StandardMaterial>>().add(StandardMaterial {
+            base_color: Color::srgb(1.0, 0.0, 1.0),
+            // Bright enough to notice in the dark too
+            emissive: LinearRgba::rgb(0.4, 0.0, 0.4),
+            ..default()
+        });
+        Self { mesh, material }
+    }
+}
+
+#[derive(Component)]
+struct ObjectLoadErrorText;
+
+// GLTF scenes, prefabs and instanced GLTF scenes
+type ObjectScene<'a> = (Option<&'a SceneRoot>, Option<&'a DynamicSceneRoot>, Option<&'a InstancedObject>);
+
+fn object_scene_id((scene, dynamic_scene, instanced): ObjectScene) -> Option<UntypedAssetId> {
+    scene
+        .map(|scene| scene.0.id().untyped())
+        .or_else(|| dynamic_scene.map(|scene| scene.0.id().untyped()))
+        .or_else(|| instanced.map(|instanced| instanced.0.id().untyped()))
+}
+
+// None while it's still loading
+fn scene_load_result(asset_server: &AssetServer, id: UntypedAssetId, path: &str) -> Option<Result<(), String>> {
+    match asset_server.get_load_state(id)? {
+        LoadState::Loaded => return Some(Ok(())),
+        LoadState::Failed(err) => return Some(Err(err.to_string())),
+        LoadState::NotLoaded | LoadState::Loading => {}
+    }
+
+    // A GLTF file that doesn't parse fails the file,
+    // the #Scene0 inside it just never finishes loading
+    let file_id = asset_server.get_path_id(AssetPath::parse(path).without_label())?;
+    match asset_server.get_load_state(file_id)? {
+        LoadState::Failed(err) => Some(Err(err.to_string())),
+        _ => None,
+    }
+}
+
+fn track_object_scenes(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    placeholder_assets: Res<PlaceholderAssets>,
+    mut errors: ResMut<ObjectLoadErrors>,
+    mut console_log: ResMut<ConsoleLog>,
+    mut objects: Query<(Entity, &SpawnedObject, &mut ObjectSceneStatus, ObjectScene)>,
+) {
+    for (entity, object, mut status, scene) in &mut objects {
+        if *status == ObjectSceneStatus::Loaded {
+            continue;
+        }
+        let Some(id) = object_scene_id(scene) else { continue };
+
+        match (scene_load_result(&asset_server, id, &object.scene_path), *status) {
+            (Some(Ok(())), _) => {
+                if let ObjectSceneStatus::Failed { placeholder } = *status {
+                    commands.entity(placeholder).despawn();
+                }
+                if errors.0.remove(&object.scene_path).is_some() {
+                    info!("{} loaded, replacing the placeholders", object.scene_path);
+                }
+                *status = ObjectSceneStatus::Loaded;
+            }
+            (Some(Err(err)), ObjectSceneStatus::Loading) => {
+                let placeholder = commands.spawn((
+                    Name::new("Missing Model Placeholder"),
+                    Mesh3d(placeholder_assets.mesh.clone()),
+                    MeshMaterial3d(placeholder_assets.material.clone()),
+                    ChildOf(entity),
+                )).id();
+                *status = ObjectSceneStatus::Failed { placeholder };
+
+                // Once per path, not once per boat
+                if !errors.0.contains_key(&object.scene_path) {
+                    let message = format!("Couldn't load {}, showing a placeholder: {err}", object.scene_path);
+                    error!("{message}");
+                    console_log.print(message);
+                    errors.0.insert(object.scene_path.clone(), err);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn log_reloaded_object_scenes(
+    mut scene_events: MessageReader<AssetEvent<Scene>>,
+    mut dynamic_scene_events: MessageReader<AssetEvent<DynamicScene>>,
+    asset_server: Res<AssetServer>,
+    mut console_log: ResMut<ConsoleLog>,
+    objects: Query<ObjectScene, With<SpawnedObject>>,
+) {
+    let modified: Vec<UntypedAssetId> = scene_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { id } => Some(id.untyped()),
+            _ => None,
+        })
+        .chain(dynamic_scene_events.read().filter_map(|event| match event {
+            AssetEvent::Modified { id } => Some(id.untyped()),
+            _ => None,
+        }))
+        .collect();
+
+    for id in modified {
+        let count = objects
+            .iter()
+            .filter(|scene| object_scene_id(*scene) == Some(id))
+            .count();
+        if count == 0 {
+            continue;
+        }
+        let Some(path) = asset_server.get_path(id) else { continue };
+        let message = format!("Reloaded {path}, updating {count} spawned objects");
+        info!("{message}");
+        console_log.print(message);
+    }
+}
+
+fn spawn_load_error_text(mut commands: Commands) {
+    commands.spawn((
+        Name::new("Object Load Errors"),
+        ObjectLoadErrorText,
+        Node {
+            position_type: PositionType::Absolute,
+            top: px(8),
+            left: px(12),
+            display: Display::None,
+            ..default()
+        },
+        Text::default(),
+        TextFont {
+            font_size: 16.0,
+            ..default()
+        },
+        TextColor(Color::srgb(1.0, 0.4, 0.4)),
+    ));
+}
+
+fn update_load_error_text(
+    errors: Res<ObjectLoadErrors>,
+    mut texts: Query<(&mut Text, &mut Node), With<ObjectLoadErrorText>>,
+) {
+    if !errors.is_changed() {
+        return;
+    }
+    for (mut text, mut node) in &mut texts {
+        node.display = if errors.0.is_empty() { Display::None } else { Display::Flex };
+        text.0 = errors
+            .0
+            .iter()
+            .map(|(path, err)| format!("Couldn't load {path}: {err}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::{HashMap, VecDeque};
+
+use bevy::{
+    ecs::{entity_disabling::Disabled, query::Allow},
+    mesh::skinning::SkinnedMesh,
+    prelude::*,
+};
+
+use crate::{
+    interaction::Interactable,
+    inventory::Pickup,
+    world::objects::SpawnedObject,
+};
+
+/*
+    Pressing E a few thousand times used to mean a few thousand
+    SceneRoots. Each one makes the SceneSpawner copy the whole
+    GLTF scene graph: a node entity for every GLTF node, plus
+    one for every mesh primitive under it.
+
+    Instancing
+    ----------
+    The first time a model is spawned, its Scene is loaded
+    and turned into an ObjectTemplate: just the meshes and
+    materials in it, with where each one sits relative to the
+    root. After that, spawning the same model makes the root
+    entity (InstancedObject) and one child per mesh, all
+    pointing at the same mesh and material handles.
+
+    Bevy already draws entities with the same mesh and
+    material in one batch, so the GPU side stays cheap. What
+    we save is the entities and the scene copying.
+
+    Models with skinned meshes or animations, and prefabs,
+    still spawn as full scenes. So do the few spawned before
+    the template is ready. Lights and cameras inside a GLTF
+    aren't part of the template.
+
+    When the GLTF changes on disk the template is rebuilt and
+    every InstancedObject gets new children, like bevy does
+    for SceneRoots.
+
+    Pooling
+    -------
+    Picking an object up calls release_object instead of
+    despawning it. With pooling on, the object (and its
+    children) get the Disabled component, which hides them
+    from rendering and from every query, and the next spawn
+    of the same model takes it out of the pool.
+
+    max_objects caps how many objects the ObjectSpawner (E,
+    dropping items, the console's spawn) keeps in the world.
+    At the cap, the oldest one is released to make room.
+
+    All three settings are in ObjectSpawnSettings, which the
+    inspector and the console's set command can change.
+    See benchmark.rs to measure the difference.
+*/
+
+pub struct ObjectPoolPlugin;
+
+impl Plugin for ObjectPoolPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ObjectSpawnSettings>();
+        app.register_type::<ObjectSpawnSettings>();
+        app.init_resource::<ObjectTemplates>();
+        app.init_resource::<ObjectPool>();
+        app.add_systems(First, prune_spawned_objects);
+        app.add_systems(Update, (build_object_templates, rebuild_reloaded_templates).chain());
+    }
+}
+
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+pub struct ObjectSpawnSettings {
+    // Spawn GLTF models as shared meshes instead of full scenes
+    pub instancing: bool,
+    // Keep picked up objects around to reuse
+    pub pooling: bool,
+    // Most objects the ObjectSpawner keeps in the world, 0 for no limit
+    pub max_objects: usize,
+}
+
+impl Default for ObjectSpawnSettings {
+    fn default() -> Self {
+        Self {
+            instancing: true,
+            pooling: true,
+            max_objects: 0,
+        }
+    }
+}
+
+// The root of an instanced object. Holds on to the scene so
+// load state tracking and hot reload work like for a SceneRoot.
+#[derive(Component, Debug, Clone)]
+#[require(Transform, Visibility)]
+pub struct InstancedObject(pub Handle<Scene>);
+
+// The mesh children of an InstancedObject
+#[derive(Component)]
+struct InstancedPart;
+
+#[derive(Resource, Default)]
+pub struct ObjectTemplates {
+    templates: HashMap<String, ObjectTemplate>,
+    // Scenes loading to make a template from
+    pending: HashMap<String, Handle<Scene>>,
+}
+
+pub struct ObjectTemplate {
+    scene: Handle<Scene>,
+    // Empty if the scene can't be instanced
+    parts: Vec<TemplatePart>,
+}
+
+struct TemplatePart {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+    // Relative to the object's root
+    transform: Transform,
+}
+
+impl ObjectTemplates {
+    // The template for a GLTF scene path if it can be instanced.
+    // The first call starts loading the scene and returns None.
+    pub fn get_or_request(&mut self, scene_path: &str, asset_server: &AssetServer) -> Option<&ObjectTemplate> {
+        if !self.templates.contains_key(scene_path) {
+            self.pending
+                .entry(scene_path.to_string())
+                .or_insert_with(|| asset_server.load(scene_path.to_string()));
+            return None;
+        }
+        self.templates.get(scene_path).filter(|template| !template.parts.is_empty())
+    }
+
+    pub fn is_ready(&self, scene_path: &str) -> bool {
+        self.templates.contains_key(scene_path)
+    }
+}
+
+impl ObjectTemplate {
+    // Makes `entity` an instanced object with a child for every mesh
+    pub fn spawn_on(&self, commands: &mut Commands, entity: Entity) {
+        // First, so the parent has its Visibility when the parts are added
+        commands.entity(entity).insert(InstancedObject(self.scene.clone()));
+        for part in &self.parts {
+            commands.spawn((
+                InstancedPart,
+                Mesh3d(part.mesh.clone()),
+                MeshMaterial3d(part.material.clone()),
+                part.transform,
+                ChildOf(entity),
+            ));
+        }
+    }
+}
+
+// Objects the pool is keeping, and the ones the spawner made
+#[derive(Resource, Default)]
+pub struct ObjectPool {
+    // Disabled objects waiting to be reused, by scene path
+    free: HashMap<String, Vec<Entity>>,
+    // Oldest first, for max_objects
+    spawned: VecDeque<Entity>,
+}
+
+impl ObjectPool {
+    pub fn take(&mut self, scene_path: &str) -> Option<Entity> {
+        self.free.get_mut(scene_path)?.pop()
+    }
+
+    // Call for every object the spawner makes or reuses
+    pub fn track_spawned(&mut self, entity: Entity) {
+        self.spawned.push_back(entity);
+    }
+
+    // The oldest objects that have to go to make room for one more
+    pub fn over_cap(&mut self, max_objects: usize) -> Vec<Entity> {
+        let mut over_cap = Vec::new();
+        if max_objects > 0 {
+            while self.spawned.len() >= max_objects {
+                over_cap.extend(self.spawned.pop_front());
+            }
+        }
+        over_cap
+    }
+}
+
+// Forgets objects that were picked up or despawned since last frame.
+// Done here and not in the spawner, which can't see objects it
+// spawned this frame yet.
+fn prune_spawned_objects(mut pool: ResMut<ObjectPool>, live_objects: Query<(), With<SpawnedObject>>) {
+    if pool.spawned.iter().all(|entity| live_objects.contains(*entity)) {
+        return;
+    }
+    pool.spawned.retain(|entity| live_objects.contains(*entity));
+}
+
+// Use instead of despawn for spawned objects. With pooling on
+// they are disabled and kept for the next spawn of the same model.
+pub fn release_object(commands: &mut Commands, entity: Entity) {
+    commands.queue(move |world: &mut World| {
+        let pooling = world.resource::<ObjectSpawnSettings>().pooling;
+        let Ok(mut object) = world.get_entity_mut(entity) else { return };
+        let scene_path = object.get::<SpawnedObject>().map(|object| object.scene_path.clone());
+        let Some(scene_path) = scene_path.filter(|_| pooling) else {
+            object.despawn();
+            return;
+        };
+
+        // Whoever reuses it adds their own Pickup
+        object.remove::<(Pickup, Interactable)>();
+        set_hierarchy_disabled(world, entity, true);
+        world.resource_mut::<ObjectPool>().free.entry(scene_path).or_default().push(entity);
+    });
+}
+
+// Takes an object out of the pool, with a new name and transform
+pub fn reuse_object(commands: &mut Commands, entity: Entity, bundle: impl Bundle) {
+    commands.queue(move |world: &mut World| {
+        if world.get_entity(entity).is_err() {
+            return;
+        }
+        set_hierarchy_disabled(world, entity, false);
+        world.entity_mut(entity).insert(bundle);
+    });
+}
+
+// Disabled only hides the entity it's on, so the children need it too
+pub(crate) fn set_hierarchy_disabled(world: &mut World, entity: Entity, disabled: bool) {
+    let children: Vec<Entity> = world
+        .get::<Children>(entity)
+        .map(|children| children.iter().collect())
+        .unwrap_or_default();
+    for child in children {
+        set_hierarchy_disabled(world, child, disabled);
+    }
+
+    let Ok(mut entity) = world.get_entity_mut(entity) else { return };
+    if disabled {
+        entity.insert(Disabled);
+    } else {
+        entity.remove::<Disabled>();
+    }
+}
+
+fn build_object_templates(
+    mut templates: ResMut<ObjectTemplates>,
+    scenes: Res<Assets<Scene>>,
+    asset_server: Res<AssetServer>,
+) {
+    let finished: Vec<String> = templates
+        .pending
+        .iter()
+        .filter(|(_, scene)| scenes.contains(*scene) || asset_server.load_state(*scene).is_failed())
+        .map(|(path, _)| path.clone())
+        .collect();
+
+    for path in finished {
+        let Some(scene) = templates.pending.remove(&path) else { continue };
+        // Failed ones are tried again by the next spawn,
+        // until then they spawn as scenes (with a placeholder)
+        let Some(parts) = scenes.get(&scene).map(template_parts) else { continue };
+        if parts.is_empty() {
+            info!("{path} can't be instanced, it will spawn as a scene");
+        }
+        templates.templates.insert(path, ObjectTemplate { scene, parts });
+    }
+}
+
+fn template_parts(scene: &Scene) -> Vec<TemplatePart> {
+    let world = &scene.world;
+    // Bones and animations need the real scene graph
+    let animated = world.try_query::<&SkinnedMesh>().is_some_and(|mut query| query.iter(world).next().is_some())
+        || world.try_query::<&AnimationPlayer>().is_some_and(|mut query| query.iter(world).next().is_some());
+    if animated {
+        return Vec::new();
+    }
+
+    let Some(mut meshes) = world.try_query::<(Entity, &Mesh3d, &MeshMaterial3d<StandardMaterial>)>() else {
+        return Vec::new();
+    };
+    meshes
+        .iter(world)
+        .map(|(entity, mesh, material)| TemplatePart {
+            mesh: mesh.0.clone(),
+            material: material.0.clone(),
+            transform: scene_transform(world, entity),
+        })
+        .collect()
+}
+
+// Where the entity is relative to the scene's root. A scene's
+// GlobalTransforms haven't been worked out, so we walk the parents.
+fn scene_transform(world: &World, entity: Entity) -> Transform {
+    let mut transform = world.get::<Transform>(entity).copied().unwrap_or_default();
+    let mut current = entity;
+    while let Some(parent) = world.get::<ChildOf>(current).map(ChildOf::parent) {
+        if let Some(parent_transform) = world.get::<Transform>(parent) {
+            transform = parent_transform.mul_transform(transform);
+        }
+        current = parent;
+    }
+    transform
+}
+
+type InstancedObjectParts<'a> = (Entity, &'a InstancedObject, Option<&'a Children>, Has<Disabled>);
+
+fn rebuild_reloaded_templates(
+    mut commands: Commands,
+    mut events: MessageReader<AssetEvent<Scene>>,
+    scenes: Res<Assets<Scene>>,
+    mut templates: ResMut<ObjectTemplates>,
+    // Pooled objects are disabled, they need the new meshes too
+    objects: Query<InstancedObjectParts, Allow<Disabled>>,
+    parts: Query<(), (With<InstancedPart>, Allow<Disabled>)>,
+) {
+    for event in events.read() {
+        let AssetEvent::Modified { id } = event else { continue };
+        let Some(scene) = scenes.get(*id) else { continue };
+        let Some(template) = templates.templates.values_mut().find(|template| template.scene.id() == *id) else { continue };
+
+        let new_parts = template_parts(scene);
+        if new_parts.is_empty() && !template.parts.is_empty() {
+            warn!("The reloaded scene can't be instanced anymore, keeping the old meshes until a restart");
+            continue;
+        }
+        template.parts = new_parts;
+
+        for (entity, instanced, children, disabled) in &objects {
+            if instanced.0.id() != *id {
+                continue;
+            }
+            for child in children.into_iter().flatten().filter(|child| parts.contains(**child)) {
+                commands.entity(*child).despawn();
+            }
+            template.spawn_on(&mut commands, entity);
+            // The new parts would show up at the pooled object's position
+            if disabled {
+                commands.queue(move |world: &mut World| set_hierarchy_disabled(world, entity, true));
+            }
+        }
+    }
+}
@@ -1,64 +1,240 @@
 // Feel free to spawn some objects in here yourself.
 // This is just an empty plugin for now.
 
-use bevy::{ecs::query::QuerySingleError, prelude::*};
+use bevy::{ecs::{entity_disabling::Disabled, query::QuerySingleError, system::SystemParam}, prelude::*};
 
-use crate::player::FpsPlayer;
+use crate::{
+    console::{ConsoleAppExt, ConsoleArgs, ConsoleResult},
+    input_context::gameplay_input,
+    inventory::{ItemCatalog, Pickup},
+    pause_menu::PauseState,
+    prefab::is_prefab_path,
+    player::FpsPlayer,
+    scene_io::{Saveable, SaveableAppExt},
+    sound::{PlaySound, SPAWN_SOUND},
+    spectator::CameraMode,
+    world::{
+        level::SpawnZone,
+        object_loading::ObjectSceneStatus,
+        object_pool::{release_object, reuse_object, ObjectPool, ObjectSpawnSettings, ObjectTemplates},
+    },
+};
 
 pub struct ObjectsPlugin;
 
 impl Plugin for ObjectsPlugin {
     fn build(&self, app: &mut App) {
-       app.add_systems(Update, spawn_object_on_key_e);
+       app.register_saveable::<SpawnedObject>();
+       app.init_resource::<SpawnKeyItem>();
+       app.register_type::<SpawnKeyItem>();
+       app.add_observer(spawn_object_scene);
+       app.add_systems(Update, spawn_object_on_key_e
+           .run_if(in_state(PauseState::Running))
+           .run_if(in_state(CameraMode::Player))
+           .run_if(gameplay_input));
+       app.add_console_command("spawn", "spawn <item>  spawn an item from the ItemCatalog in front of you", spawn_command);
+       app.add_console_completions("spawn", item_ids);
     }
 }
 
-pub fn spawn_object_on_key_e(
-   mut commands: Commands,
-   asset_server: Res<AssetServer>,
-   keyboard_input: Res<ButtonInput<KeyCode>>,
-   player_head: Query<&GlobalTransform, With<FpsPlayer>>,
-) {
-    if !keyboard_input.just_pressed(KeyCode::KeyE) {
-        return;
+// The ItemCatalog item E spawns
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+pub struct SpawnKeyItem(pub String);
+
+impl Default for SpawnKeyItem {
+    fn default() -> Self {
+        Self("boat".to_string())
     }
+}
 
-    let mut player_g_transform = match player_head.single() {
-        Ok(transform) => transform,
-        Err(QuerySingleError::NoEntities(_)) => {
-            error!("No FpsPlayer found (0 players)");
-            return;
-        }
-        Err(QuerySingleError::MultipleEntities(_)) => {
-            error!("Multiple FpsPlayer entities found (>1 players)");
-            return;
-        }
+// Same idea as FpsPlayer, we only store what scene to spawn
+// and let an observer add the SceneRoot. Handles can't be
+// saved to a scene file, but a path can.
+// ObjectSceneStatus shows a placeholder if the scene
+// doesn't load, see object_loading.rs
+// GLTF models that were spawned before are instanced
+// instead of getting a SceneRoot, see object_pool.rs
+#[derive(Component, Reflect, Default, Clone)]
+#[reflect(Component, Default)]
+#[require(ObjectSceneStatus)]
+pub struct SpawnedObject {
+    pub scene_path: String,
+}
+
+fn spawn_object_scene(
+    trigger: On<Add, SpawnedObject>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<ObjectSpawnSettings>,
+    mut templates: ResMut<ObjectTemplates>,
+    objects: Query<&SpawnedObject>,
+) {
+    let Ok(object) = objects.get(trigger.entity) else { return };
+
+    // Prefabs are bevy scenes, everything else is a GLTF scene
+    let is_prefab = is_prefab_path(&object.scene_path);
+    let template = if settings.instancing && !is_prefab {
+        templates.get_or_request(&object.scene_path, &asset_server)
+    } else {
+        None
     };
 
-   // FILL IN HERE WITH YOUR OWN GLTF SPAWNING CODE
+    if let Some(template) = template {
+        template.spawn_on(&mut commands, trigger.entity);
+    } else if is_prefab {
+        commands.entity(trigger.entity).insert_if_new(DynamicSceneRoot(asset_server.load(object.scene_path.clone())));
+    } else {
+        commands.entity(trigger.entity).insert_if_new(SceneRoot(asset_server.load(object.scene_path.clone())));
+    }
+    commands.entity(trigger.entity).insert_if_new((
+        Transform::default(),
+        Saveable,
+    ));
+}
+
+// The "boat" item's model, what E spawns by default
+pub const BOAT_SCENE: &str = "models/scene.gltf#Scene0";
+
+// Spawns objects in front of the player camera.
+// Pressing E uses it, and so does dropping an item
+// from the inventory, so both follow the same rules.
+// It reuses pooled objects and keeps to max_objects.
+#[derive(SystemParam)]
+pub struct ObjectSpawner<'w, 's> {
+    commands: Commands<'w, 's>,
+    pool: ResMut<'w, ObjectPool>,
+    settings: Res<'w, ObjectSpawnSettings>,
+    pooled: Query<'w, 's, (), (With<SpawnedObject>, With<Disabled>)>,
+    players: Query<'w, 's, &'static FpsPlayer>,
+    cameras: Query<'w, 's, &'static GlobalTransform, Without<SpawnZone>>,
+    spawn_zones: Query<'w, 's, (&'static SpawnZone, &'static GlobalTransform)>,
+}
 
-   // When you spawn SceneRoot component
-   // Add a transform to the spawn function
-   // and adjust that transform based on playe position
+impl ObjectSpawner<'_, '_> {
+    // Returns the new entity so callers can add their own components,
+    // or None if there's no player or it's outside the spawn zones
+    pub fn spawn_in_front(&mut self, name: &str, scene_path: &str) -> Option<Entity> {
+        let player = match self.players.single() {
+            Ok(player) => player,
+            Err(QuerySingleError::NoEntities(_)) => {
+                error!("No FpsPlayer found (0 players)");
+                return None;
+            }
+            Err(QuerySingleError::MultipleEntities(_)) => {
+                error!("Multiple FpsPlayer entities found (>1 players)");
+                return None;
+            }
+        };
 
-   // load gltf example:
-   // https://bevy.org/examples/3d-rendering/load-gltf/
+        // Spawn in front of where the camera is looking.
+        // The player root only knows where the feet are.
+        let player_g_transform = player.camera_entity().and_then(|e| self.cameras.get(e).ok())?;
 
-    let player_pos = player_g_transform.translation();
-    let forward = player_g_transform.forward();
-    let spawn_pos = player_pos + (forward * 2.0);
+       // FILL IN HERE WITH YOUR OWN GLTF SPAWNING CODE
 
-    let loaded_asset = asset_server.load("models/scene.gltf#Scene0");
+       // When you spawn SceneRoot component
+       // Add a transform to the spawn function
+       // and adjust that transform based on playe position
 
-    commands.spawn((
-        Name::new("Spawned Boat"),
-        SceneRoot(loaded_asset), 
-        Transform{
+       // load gltf example:
+       // https://bevy.org/examples/3d-rendering/load-gltf/
+
+        let player_pos = player_g_transform.translation();
+        let forward = player_g_transform.forward();
+        let spawn_pos = player_pos + (forward * 2.0);
+
+        // Levels can limit where objects are allowed to appear
+        if !self.spawn_zones.is_empty()
+            && !self.spawn_zones.iter().any(|(zone, zone_transform)| zone.contains(zone_transform, spawn_pos))
+        {
+            info!("Can't spawn an object outside of the level's spawn zones");
+            return None;
+        }
+
+        let transform = Transform{
             translation: spawn_pos,
             // You might need to scale the object if the GLTF is too big/small
             scale: Vec3::splat(1.0), 
             ..default()
+        };
+
+        // At the cap the oldest object goes back to the pool
+        for oldest in self.pool.over_cap(self.settings.max_objects) {
+            release_object(&mut self.commands, oldest);
         }
-    ));
 
-}
\ No newline at end of file
+        let pooled = std::iter::from_fn(|| self.pool.take(scene_path))
+            .find(|entity| self.pooled.contains(*entity));
+        let object = match pooled {
+            Some(object) => {
+                reuse_object(&mut self.commands, object, (Name::new(name.to_string()), transform));
+                object
+            }
+            None => self.commands.spawn((
+                Name::new(name.to_string()),
+                SpawnedObject {
+                    scene_path: scene_path.to_string(),
+                },
+                transform,
+            )).id(),
+        };
+        self.pool.track_spawned(object);
+
+        self.commands.trigger(PlaySound::at(SPAWN_SOUND, spawn_pos));
+        Some(object)
+    }
+}
+
+pub fn spawn_object_on_key_e(
+   mut commands: Commands,
+   keyboard_input: Res<ButtonInput<KeyCode>>,
+   spawn_item: Res<SpawnKeyItem>,
+   catalog: Res<ItemCatalog>,
+   mut spawner: ObjectSpawner,
+) {
+    if !keyboard_input.just_pressed(KeyCode::KeyE) {
+        return;
+    }
+
+    let Some(item) = catalog.get(&spawn_item.0) else {
+        warn!("E spawns {:?}, which isn't in the ItemCatalog", spawn_item.0);
+        return;
+    };
+
+    // Whatever E spawns can be picked up into the inventory
+    let name = format!("Spawned {}", item.name);
+    if let Some(object) = spawner.spawn_in_front(&name, &item.scene_path) {
+        commands.entity(object).insert(Pickup::new(spawn_item.0.clone(), 1));
+    }
+}
+
+// The console's spawn command, like E but for any catalog item
+fn spawn_command(
+    In(args): In<ConsoleArgs>,
+    mut commands: Commands,
+    catalog: Res<ItemCatalog>,
+    mut spawner: ObjectSpawner,
+) -> ConsoleResult {
+    let Some(id) = args.get(0) else {
+        return Err(format!("which item? {}", item_list(&catalog)));
+    };
+    let Some(item) = catalog.get(id) else {
+        return Err(format!("no item '{id}', try {}", item_list(&catalog)));
+    };
+
+    let name = format!("Spawned {}", item.name);
+    let object = spawner
+        .spawn_in_front(&name, &item.scene_path)
+        .ok_or("can't spawn here, there's no player or it's outside the spawn zones")?;
+    commands.entity(object).insert(Pickup::new(id.to_string(), 1));
+    Ok(name)
+}
+
+fn item_list(catalog: &ItemCatalog) -> String {
+    catalog.iter().map(|(id, _)| id).collect::<Vec<_>>().join(", ")
+}
+
+fn item_ids(world: &World) -> Vec<String> {
+    world.resource::<ItemCatalog>().iter().map(|(id, _)| id.to_string()).collect()
+}
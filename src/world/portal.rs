@@ -0,0 +1,486 @@
+use std::f32::consts::PI;
+
+use bevy::{
+    camera::{CameraProjection, CameraUpdateSystems, RenderTarget, SubCameraView, visibility::RenderLayers},
+    core_pipeline::tonemapping::Tonemapping,
+    ecs::entity::EntityHashMap,
+    math::Vec3A,
+    prelude::*,
+    render::render_resource::{AsBindGroup, Extent3d, TextureFormat},
+    shader::ShaderRef,
+    transform::helper::TransformHelper,
+    window::PrimaryWindow,
+};
+use serde::Deserialize;
+
+use crate::{
+    camera_effects::apply_camera_effects,
+    keyboard_input::update_keyboard_movement,
+    pause_menu::PauseState,
+    player::{FpsPlayer, PlayerEyeHeight, PlayerVelocity, Teleported},
+    scene_io::SaveableAppExt,
+    world::objects::SpawnedObject,
+};
+
+/*
+    A Portal is an opening that looks out of, and leads to,
+    the portal named in its `linked` field. Make two of them
+    that link to each other and you can walk between them.
+
+    Looking through a portal:
+        Each portal has its own camera that renders into an image.
+        If the player camera is at some pose relative to portal A,
+        A's camera is placed at the same pose relative to portal B,
+        turned around so that looking into A's front is looking out
+        of B's front. A's surface then shows that image, sampled in
+        screen space, so it lines up with the world around it.
+
+        Anything between A's camera and portal B (the wall B is
+        on, for example) would block the view. So the camera's near
+        plane is tilted to lie on B's surface (an "oblique" near plane,
+        see PortalProjection), and everything behind B is clipped.
+
+        Portal surfaces are on PORTAL_LAYER. The player camera sees
+        it, the portal cameras don't, so a portal camera never
+        draws into the image it is reading from.
+
+    Walking through:
+        When the player's eyes or a spawned object cross a portal's
+        surface from the front, they're moved to the same pose relative
+        to the linked portal. Velocity is turned with them, and the
+        camera pitch is untouched, so you keep looking the same way.
+
+    Portals should be upright (only turned around Y), the player
+    only keeps the yaw part of the rotation when walking through.
+    The portal's Transform is the bottom centre of the opening,
+    and the opening faces along the portal's local +Z.
+*/
+
+pub struct PortalPlugin;
+
+impl Plugin for PortalPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(MaterialPlugin::<PortalMaterial>::default());
+        app.register_saveable::<Portal>();
+
+        app.add_observer(setup_portal);
+        app.add_systems(Update, (
+            link_portals,
+            resize_portal_images,
+        ));
+        app.add_systems(Update, teleport_through_portals
+            .after(update_keyboard_movement)
+            .run_if(in_state(PauseState::Running)));
+        // Before the camera's projection matrix and the transforms are
+        // updated, so the portal view is never a frame behind
+        app.add_systems(PostUpdate, update_portal_cameras
+            .after(apply_camera_effects)
+            .before(CameraUpdateSystems)
+            .before(TransformSystems::Propagate));
+    }
+}
+
+// Portal surfaces are only seen by cameras on this layer
+pub const PORTAL_LAYER: usize = 2;
+const PORTAL_SHADER: &str = "shaders/portal.wgsl";
+// The clip plane sits this far in front of the exit portal,
+// so the exit portal's own frame isn't drawn in the view
+const CLIP_PLANE_OFFSET: f32 = 0.01;
+
+#[derive(Component, Reflect, Deserialize, Debug, Clone)]
+#[reflect(Component, Default)]
+#[require(PortalRuntime)]
+#[serde(default)]
+pub struct Portal {
+    // Name of the portal this one leads to
+    pub linked: String,
+    // Width and height of the opening
+    pub size: Vec2,
+}
+
+impl Default for Portal {
+    fn default() -> Self {
+        Self {
+            linked: String::new(),
+            size: Vec2::new(1.2, 2.2),
+        }
+    }
+}
+
+#[derive(Component, Default)]
+struct PortalRuntime {
+    camera: Option<Entity>,
+    surface: Option<Entity>,
+    image: Handle<Image>,
+    // The linked portal's entity, found by name
+    link: Option<Entity>,
+}
+
+#[derive(Component)]
+struct PortalCamera;
+
+// Shows the portal camera's image in screen space, see assets/shaders/portal.wgsl
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct PortalMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    pub texture: Handle<Image>,
+}
+
+impl Material for PortalMaterial {
+    fn fragment_shader() -> ShaderRef {
+        PORTAL_SHADER.into()
+    }
+}
+
+// A perspective projection with a tilted near plane.
+//
+// Bevy uses reverse Z with an infinite far plane, so a point is
+// in front of the near plane when (row 3 - row 2) · point >= 0.
+// Replacing row 2 with row 3 - plane / k makes the near plane the
+// given plane instead. k keeps the depth of everything inside the
+// frustum between 0 and 1.
+#[derive(Debug, Clone, Default)]
+pub struct PortalProjection {
+    pub perspective: PerspectiveProjection,
+    // In view space, xyz is the normal and w the distance.
+    // Points with plane · point >= 0 are kept.
+    // Zero means a normal near plane.
+    pub near_plane: Vec4,
+}
+
+impl CameraProjection for PortalProjection {
+    fn get_clip_from_view(&self) -> Mat4 {
+        let mut clip_from_view = self.perspective.get_clip_from_view();
+        if self.near_plane == Vec4::ZERO {
+            return clip_from_view;
+        }
+
+        let tan_y = (self.perspective.fov * 0.5).tan();
+        let tan_x = tan_y * self.perspective.aspect_ratio;
+        let k = (1.0 + tan_x * tan_x + tan_y * tan_y).sqrt();
+        let plane = self.near_plane / k;
+
+        // glam matrices are column major, these are row 2's elements
+        clip_from_view.x_axis.z = -plane.x;
+        clip_from_view.y_axis.z = -plane.y;
+        clip_from_view.z_axis.z = -1.0 - plane.z;
+        clip_from_view.w_axis.z = -plane.w;
+        clip_from_view
+    }
+
+    fn get_clip_from_view_for_sub(&self, sub_view: &SubCameraView) -> Mat4 {
+        self.perspective.get_clip_from_view_for_sub(sub_view)
+    }
+
+    fn update(&mut self, width: f32, height: f32) {
+        self.perspective.update(width, height);
+    }
+
+    fn far(&self) -> f32 {
+        self.perspective.far
+    }
+
+    fn get_frustum_corners(&self, z_near: f32, z_far: f32) -> [Vec3A; 8] {
+        self.perspective.get_frustum_corners(z_near, z_far)
+    }
+}
+
+// Takes a pose in front of `from` to the same pose in front of `to`
+fn portal_transform(from: &GlobalTransform, to: &GlobalTransform) -> GlobalTransform {
+    let turn_around = GlobalTransform::from_rotation(Quat::from_rotation_y(PI));
+    *to * turn_around * GlobalTransform::from(from.affine().inverse())
+}
+
+fn setup_portal(
+    trigger: On<Add, Portal>,
+    mut commands: Commands,
+    portals: Query<&Portal>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<PortalMaterial>>,
+) {
+    let Ok(portal) = portals.get(trigger.entity) else { return };
+
+    // Resized to the player camera later, this is just a first guess
+    let size = windows.iter().next().map_or(UVec2::new(1280, 720), |window| window.physical_size());
+    let image = images.add(Image::new_target_texture(
+        size.x.max(1),
+        size.y.max(1),
+        TextureFormat::Bgra8UnormSrgb,
+    ));
+
+    let surface = commands.spawn((
+        Name::new("Portal Surface"),
+        Mesh3d(meshes.add(Rectangle::from_size(portal.size))),
+        MeshMaterial3d(materials.add(PortalMaterial { texture: image.clone() })),
+        Transform::from_xyz(0.0, portal.size.y * 0.5, 0.0),
+        RenderLayers::layer(PORTAL_LAYER),
+        ChildOf(trigger.entity),
+    )).id();
+
+    let camera = commands.spawn((
+        Name::new("Portal Camera"),
+        PortalCamera,
+        Camera3d::default(),
+        Camera {
+            // Before the player camera, which shows the image
+            order: -1,
+            target: RenderTarget::from(image.clone()),
+            is_active: false,
+            ..default()
+        },
+        Projection::custom(PortalProjection::default()),
+        // The player camera tonemaps the portal surface,
+        // so don't do it twice
+        Tonemapping::None,
+        ChildOf(trigger.entity),
+    )).id();
+
+    commands.entity(trigger.entity).insert(PortalRuntime {
+        camera: Some(camera),
+        surface: Some(surface),
+        image,
+        link: None,
+    }).insert_if_new((
+        Name::new("Portal"),
+        Transform::default(),
+        Visibility::default(),
+    ));
+}
+
+// Finds each portal's linked portal by name
+fn link_portals(
+    mut portals: Query<(Entity, Ref<Portal>, &mut PortalRuntime)>,
+    names: Query<(Entity, &Name), With<Portal>>,
+) {
+    for (entity, portal, mut runtime) in &mut portals {
+        let still_linked = runtime.link.is_some_and(|link| names.contains(link));
+        if still_linked && !portal.is_changed() {
+            continue;
+        }
+
+        let link = names
+            .iter()
+            .find(|(other, name)| *other != entity && name.as_str() == portal.linked)
+            .map(|(other, _)| other);
+        if runtime.link != link {
+            runtime.link = link;
+        }
+    }
+}
+
+// The portal images match the player camera's size
+fn resize_portal_images(
+    players: Query<&FpsPlayer>,
+    cameras: Query<&Camera, Without<PortalCamera>>,
+    portals: Query<&PortalRuntime>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Some(size) = players
+        .iter()
+        .next()
+        .and_then(|player| player.camera_entity())
+        .and_then(|camera| cameras.get(camera).ok())
+        .and_then(|camera| camera.physical_viewport_size())
+    else {
+        return;
+    };
+
+    for runtime in &portals {
+        // get_mut marks the image as changed, so check first
+        let image_size = images.get(&runtime.image).map(|image| image.size());
+        if image_size.is_none_or(|image_size| image_size == size) {
+            continue;
+        }
+        if let Some(image) = images.get_mut(&runtime.image) {
+            image.resize(Extent3d { width: size.x, height: size.y, ..default() });
+        }
+    }
+}
+
+fn update_portal_cameras(
+    players: Query<&FpsPlayer>,
+    player_cameras: Query<(&Camera, &Projection), Without<PortalCamera>>,
+    portals: Query<(Entity, &PortalRuntime)>,
+    surfaces: Query<&ViewVisibility>,
+    mut portal_cameras: Query<(&mut Camera, &mut Projection), With<PortalCamera>>,
+    mut transforms: ParamSet<(TransformHelper, Query<&mut Transform, With<PortalCamera>>)>,
+) {
+    let Some(player_camera) = players.iter().next().and_then(|player| player.camera_entity()) else { return };
+    let Ok((player_cam, player_projection)) = player_cameras.get(player_camera) else { return };
+    let player_fov = match player_projection {
+        Projection::Perspective(perspective) => perspective.fov,
+        _ => PerspectiveProjection::default().fov,
+    };
+
+    // Work out every camera's pose first, TransformHelper
+    // reads the Transforms we're about to write
+    let mut poses = Vec::new();
+    {
+        let helper = transforms.p0();
+        let Ok(eye) = helper.compute_global_transform(player_camera) else { return };
+
+        for (entity, runtime) in &portals {
+            let Some(camera) = runtime.camera else { continue };
+            let visible = runtime.surface.and_then(|surface| surfaces.get(surface).ok()).is_some_and(|v| v.get());
+            let globals = runtime.link.and_then(|link| {
+                Some((helper.compute_global_transform(entity).ok()?, helper.compute_global_transform(link).ok()?))
+            });
+            let pose = globals.map(|(portal, exit)| {
+                let camera_global = portal_transform(&portal, &exit) * eye;
+                (camera_global.reparented_to(&portal), near_plane(&camera_global, &exit))
+            });
+            // No need to render what nobody can see
+            poses.push((camera, pose.filter(|_| visible && player_cam.is_active)));
+        }
+    }
+
+    let mut camera_transforms = transforms.p1();
+    for (camera, pose) in poses {
+        let Ok((mut portal_cam, mut projection)) = portal_cameras.get_mut(camera) else { continue };
+        if portal_cam.is_active != pose.is_some() {
+            portal_cam.is_active = pose.is_some();
+        }
+        let Some((transform, plane)) = pose else { continue };
+
+        if let Ok(mut camera_transform) = camera_transforms.get_mut(camera) {
+            *camera_transform = transform;
+        }
+        let Projection::Custom(custom) = &mut *projection else { continue };
+        if let Some(portal_projection) = custom.get_mut::<PortalProjection>() {
+            portal_projection.perspective.fov = player_fov;
+            portal_projection.near_plane = plane;
+        }
+    }
+}
+
+// The exit portal's surface as a plane in the portal camera's view space
+fn near_plane(camera: &GlobalTransform, exit: &GlobalTransform) -> Vec4 {
+    let view_from_world = camera.affine().inverse();
+    let normal = exit.rotation() * Vec3::Z;
+    let point = exit.translation() + normal * CLIP_PLANE_OFFSET;
+
+    let view_normal = view_from_world.transform_vector3(normal).normalize();
+    let view_point = view_from_world.transform_point3(point);
+    let plane = view_normal.extend(-view_normal.dot(view_point));
+
+    // If the camera is in front of the exit there's nothing
+    // behind the exit to hide, and the tilted plane would clip everything
+    if plane.w >= 0.0 { Vec4::ZERO } else { plane }
+}
+
+fn teleport_through_portals(
+    mut commands: Commands,
+    mut tracked: Query<
+        (Entity, &mut Transform, Option<&PlayerEyeHeight>, Option<&mut PlayerVelocity>),
+        Or<(With<FpsPlayer>, With<SpawnedObject>)>,
+    >,
+    portals: Query<(&Portal, &GlobalTransform, &PortalRuntime)>,
+    exits: Query<&GlobalTransform, With<Portal>>,
+    mut last_positions: Local<EntityHashMap<Vec3>>,
+) {
+    let mut positions = EntityHashMap::default();
+
+    for (entity, mut transform, eye_height, velocity) in &mut tracked {
+        // The player goes through when their eyes do, that's what the camera sees
+        let eye_offset = eye_height.map_or(Vec3::ZERO, |height| Vec3::Y * height.0);
+        let position = transform.translation + eye_offset;
+        let Some(&last_position) = last_positions.get(&entity) else {
+            positions.insert(entity, position);
+            continue;
+        };
+
+        let crossed = portals.iter().find_map(|(portal, portal_transform, runtime)| {
+            let exit = exits.get(runtime.link?).ok()?;
+            crosses_portal(portal, portal_transform, last_position, position).then_some((portal_transform, exit))
+        });
+        let Some((portal_global, exit)) = crossed else {
+            positions.insert(entity, position);
+            continue;
+        };
+
+        let through = portal_transform(portal_global, exit);
+        let moved = through * GlobalTransform::from(*transform);
+        transform.translation = moved.translation();
+        // Only the yaw, the root must stay upright. Pitch is on the camera.
+        let (yaw, _, _) = moved.rotation().to_euler(EulerRot::YXZ);
+        transform.rotation = Quat::from_rotation_y(yaw);
+        if let Some(mut velocity) = velocity {
+            velocity.0 = through.rotation() * velocity.0;
+        }
+        commands.entity(entity).insert(Teleported);
+
+        positions.insert(entity, transform.translation + eye_offset);
+    }
+
+    *last_positions = positions;
+}
+
+// Did the segment go through the opening from the front?
+fn crosses_portal(portal: &Portal, transform: &GlobalTransform, from: Vec3, to: Vec3) -> bool {
+    let local_from_world = transform.affine().inverse();
+    let from = local_from_world.transform_point3(from);
+    let to = local_from_world.transform_point3(to);
+    if from.z <= 0.0 || to.z > 0.0 {
+        return false;
+    }
+
+    let hit = from.lerp(to, from.z / (from.z - to.z));
+    hit.x.abs() <= portal.size.x * 0.5 && hit.y >= 0.0 && hit.y <= portal.size.y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A plane 5m in front of the camera, facing away from it,
+    // like the exit portal seen from behind it
+    fn exit_projection() -> PortalProjection {
+        PortalProjection {
+            perspective: PerspectiveProjection::default(),
+            near_plane: Vec4::new(0.0, 0.0, -1.0, -5.0),
+        }
+    }
+
+    // Bevy uses reverse z, kept points end up with 0 <= z <= w
+    fn is_kept(clip_from_view: Mat4, view_point: Vec3) -> bool {
+        let clip = clip_from_view * view_point.extend(1.0);
+        clip.z >= 0.0 && clip.z <= clip.w
+    }
+
+    #[test]
+    fn oblique_near_plane_clips_between_camera_and_exit() {
+        let clip_from_view = exit_projection().get_clip_from_view();
+        assert!(!is_kept(clip_from_view, Vec3::new(0.0, 0.0, -4.9)));
+        assert!(!is_kept(clip_from_view, Vec3::new(0.5, -0.5, -2.0)));
+        assert!(is_kept(clip_from_view, Vec3::new(0.0, 0.0, -5.1)));
+        assert!(is_kept(clip_from_view, Vec3::new(0.5, -0.5, -20.0)));
+    }
+
+    #[test]
+    fn zero_near_plane_is_a_normal_perspective() {
+        let projection = PortalProjection {
+            perspective: PerspectiveProjection::default(),
+            near_plane: Vec4::ZERO,
+        };
+        assert_eq!(projection.get_clip_from_view(), projection.perspective.get_clip_from_view());
+        assert!(is_kept(projection.get_clip_from_view(), Vec3::new(0.0, 0.0, -1.0)));
+    }
+
+    #[test]
+    fn crosses_only_from_the_front_through_the_opening() {
+        let portal = Portal::default();
+        let transform = GlobalTransform::from_xyz(3.0, 0.0, 0.0);
+        let front = Vec3::new(3.0, 1.0, 0.5);
+        let back = Vec3::new(3.0, 1.0, -0.5);
+
+        assert!(crosses_portal(&portal, &transform, front, back));
+        assert!(!crosses_portal(&portal, &transform, back, front));
+        assert!(!crosses_portal(&portal, &transform, front, front + Vec3::Z));
+        // Past the side of the opening
+        let offset = Vec3::X * portal.size.x;
+        assert!(!crosses_portal(&portal, &transform, front + offset, back + offset));
+    }
+}
@@ -0,0 +1,439 @@
+use bevy::{
+    asset::RenderAssetUsages,
+    mesh::{Indices, PrimitiveTopology},
+    platform::collections::HashMap,
+    prelude::*,
+};
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use serde::Deserialize;
+
+use crate::player::FpsPlayer;
+
+/*
+    Terrain is split into square chunks that are spawned
+    around the player as they move, and despawned when they
+    get too far away. Chunks close to the player get more
+    vertices than chunks far away (LOD).
+
+    Heights come from either noise or a grayscale PNG.
+    The same height function builds the meshes and keeps
+    the player on top of the ground, so what you see is
+    what you walk on.
+
+    Where a detailed chunk meets a coarser one, the edges
+    don't line up exactly. Every chunk has a skirt, a strip
+    hanging down from its edges, that fills those gaps.
+
+    Add a Terrain component to any entity (levels do this
+    with a Terrain entry) and the chunks become its children.
+    Despawning that entity removes the whole terrain.
+*/
+
+pub struct TerrainPlugin;
+
+impl Plugin for TerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Terrain>();
+        app.register_type::<TerrainChunk>();
+
+        app.add_observer(setup_terrain);
+        app.add_systems(Update, (
+            load_heightmaps,
+            stream_terrain_chunks,
+        ).chain());
+        app.add_systems(PostUpdate, keep_players_above_terrain.before(TransformSystems::Propagate));
+    }
+}
+
+#[derive(Component, Reflect, Deserialize, Debug, Clone)]
+#[reflect(Component, Default)]
+#[serde(default)]
+pub struct Terrain {
+    pub source: TerrainSource,
+    // Width of one chunk in meters
+    pub chunk_size: f32,
+    // Quads along one side of a chunk at full detail
+    pub resolution: u32,
+    // How many chunks to keep loaded in each direction
+    pub view_distance: u32,
+    // Every distance in this list halves the resolution
+    // for chunks further away than it
+    pub lod_distances: Vec<f32>,
+    // How many meters one repeat of the texture covers
+    pub uv_scale: f32,
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Self {
+            source: TerrainSource::default(),
+            chunk_size: 32.0,
+            resolution: 32,
+            view_distance: 4,
+            lod_distances: vec![48.0, 96.0],
+            uv_scale: 4.0,
+        }
+    }
+}
+
+#[derive(Reflect, Deserialize, Debug, Clone)]
+pub enum TerrainSource {
+    Noise {
+        seed: u32,
+        // Lower is smoother, bigger hills
+        frequency: f64,
+        octaves: usize,
+        // Height in meters from the lowest to the highest point
+        height: f32,
+    },
+    // A grayscale image, black is 0 and white is height.
+    // The image is stretched over size x size meters
+    // centered on the terrain's origin.
+    Heightmap {
+        path: String,
+        size: f32,
+        height: f32,
+    },
+}
+
+impl Default for TerrainSource {
+    fn default() -> Self {
+        Self::Noise {
+            seed: 0,
+            frequency: 0.01,
+            octaves: 4,
+            height: 12.0,
+        }
+    }
+}
+
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component)]
+pub struct TerrainChunk {
+    pub coord: IVec2,
+    pub lod: u32,
+}
+
+// The runtime half of a Terrain, built when Terrain is added
+#[derive(Component)]
+struct TerrainRuntime {
+    sampler: HeightSampler,
+    material: Handle<StandardMaterial>,
+    chunks: HashMap<IVec2, Entity>,
+}
+
+enum HeightSampler {
+    Noise {
+        fbm: Fbm<Perlin>,
+        height: f32,
+    },
+    Heightmap {
+        image: Handle<Image>,
+        size: f32,
+        height: f32,
+        // Filled in once the image has loaded
+        grid: Option<HeightGrid>,
+    },
+}
+
+struct HeightGrid {
+    width: usize,
+    depth: usize,
+    samples: Vec<f32>,
+}
+
+impl HeightSampler {
+    // Height at a point relative to the terrain's origin.
+    // None means the heights aren't ready yet.
+    fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        match self {
+            HeightSampler::Noise { fbm, height } => {
+                // Fbm returns roughly -1..1
+                let value = fbm.get([x as f64, z as f64]) as f32;
+                Some((value * 0.5 + 0.5) * height)
+            }
+            HeightSampler::Heightmap { size, height, grid, .. } => {
+                let grid = grid.as_ref()?;
+                Some(grid.sample(x / size + 0.5, z / size + 0.5) * height)
+            }
+        }
+    }
+
+    fn normal_at(&self, x: f32, z: f32) -> Vec3 {
+        // Central differences, so normals match across chunk borders
+        const STEP: f32 = 0.25;
+        let height = |x, z| self.height_at(x, z).unwrap_or(0.0);
+        let dx = height(x + STEP, z) - height(x - STEP, z);
+        let dz = height(x, z + STEP) - height(x, z - STEP);
+        Vec3::new(-dx, 2.0 * STEP, -dz).normalize()
+    }
+}
+
+impl HeightGrid {
+    // Bilinear sample with u and v in 0..1, clamped at the edges
+    fn sample(&self, u: f32, v: f32) -> f32 {
+        let x = u.clamp(0.0, 1.0) * (self.width - 1) as f32;
+        let z = v.clamp(0.0, 1.0) * (self.depth - 1) as f32;
+        let (x0, z0) = (x.floor() as usize, z.floor() as usize);
+        let (x1, z1) = ((x0 + 1).min(self.width - 1), (z0 + 1).min(self.depth - 1));
+        let (tx, tz) = (x.fract(), z.fract());
+
+        let at = |x: usize, z: usize| self.samples[z * self.width + x];
+        let top = at(x0, z0).lerp(at(x1, z0), tx);
+        let bottom = at(x0, z1).lerp(at(x1, z1), tx);
+        top.lerp(bottom, tz)
+    }
+
+    fn from_image(image: &Image) -> Option<Self> {
+        let (width, depth) = (image.width() as usize, image.height() as usize);
+        if width < 2 || depth < 2 {
+            return None;
+        }
+        let mut samples = Vec::with_capacity(width * depth);
+        for z in 0..depth as u32 {
+            for x in 0..width as u32 {
+                let color = image.get_color_at(x, z).ok()?;
+                samples.push(raw_red(color));
+            }
+        }
+        Some(Self { width, depth, samples })
+    }
+}
+
+// The value stored in the image, 0..1. A grayscale PNG is an
+// sRGB image, and converting that to linear would bend the heights.
+fn raw_red(color: Color) -> f32 {
+    match color {
+        Color::Srgba(color) => color.red,
+        Color::LinearRgba(color) => color.red,
+        other => other.to_srgba().red,
+    }
+}
+
+fn setup_terrain(
+    trigger: On<Add, Terrain>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    terrains: Query<&Terrain>,
+) {
+    let Ok(terrain) = terrains.get(trigger.entity) else { return };
+
+    let sampler = match &terrain.source {
+        TerrainSource::Noise { seed, frequency, octaves, height } => HeightSampler::Noise {
+            fbm: Fbm::<Perlin>::new(*seed).set_frequency(*frequency).set_octaves(*octaves),
+            height: *height,
+        },
+        TerrainSource::Heightmap { path, size, height } => HeightSampler::Heightmap {
+            image: asset_server.load(path.clone()),
+            size: *size,
+            height: *height,
+            grid: None,
+        },
+    };
+
+    commands.entity(trigger.entity).insert((
+        TerrainRuntime {
+            sampler,
+            material: materials.add(StandardMaterial {
+                base_color: Color::srgb(0.35, 0.55, 0.25),
+                perceptual_roughness: 0.9,
+                ..default()
+            }),
+            chunks: HashMap::default(),
+        },
+    )).insert_if_new((
+        Transform::default(),
+        Visibility::default(),
+    ));
+}
+
+fn load_heightmaps(
+    images: Res<Assets<Image>>,
+    mut terrains: Query<&mut TerrainRuntime>,
+) {
+    for mut runtime in &mut terrains {
+        let HeightSampler::Heightmap { image, grid, .. } = &mut runtime.sampler else { continue };
+        if grid.is_some() {
+            continue;
+        }
+        if let Some(image) = images.get(&*image) {
+            *grid = HeightGrid::from_image(image);
+            if grid.is_none() {
+                error!("Terrain heightmap must be at least 2x2 pixels with a readable format");
+            }
+        }
+    }
+}
+
+const MAX_CHUNK_BUILDS_PER_FRAME: usize = 4;
+
+fn stream_terrain_chunks(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    players: Query<&GlobalTransform, With<FpsPlayer>>,
+    mut terrains: Query<(Entity, &Terrain, &mut TerrainRuntime, &GlobalTransform)>,
+    mut chunks: Query<(&mut TerrainChunk, &mut Mesh3d)>,
+) {
+    let Some(player) = players.iter().next() else { return };
+
+    // Building a mesh samples every vertex, so only build a few
+    // chunks per frame and pick up the rest on the next frames
+    let mut chunk_builds = MAX_CHUNK_BUILDS_PER_FRAME;
+
+    for (terrain_entity, terrain, mut runtime, terrain_transform) in &mut terrains {
+        // Wait for the heightmap before building anything
+        if runtime.sampler.height_at(0.0, 0.0).is_none() {
+            continue;
+        }
+
+        let player_local = terrain_transform.affine().inverse().transform_point3(player.translation());
+        let player_chunk = (player_local.xz() / terrain.chunk_size).floor().as_ivec2();
+        let view_distance = terrain.view_distance as i32;
+
+        // Unload chunks that are out of range
+        let runtime = &mut *runtime;
+        runtime.chunks.retain(|coord, chunk| {
+            let keep = (*coord - player_chunk).abs().max_element() <= view_distance;
+            if !keep {
+                commands.entity(*chunk).despawn();
+            }
+            keep
+        });
+
+        // Load new chunks and update the detail of loaded ones,
+        // closest first so the ground under the player is built
+        // before the horizon
+        let mut coords: Vec<(IVec2, f32)> = Vec::new();
+        for z in -view_distance..=view_distance {
+            for x in -view_distance..=view_distance {
+                let coord = player_chunk + IVec2::new(x, z);
+                let center = (coord.as_vec2() + 0.5) * terrain.chunk_size;
+                coords.push((coord, center.distance(player_local.xz())));
+            }
+        }
+        coords.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+
+        // Halving the resolution more often than this would shift it to 0
+        let max_lod = terrain.resolution.max(1).ilog2();
+
+        for (coord, distance) in coords {
+            if chunk_builds == 0 {
+                break;
+            }
+            let lod = terrain.lod_distances.iter().filter(|d| distance > **d).count() as u32;
+            let lod = lod.min(max_lod);
+
+            match runtime.chunks.get(&coord) {
+                Some(chunk_entity) => {
+                    let Ok((mut chunk, mut mesh)) = chunks.get_mut(*chunk_entity) else { continue };
+                    if chunk.lod != lod {
+                        chunk.lod = lod;
+                        mesh.0 = meshes.add(build_chunk_mesh(terrain, &runtime.sampler, coord, lod));
+                        chunk_builds -= 1;
+                    }
+                }
+                None => {
+                    let mesh = meshes.add(build_chunk_mesh(terrain, &runtime.sampler, coord, lod));
+                    chunk_builds -= 1;
+                    let chunk = commands.spawn((
+                        Name::new(format!("Terrain Chunk {} {}", coord.x, coord.y)),
+                        TerrainChunk { coord, lod },
+                        Mesh3d(mesh),
+                        MeshMaterial3d(runtime.material.clone()),
+                        Transform::from_xyz(
+                            coord.x as f32 * terrain.chunk_size,
+                            0.0,
+                            coord.y as f32 * terrain.chunk_size,
+                        ),
+                        ChildOf(terrain_entity),
+                    )).id();
+                    runtime.chunks.insert(coord, chunk);
+                }
+            }
+        }
+    }
+}
+
+// Builds the grid of vertices for one chunk.
+// Positions are relative to the chunk's corner.
+fn build_chunk_mesh(terrain: &Terrain, sampler: &HeightSampler, coord: IVec2, lod: u32) -> Mesh {
+    let resolution = (terrain.resolution >> lod).max(2);
+    let step = terrain.chunk_size / resolution as f32;
+    let origin = coord.as_vec2() * terrain.chunk_size;
+    let row = resolution + 1;
+
+    let mut positions = Vec::with_capacity((row * row) as usize);
+    let mut normals = Vec::with_capacity((row * row) as usize);
+    let mut uvs = Vec::with_capacity((row * row) as usize);
+
+    for z in 0..row {
+        for x in 0..row {
+            let local = Vec2::new(x as f32, z as f32) * step;
+            let world = origin + local;
+            let height = sampler.height_at(world.x, world.y).unwrap_or(0.0);
+
+            positions.push([local.x, height, local.y]);
+            normals.push(sampler.normal_at(world.x, world.y).to_array());
+            // World space uvs so the texture lines up between chunks
+            uvs.push((world / terrain.uv_scale).to_array());
+        }
+    }
+
+    let mut indices = Vec::with_capacity((resolution * resolution * 6) as usize);
+    for z in 0..resolution {
+        for x in 0..resolution {
+            let i = z * row + x;
+            indices.extend_from_slice(&[i, i + row, i + 1, i + 1, i + row, i + row + 1]);
+        }
+    }
+
+    // The skirt. Each edge is walked so its triangles face out
+    // of the chunk. Deep enough to cover the gap next to a
+    // chunk with half the detail on steep slopes.
+    let skirt_depth = step * 2.0;
+    let edges: [Vec<u32>; 4] = [
+        (0..row).collect(),
+        (0..row).rev().map(|x| resolution * row + x).collect(),
+        (0..row).rev().map(|z| z * row).collect(),
+        (0..row).map(|z| z * row + resolution).collect(),
+    ];
+    for edge in edges {
+        let first_skirt = positions.len() as u32;
+        for &i in &edge {
+            let [x, y, z] = positions[i as usize];
+            positions.push([x, y - skirt_depth, z]);
+            normals.push(normals[i as usize]);
+            uvs.push(uvs[i as usize]);
+        }
+        for (n, pair) in edge.windows(2).enumerate() {
+            let (a, b) = (pair[0], pair[1]);
+            let (a_low, b_low) = (first_skirt + n as u32, first_skirt + n as u32 + 1);
+            indices.extend_from_slice(&[a, b, a_low, b, b_low, a_low]);
+        }
+    }
+
+    Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_inserted_indices(Indices::U32(indices))
+}
+
+// Our ground collision: the player's feet never go
+// below the terrain height under them.
+fn keep_players_above_terrain(
+    mut players: Query<&mut Transform, With<FpsPlayer>>,
+    terrains: Query<(&TerrainRuntime, &GlobalTransform)>,
+) {
+    for mut player in &mut players {
+        for (runtime, terrain_transform) in &terrains {
+            let local = terrain_transform.affine().inverse().transform_point3(player.translation);
+            let Some(height) = runtime.sampler.height_at(local.x, local.z) else { continue };
+            let ground = terrain_transform.transform_point(local.with_y(height)).y;
+            if player.translation.y < ground {
+                player.translation.y = ground;
+            }
+        }
+    }
+}